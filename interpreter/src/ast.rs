@@ -1,9 +1,13 @@
-use std::collections::HashMap;
+use crate::features::Regex;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Int,
     Float,
+    Decimal,
     String,
     Boolean,
     Array,
@@ -13,22 +17,66 @@ pub enum Type {
     Pairs(Box<Type>, Box<Type>),
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "Int"),
+            Type::Float => write!(f, "Float"),
+            Type::Decimal => write!(f, "Decimal"),
+            Type::String => write!(f, "String"),
+            Type::Boolean => write!(f, "Boolean"),
+            Type::Array => write!(f, "Array"),
+            Type::Object => write!(f, "Object"),
+            Type::Regex => write!(f, "Regex"),
+            Type::List(item) => write!(f, "List({})", item),
+            Type::Pairs(key, value) => write!(f, "Pairs({}, {})", key, value),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Integer(i64),
+    // An integer literal too large for an i64
+    BigInt(BigInt),
     Float(f64),
     String(String),
     Boolean(bool),
     Null,
     Undefined,
     Nil,
-    Regex(String),
+    Regex(Regex),
     Array(Vec<Expression>),
-    Object(HashMap<String, Expression>),
+    Object(IndexMap<String, Expression>),
+}
+
+/// Byte range of a node in the source it was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expression {
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionKind {
     Literal(Literal),
     Identifier(String),
     EphemeralVar(String),
@@ -45,6 +93,12 @@ pub enum Expression {
         object: Box<Expression>,
         property: String,
     },
+    // `object?.property`: null instead of an error when `object` is null or
+    // has no such field
+    OptionalPropertyAccess {
+        object: Box<Expression>,
+        property: String,
+    },
     BracketAccess {
         object: Box<Expression>,
         index: Box<Expression>,
@@ -71,6 +125,17 @@ pub enum Expression {
     StringInterpolation {
         parts: Vec<StringPart>,
     },
+    Lambda {
+        parameters: Vec<Parameter>,
+        return_type: Option<Type>,
+        body: Vec<Statement>,
+    },
+    Range {
+        start: Box<Expression>,
+        end: Box<Expression>,
+        step: Option<Box<Expression>>,
+        inclusive: bool,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -86,12 +151,17 @@ pub enum BinaryOperator {
     Subtract,
     Multiply,
     Divide,
+    // `div`: division rounded down
+    FloorDivide,
     Modulo,
     Power,
 
     // Comparison
     Equal,
     NotEqual,
+    // `===` and `!==`: the same array or object, not just an equal one
+    Identical,
+    NotIdentical,
     LessThan,
     GreaterThan,
     LessThanOrEqual,
@@ -101,9 +171,11 @@ pub enum BinaryOperator {
     Match,
     NotMatch,
 
-    // Logical
+    // Logical; these give back whichever operand decided the result
     And,
     Or,
+    // `??`: the right operand when the left one is null
+    Coalesce,
 
     // Assignment
     Assign,
@@ -123,7 +195,19 @@ pub enum UnaryOperator {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Statement { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementKind {
     VariableDeclaration {
         type_annotation: Option<Type>,
         is_const: bool,
@@ -138,6 +222,8 @@ pub enum Statement {
         body: Vec<Statement>,
     },
     Return(Option<Expression>),
+    Break(Option<String>),
+    Continue(Option<String>),
     If {
         condition: Expression,
         then_branch: Vec<Statement>,
@@ -145,16 +231,19 @@ pub enum Statement {
         else_branch: Option<Vec<Statement>>,
     },
     ForLoop {
+        label: Option<String>,
         key_var: Option<String>,
         value_var: String,
         iterable: Expression,
         body: Vec<Statement>,
     },
     WhileLoop {
+        label: Option<String>,
         condition: Expression,
         body: Vec<Statement>,
     },
     Loop {
+        label: Option<String>,
         condition: Expression,
         body: Vec<Statement>,
     },
@@ -162,7 +251,9 @@ pub enum Statement {
         try_body: Vec<Statement>,
         error_var: String,
         catch_body: Vec<Statement>,
+        finally_body: Option<Vec<Statement>>,
     },
+    Throw(Expression),
     Block {
         expression: Expression,
     },
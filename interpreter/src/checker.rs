@@ -0,0 +1,992 @@
+//! Optional static type checking, run by `fenics check`; unknown types are never reported.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    BinaryOperator, Expression, ExpressionKind, Literal, Parameter, Program, Span, Statement,
+    StatementKind, StringPart, Type, UnaryOperator,
+};
+use crate::error::FenicsError;
+use crate::utils::string_methods::string_method;
+
+/// Methods the interpreter provides for arrays.
+const ARRAY_METHODS: &[&str] = &[
+    "reverse",
+    "sort",
+    "has",
+    "join",
+    "map",
+    "filter",
+    "reduce",
+    "each",
+    "find",
+    "any",
+    "all",
+    "flat_map",
+    "zip",
+    "enumerate",
+    "chunk",
+    "unique",
+    "group_by",
+];
+
+/// Check `program` and return every problem found, in source order.
+pub fn check_program(program: &Program) -> Vec<FenicsError> {
+    let mut checker = Checker {
+        scopes: vec![Frame::default()],
+        return_types: Vec::new(),
+        span: Span::default(),
+        errors: Vec::new(),
+    };
+    checker.statements(&program.statements);
+    checker.errors
+}
+
+/// What the checker knows about a variable.
+#[derive(Clone, Default)]
+struct Symbol {
+    /// Inferred type of the current value, if known.
+    ty: Option<Type>,
+    /// Type the variable was declared with; every assignment must match it.
+    declared: Option<Type>,
+    /// Set when the variable holds a known function.
+    signature: Option<Signature>,
+}
+
+#[derive(Clone)]
+struct Signature {
+    name: Option<String>,
+    params: Vec<Parameter>,
+    return_type: Option<Type>,
+}
+
+impl Signature {
+    fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("'{}'", name),
+            None => "anonymous function".to_string(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Frame {
+    symbols: HashMap<String, Symbol>,
+}
+
+struct Checker {
+    scopes: Vec<Frame>,
+    /// Signatures of the functions being checked, innermost last.
+    return_types: Vec<Signature>,
+    /// Span of the node being checked, where problems are reported.
+    span: Span,
+    errors: Vec<FenicsError>,
+}
+
+/// Whether `actual` can be stored where `expected` is declared, allowing widening.
+fn fits(actual: &Type, expected: &Type) -> bool {
+    match (actual, expected) {
+        (Type::Int, Type::Float | Type::Decimal) | (Type::Float, Type::Decimal) => true,
+        (Type::List(_), Type::Array) | (Type::Array, Type::List(_)) => true,
+        (Type::Pairs(..), Type::Object) | (Type::Object, Type::Pairs(..)) => true,
+        (Type::List(a), Type::List(b)) => fits(a, b),
+        (Type::Pairs(ka, va), Type::Pairs(kb, vb)) => fits(ka, kb) && fits(va, vb),
+        _ => actual == expected,
+    }
+}
+
+fn is_numeric(ty: &Type) -> bool {
+    matches!(ty, Type::Int | Type::Float | Type::Decimal)
+}
+
+fn is_array(ty: &Type) -> bool {
+    matches!(ty, Type::Array | Type::List(_))
+}
+
+/// Result type of a binary operator on known operand types, mirroring `evaluate_binary_op`.
+fn binary_result(
+    op: &BinaryOperator,
+    left: &Type,
+    right: &Type,
+) -> Result<Option<Type>, &'static str> {
+    let arithmetic = |message| match (left, right) {
+        (Type::Int, Type::Int) => Ok(Some(Type::Int)),
+        (Type::Decimal, Type::Float) | (Type::Float, Type::Decimal) => Err(message),
+        (Type::Decimal, r) if is_numeric(r) => Ok(Some(Type::Decimal)),
+        (l, Type::Decimal) if is_numeric(l) => Ok(Some(Type::Decimal)),
+        (l, r) if is_numeric(l) && is_numeric(r) => Ok(Some(Type::Float)),
+        _ => Err(message),
+    };
+    let comparison = || {
+        if is_numeric(left) && is_numeric(right) {
+            Ok(Some(Type::Boolean))
+        } else {
+            Err("Invalid types for comparison")
+        }
+    };
+
+    match op {
+        BinaryOperator::Add => match (left, right) {
+            (Type::String, Type::String) => Ok(Some(Type::String)),
+            _ => arithmetic("Invalid types for addition"),
+        },
+        BinaryOperator::Subtract => arithmetic("Invalid types for subtraction"),
+        BinaryOperator::Multiply => arithmetic("Invalid types for multiplication"),
+        BinaryOperator::Divide => match (left, right) {
+            (Type::Int, Type::Int) => Ok(Some(Type::Float)),
+            _ => arithmetic("Invalid types for division"),
+        },
+        BinaryOperator::FloorDivide => arithmetic("Invalid types for division"),
+        BinaryOperator::Modulo => arithmetic("Invalid types for modulo"),
+        // A negative exponent makes `Int ^ Int` a Float
+        BinaryOperator::Power => match (left, right) {
+            (Type::Int, Type::Int) => Ok(None),
+            _ => arithmetic("Invalid types for power"),
+        },
+        BinaryOperator::LessThan
+        | BinaryOperator::GreaterThan
+        | BinaryOperator::LessThanOrEqual
+        | BinaryOperator::GreaterThanOrEqual => comparison(),
+        BinaryOperator::Equal
+        | BinaryOperator::NotEqual
+        | BinaryOperator::Identical
+        | BinaryOperator::NotIdentical
+        | BinaryOperator::Is
+        | BinaryOperator::IsNot => Ok(Some(Type::Boolean)),
+        // Either operand may be the result
+        BinaryOperator::And | BinaryOperator::Or | BinaryOperator::Coalesce => {
+            Ok((left == right).then(|| left.clone()))
+        }
+        BinaryOperator::Match | BinaryOperator::NotMatch => match (left, right) {
+            (Type::String, Type::Regex) => Ok(Some(Type::Boolean)),
+            _ => Err("Regex matching needs a String on the left and a Regex on the right"),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// The operator applied by a compound assignment such as `+:`.
+fn compound_operator(op: &BinaryOperator) -> Option<BinaryOperator> {
+    match op {
+        BinaryOperator::AddAssign => Some(BinaryOperator::Add),
+        BinaryOperator::SubAssign => Some(BinaryOperator::Subtract),
+        BinaryOperator::MulAssign => Some(BinaryOperator::Multiply),
+        BinaryOperator::DivAssign => Some(BinaryOperator::Divide),
+        BinaryOperator::ModAssign => Some(BinaryOperator::Modulo),
+        _ => None,
+    }
+}
+
+impl Checker {
+    fn error(&mut self, message: String) {
+        self.error_at(self.span, message);
+    }
+
+    fn error_at(&mut self, span: Span, message: String) {
+        self.errors
+            .push(FenicsError::type_error(message).with_span(span));
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let outer = std::mem::replace(&mut self.span, statement.span);
+        self.statement_kind(&statement.kind);
+        self.span = outer;
+    }
+
+    fn statement_kind(&mut self, statement: &StatementKind) {
+        match statement {
+            StatementKind::VariableDeclaration {
+                type_annotation,
+                is_const,
+                is_global,
+                name,
+                value,
+            } => {
+                let ty = self.expression(value);
+                let signature = self.signature_of(value);
+                if let Some(expected) = type_annotation {
+                    self.expect(value, ty.as_ref(), expected, |found| {
+                        format!(
+                            "Type mismatch for '{}': expected {}, got {}",
+                            name, expected, found
+                        )
+                    });
+                }
+
+                if type_annotation.is_none() && !*is_const && !*is_global {
+                    self.bind(name, ty, signature);
+                } else {
+                    let symbol = Symbol {
+                        ty: type_annotation.clone().or(ty),
+                        declared: type_annotation.clone(),
+                        signature,
+                    };
+                    let frame = if *is_global { 0 } else { self.scopes.len() - 1 };
+                    self.scopes[frame].symbols.insert(name.clone(), symbol);
+                }
+            }
+            StatementKind::FunctionDeclaration {
+                name,
+                parameters,
+                return_type,
+                body,
+            } => {
+                let signature = Signature {
+                    name: Some(name.clone()),
+                    params: parameters.clone(),
+                    return_type: return_type.clone(),
+                };
+                self.declare(
+                    name,
+                    Symbol {
+                        signature: Some(signature.clone()),
+                        ..Symbol::default()
+                    },
+                );
+                self.function_body(signature, body);
+            }
+            StatementKind::Return(value) => {
+                let ty = value.as_ref().and_then(|e| self.expression(e));
+                let signature = self.return_types.last().cloned();
+                if let (Some(signature), Some(expected), Some(value)) = (
+                    signature.as_ref(),
+                    signature.as_ref().and_then(|s| s.return_type.as_ref()),
+                    value,
+                ) {
+                    self.expect(value, ty.as_ref(), expected, |found| {
+                        format!(
+                            "Type mismatch for return value of {}: expected {}, got {}",
+                            signature.describe(),
+                            expected,
+                            found
+                        )
+                    });
+                }
+            }
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_ifs,
+                else_branch,
+            } => {
+                self.expression(condition);
+                self.block(Vec::new(), then_branch);
+                for (condition, body) in else_ifs {
+                    self.expression(condition);
+                    self.block(Vec::new(), body);
+                }
+                if let Some(body) = else_branch {
+                    self.block(Vec::new(), body);
+                }
+            }
+            StatementKind::ForLoop {
+                key_var,
+                value_var,
+                iterable,
+                body,
+                ..
+            } => {
+                let (key_ty, value_ty) = match self.expression(iterable) {
+                    // Ranges have no type of their own but always yield Ints
+                    None if matches!(iterable.kind, ExpressionKind::Range { .. }) => {
+                        (Some(Type::Int), Some(Type::Int))
+                    }
+                    Some(Type::List(item)) => (Some(Type::Int), Some(*item)),
+                    Some(Type::Array) => (Some(Type::Int), None),
+                    Some(Type::Pairs(_, value)) => (Some(Type::String), Some(*value)),
+                    Some(Type::Object) => (Some(Type::String), None),
+                    Some(other) => {
+                        self.error(format!(
+                            "For loop requires an array, object or range, got {}",
+                            other
+                        ));
+                        (None, None)
+                    }
+                    None => (None, None),
+                };
+                let mut vars = Vec::new();
+                if let Some(key) = key_var {
+                    vars.push((key.clone(), key_ty));
+                }
+                vars.push((value_var.clone(), value_ty));
+                self.block(vars, body);
+            }
+            StatementKind::WhileLoop {
+                condition, body, ..
+            }
+            | StatementKind::Loop {
+                condition, body, ..
+            } => {
+                self.expression(condition);
+                self.block(Vec::new(), body);
+            }
+            StatementKind::TryCatch {
+                try_body,
+                error_var,
+                catch_body,
+                finally_body,
+            } => {
+                self.block(Vec::new(), try_body);
+                // Caught errors have no static type, so their use goes unchecked
+                self.block(vec![(error_var.clone(), None)], catch_body);
+                if let Some(finally_body) = finally_body {
+                    self.block(Vec::new(), finally_body);
+                }
+            }
+            StatementKind::Throw(value) => {
+                self.expression(value);
+            }
+            StatementKind::Block { expression } | StatementKind::Expression(expression) => {
+                self.expression(expression);
+            }
+            StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::LibExport { .. }
+            | StatementKind::Import { .. } => {}
+        }
+    }
+
+    /// Infer the type of `expression`, reporting problems inside it.
+    fn expression(&mut self, expression: &Expression) -> Option<Type> {
+        let outer = std::mem::replace(&mut self.span, expression.span);
+        let ty = self.expression_kind(&expression.kind);
+        self.span = outer;
+        ty
+    }
+
+    fn expression_kind(&mut self, expression: &ExpressionKind) -> Option<Type> {
+        match expression {
+            ExpressionKind::Literal(literal) => self.literal(literal),
+            ExpressionKind::Identifier(name) => self.lookup(name).and_then(|s| s.ty.clone()),
+            ExpressionKind::EphemeralVar(_) => None,
+            ExpressionKind::FunctionCall { name, args } => self.call(name, args),
+            ExpressionKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let object_ty = self.expression(object);
+                for arg in args {
+                    self.expression(arg);
+                }
+                let found = match &object_ty {
+                    Some(ty) if is_array(ty) => ARRAY_METHODS.contains(&method.as_str()),
+                    Some(Type::String) => string_method(method).is_some(),
+                    Some(Type::Int | Type::Float | Type::Boolean | Type::Regex) => false,
+                    _ => return None,
+                };
+                if let (Some(Type::String), Some(string_method)) =
+                    (&object_ty, string_method(method))
+                {
+                    if let Err(err) = string_method.check_arity(args.len()) {
+                        self.error(err.message().to_string());
+                    }
+                }
+                if !found {
+                    self.error(format!(
+                        "Method '{}' not found on {}",
+                        method,
+                        object_ty.unwrap()
+                    ));
+                    return None;
+                }
+                match (object_ty, method.as_str()) {
+                    (Some(ty), "reverse" | "sort" | "filter" | "unique") => Some(ty),
+                    (_, "map" | "flat_map" | "zip" | "enumerate" | "chunk") => Some(Type::Array),
+                    (_, "group_by") => Some(Type::Object),
+                    (
+                        _,
+                        "has" | "any" | "all" | "starts_with" | "ends_with" | "contains" | "test",
+                    ) => Some(Type::Boolean),
+                    (_, "join") => Some(Type::String),
+                    (_, "index_of") => Some(Type::Int),
+                    (_, "split" | "chars" | "lines") => Some(Type::List(Box::new(Type::String))),
+                    (_, "bytes") => Some(Type::List(Box::new(Type::Int))),
+                    (_, "match_all") => Some(Type::Array),
+                    (_, "match") => None,
+                    (Some(Type::String), _) => Some(Type::String),
+                    _ => None,
+                }
+            }
+            ExpressionKind::PropertyAccess { object, property } => {
+                let object_ty = self.expression(object)?;
+                match (&object_ty, property.as_str()) {
+                    (Type::String, "length") => Some(Type::Int),
+                    (ty, "length") if is_array(ty) => Some(Type::Int),
+                    (Type::List(item), "first" | "last") => Some(*item.clone()),
+                    (ty, "first" | "last") if is_array(ty) => None,
+                    (Type::Pairs(_, value), _) => Some(*value.clone()),
+                    (Type::Object, _) => None,
+                    (ty, _) => {
+                        self.error(format!("Property '{}' not found on {}", property, ty));
+                        None
+                    }
+                }
+            }
+            // Null whenever the field is missing, so nothing is known
+            ExpressionKind::OptionalPropertyAccess { object, .. } => {
+                self.expression(object);
+                None
+            }
+            ExpressionKind::BracketAccess { object, index } => {
+                let object_ty = self.expression(object);
+                let index_ty = self.expression(index);
+                match (object_ty?, index_ty?) {
+                    (Type::List(item), Type::Int) => Some(*item),
+                    (Type::Pairs(_, value), Type::String) => Some(*value),
+                    _ => None,
+                }
+            }
+            ExpressionKind::BinaryOp { left, op, right } => {
+                if matches!(op, BinaryOperator::Assign) || compound_operator(op).is_some() {
+                    return self.assignment(left, op, right);
+                }
+                let left_ty = self.expression(left);
+                let right_ty = self.expression(right);
+                self.binary(op, left_ty, right_ty)
+            }
+            ExpressionKind::UnaryOp { op, operand } => {
+                let ty = self.expression(operand);
+                match op {
+                    UnaryOperator::Not => Some(Type::Boolean),
+                    UnaryOperator::Negate | UnaryOperator::Increment | UnaryOperator::Decrement => {
+                        match ty? {
+                            ty if is_numeric(&ty) => Some(ty),
+                            other => {
+                                let action = match op {
+                                    UnaryOperator::Negate => "negate",
+                                    UnaryOperator::Increment => "increment",
+                                    _ => "decrement",
+                                };
+                                self.error(format!("Cannot {} a value of type {}", action, other));
+                                None
+                            }
+                        }
+                    }
+                }
+            }
+            ExpressionKind::TernaryThen {
+                condition,
+                true_expr,
+                false_expr,
+            }
+            | ExpressionKind::TernaryQuestion {
+                condition,
+                true_expr,
+                false_expr,
+            } => {
+                self.expression(condition);
+                let a = self.expression(true_expr);
+                let b = self.expression(false_expr);
+                if a == b {
+                    a
+                } else {
+                    None
+                }
+            }
+            ExpressionKind::StringInterpolation { parts } => {
+                for part in parts {
+                    if let StringPart::Expression(e) = part {
+                        self.expression(e);
+                    }
+                }
+                Some(Type::String)
+            }
+            ExpressionKind::Lambda {
+                parameters,
+                return_type,
+                body,
+            } => {
+                let signature = Signature {
+                    name: None,
+                    params: parameters.clone(),
+                    return_type: return_type.clone(),
+                };
+                self.function_body(signature, body);
+                None
+            }
+            ExpressionKind::Range {
+                start, end, step, ..
+            } => {
+                for bound in [Some(start), Some(end), step.as_ref()]
+                    .into_iter()
+                    .flatten()
+                {
+                    if let Some(ty) = self.expression(bound) {
+                        if ty != Type::Int {
+                            self.error(format!("Range bounds must be Int, got {}", ty));
+                        }
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal) -> Option<Type> {
+        match literal {
+            Literal::Integer(_) | Literal::BigInt(_) => Some(Type::Int),
+            Literal::Float(_) => Some(Type::Float),
+            Literal::String(_) => Some(Type::String),
+            Literal::Boolean(_) => Some(Type::Boolean),
+            Literal::Regex(_) => Some(Type::Regex),
+            Literal::Null | Literal::Undefined | Literal::Nil => None,
+            Literal::Array(items) => {
+                let types: Vec<Option<Type>> = items.iter().map(|e| self.expression(e)).collect();
+                match types.first() {
+                    Some(Some(first)) if types.iter().all(|t| t.as_ref() == Some(first)) => {
+                        Some(Type::List(Box::new(first.clone())))
+                    }
+                    _ => Some(Type::Array),
+                }
+            }
+            Literal::Object(fields) => {
+                for value in fields.values() {
+                    self.expression(value);
+                }
+                Some(Type::Object)
+            }
+        }
+    }
+
+    fn binary(
+        &mut self,
+        op: &BinaryOperator,
+        left: Option<Type>,
+        right: Option<Type>,
+    ) -> Option<Type> {
+        match (left, right) {
+            (Some(l), Some(r)) => match binary_result(op, &l, &r) {
+                Ok(ty) => ty,
+                Err(message) => {
+                    self.error(format!("{}: {} and {}", message, l, r));
+                    None
+                }
+            },
+            _ => match op {
+                BinaryOperator::Equal
+                | BinaryOperator::NotEqual
+                | BinaryOperator::Identical
+                | BinaryOperator::NotIdentical
+                | BinaryOperator::Is
+                | BinaryOperator::IsNot
+                | BinaryOperator::LessThan
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::LessThanOrEqual
+                | BinaryOperator::GreaterThanOrEqual => Some(Type::Boolean),
+                _ => None,
+            },
+        }
+    }
+
+    fn assignment(
+        &mut self,
+        target: &Expression,
+        op: &BinaryOperator,
+        value: &Expression,
+    ) -> Option<Type> {
+        let value_ty = self.expression(value);
+        let ExpressionKind::Identifier(name) = &target.kind else {
+            self.expression(target);
+            return value_ty;
+        };
+        let Some(symbol) = self.lookup(name).cloned() else {
+            return value_ty;
+        };
+
+        let new_ty = match compound_operator(op) {
+            Some(op) => self.binary(&op, symbol.ty.clone(), value_ty),
+            None => value_ty,
+        };
+        if let Some(expected) = &symbol.declared {
+            if let Some(found) = &new_ty {
+                if !fits(found, expected) {
+                    self.error(format!(
+                        "Type mismatch for '{}': expected {}, got {}",
+                        name, expected, found
+                    ));
+                }
+            }
+        } else {
+            self.retype(name, new_ty.clone());
+        }
+        new_ty
+    }
+
+    fn call(&mut self, name: &str, args: &[Expression]) -> Option<Type> {
+        let arg_types: Vec<Option<Type>> = args.iter().map(|a| self.expression(a)).collect();
+        match name {
+            // A variable named like a builtin shadows it
+            _ if self.lookup(name).is_some() => {}
+            "print" => return None,
+            "len" => {
+                if args.len() != 1 {
+                    self.error("len() takes exactly 1 argument".to_string());
+                }
+                return Some(Type::Int);
+            }
+            "input" => {
+                if args.len() > 1 {
+                    self.error("input() takes at most 1 argument".to_string());
+                }
+                return None;
+            }
+            "type" | "str" | "int" | "float" | "decimal" | "bool" => {
+                if args.len() != 1 {
+                    self.error(format!("{}() takes exactly 1 argument", name));
+                }
+                return Some(match name {
+                    "int" => Type::Int,
+                    "float" => Type::Float,
+                    "decimal" => Type::Decimal,
+                    "bool" => Type::Boolean,
+                    _ => Type::String,
+                });
+            }
+            "parse_int" => {
+                if args.is_empty() || args.len() > 2 {
+                    self.error("parse_int() takes 1 or 2 arguments".to_string());
+                }
+                return Some(Type::Int);
+            }
+            "copy" | "deep_copy" => {
+                if args.len() != 1 {
+                    self.error(format!("{}() takes exactly 1 argument", name));
+                }
+                return arg_types.into_iter().next().flatten();
+            }
+            _ => {}
+        }
+
+        let symbol = self.lookup(name)?.clone();
+        let Some(signature) = symbol.signature else {
+            if symbol.ty.is_some() {
+                self.error(format!("'{}' is not a function", name));
+            }
+            return None;
+        };
+        if args.len() != signature.params.len() {
+            self.error(format!(
+                "Function '{}' expects {} arguments, got {}",
+                name,
+                signature.params.len(),
+                args.len()
+            ));
+            return signature.return_type;
+        }
+        for ((param, arg), ty) in signature.params.iter().zip(args).zip(&arg_types) {
+            if let Some(expected) = &param.type_annotation {
+                self.expect(arg, ty.as_ref(), expected, |found| {
+                    format!(
+                        "Type mismatch for parameter '{}' of {}: expected {}, got {}",
+                        param.name,
+                        signature.describe(),
+                        expected,
+                        found
+                    )
+                });
+            }
+        }
+        signature.return_type
+    }
+
+    /// Report a mismatch between the inferred type of `value` and `expected`.
+    fn expect(
+        &mut self,
+        value: &Expression,
+        actual: Option<&Type>,
+        expected: &Type,
+        message: impl Fn(String) -> String,
+    ) {
+        if let (ExpressionKind::Literal(Literal::Array(items)), Type::List(item_type)) =
+            (&value.kind, expected)
+        {
+            for (i, item) in items.iter().enumerate() {
+                // The elements were already checked when the literal was inferred
+                let reported = self.errors.len();
+                let item_ty = self.expression(item);
+                self.errors.truncate(reported);
+                if let Some(found) = item_ty.filter(|t| !fits(t, item_type)) {
+                    let found = format!("Array with {} at index {}", found, i);
+                    self.error_at(item.span, message(found));
+                    return;
+                }
+            }
+            return;
+        }
+        if let Some(found) = actual.filter(|t| !fits(t, expected)) {
+            self.error_at(value.span, message(found.to_string()));
+        }
+    }
+
+    /// The signature of a function-valued expression, when it is known.
+    fn signature_of(&self, expression: &Expression) -> Option<Signature> {
+        match &expression.kind {
+            ExpressionKind::Lambda {
+                parameters,
+                return_type,
+                ..
+            } => Some(Signature {
+                name: None,
+                params: parameters.clone(),
+                return_type: return_type.clone(),
+            }),
+            ExpressionKind::Identifier(name) => self.lookup(name)?.signature.clone(),
+            _ => None,
+        }
+    }
+
+    /// Mirror of the runtime rule for a plain `name : value`.
+    fn bind(&mut self, name: &str, ty: Option<Type>, signature: Option<Signature>) {
+        // Functions can't rebind globals, so `name` there is a new local
+        let first_frame = usize::from(!self.return_types.is_empty());
+        match self.scopes[first_frame..]
+            .iter()
+            .rev()
+            .find_map(|frame| frame.symbols.get(name))
+            .cloned()
+        {
+            Some(Symbol {
+                declared: Some(expected),
+                ..
+            }) => {
+                if let Some(found) = ty.filter(|t| !fits(t, &expected)) {
+                    self.error(format!(
+                        "Type mismatch for '{}': expected {}, got {}",
+                        name, expected, found
+                    ));
+                }
+            }
+            Some(_) => self.retype(name, ty),
+            None => self.declare(
+                name,
+                Symbol {
+                    ty,
+                    declared: None,
+                    signature,
+                },
+            ),
+        }
+    }
+
+    /// Update the inferred type of an untyped variable, which becomes unknown when it varies.
+    fn retype(&mut self, name: &str, ty: Option<Type>) {
+        if let Some(symbol) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|frame| frame.symbols.get_mut(name))
+        {
+            if symbol.ty != ty {
+                symbol.ty = None;
+            }
+            symbol.signature = None;
+        }
+    }
+
+    fn declare(&mut self, name: &str, symbol: Symbol) {
+        let frame = self.scopes.last_mut().expect("checker always has a frame");
+        frame.symbols.insert(name.to_string(), symbol);
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|frame| frame.symbols.get(name))
+    }
+
+    fn block(&mut self, vars: Vec<(String, Option<Type>)>, body: &[Statement]) {
+        self.scopes.push(Frame {
+            symbols: vars
+                .into_iter()
+                .map(|(name, ty)| {
+                    (
+                        name,
+                        Symbol {
+                            ty,
+                            ..Symbol::default()
+                        },
+                    )
+                })
+                .collect(),
+        });
+        self.statements(body);
+        self.scopes.pop();
+    }
+
+    fn function_body(&mut self, signature: Signature, body: &[Statement]) {
+        let symbols = signature
+            .params
+            .iter()
+            .map(|p| {
+                let symbol = Symbol {
+                    ty: p.type_annotation.clone(),
+                    declared: p.type_annotation.clone(),
+                    signature: None,
+                };
+                (p.name.clone(), symbol)
+            })
+            .collect();
+        self.scopes.push(Frame { symbols });
+        self.return_types.push(signature);
+        self.statements(body);
+        self.return_types.pop();
+        self.scopes.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check_program;
+    use crate::parser::parse_program;
+
+    fn check(src: &str) -> Vec<String> {
+        check_program(&parse_program(src).unwrap())
+            .iter()
+            .map(|e| e.message().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn accepts_well_typed_programs() {
+        let src = r##"
+Int count : 1
+Float ratio : count / 2
+List(Int) xs : [1, 2, 3]
+words : "a b".split(" ")
+fn add(x: Int, y: Int) -> Int:
+    return x + y
+
+total : add(count, xs.first)
+label : "total: " + "#{total}"
+for x in xs:
+    count +: x
+
+"##;
+        assert_eq!(check(src), Vec::<String>::new());
+    }
+
+    #[test]
+    fn reports_mismatched_declarations_and_assignments() {
+        assert_eq!(
+            check("Int n : \"one\"\nInt m : 1\nm : true\nm +: 1.5\n"),
+            vec![
+                "Type mismatch for 'n': expected Int, got String",
+                "Type mismatch for 'm': expected Int, got Boolean",
+                "Type mismatch for 'm': expected Int, got Float",
+            ]
+        );
+        assert_eq!(
+            check("List(Int) xs : [1, \"two\"]\n"),
+            vec!["Type mismatch for 'xs': expected List(Int), got Array with String at index 1"]
+        );
+        // Inside a function `n` is a new local, not the global
+        assert_eq!(
+            check("Int n : 1\nfn f():\n    n : \"s\"\n\n"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn propagates_types_through_operators() {
+        assert_eq!(
+            check("a : 1\nb : \"x\"\nc : a + b\nd : a * 2.5\nString e : d\n"),
+            vec![
+                "Invalid types for addition: Int and String",
+                "Type mismatch for 'e': expected String, got Float",
+            ]
+        );
+        assert_eq!(
+            check("Decimal p : 1.5\nq : p * 2\nr : q + 0.5\nInt n : 7 / 2\nInt m : 7 div 2\n"),
+            vec![
+                "Invalid types for addition: Decimal and Float",
+                "Type mismatch for 'n': expected Int, got Float",
+            ]
+        );
+        assert_eq!(
+            check("name : \"\" or \"anon\"\nInt n : name\nok : 1 and true\nInt m : ok\n"),
+            vec!["Type mismatch for 'n': expected Int, got String"]
+        );
+    }
+
+    #[test]
+    fn range_loop_variables_are_ints() {
+        assert_eq!(
+            check("for i in 0..3:\n    x : i + \"s\"\n\nfor n, v in 0..=4 step 2:\n    String s : n + v\n"),
+            vec![
+                "Invalid types for addition: Int and String",
+                "Type mismatch for 's': expected String, got Int",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_calls_against_known_functions() {
+        let src = r#"
+fn greet(name: String) -> String:
+    return 42
+
+greet("a", "b")
+greet(1)
+double : fn(n: Int) -> Int: n * 2
+double("x")
+"#;
+        assert_eq!(
+            check(src),
+            vec![
+                "Type mismatch for return value of 'greet': expected String, got Int",
+                "Function 'greet' expects 1 arguments, got 2",
+                "Type mismatch for parameter 'name' of 'greet': expected String, got Int",
+                "Type mismatch for parameter 'n' of anonymous function: expected Int, got String",
+            ]
+        );
+        // A user function shadows the builtin of the same name
+        assert_eq!(
+            check("fn len(x) -> String:\n    return \"a\"\n\nInt n : len([1])\n"),
+            vec!["Type mismatch for 'n': expected Int, got String"]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_methods() {
+        assert_eq!(
+            check("xs : [1, 2]\nxs.shuffle()\ns : \"abc\"\ns.reverse()\n"),
+            vec![
+                "Method 'shuffle' not found on List(Int)",
+                "Method 'reverse' not found on String",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_string_methods() {
+        assert_eq!(
+            check("s : \"abc\"\nInt n : s.index_of(\"b\")\nn : s.upper()\ns.slice()\n"),
+            vec![
+                "Type mismatch for 'n': expected Int, got String",
+                "slice() takes 1 or 2 arguments",
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_unknown_types_alone() {
+        let src = r#"
+fn id(x):
+    return x
+
+a : id(1) + "s"
+b : 1
+b : "now a string"
+c : b + 1
+"#;
+        assert_eq!(check(src), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,382 @@
+use crate::ast::Span;
+use crate::features::{SourceFile, Value};
+use std::fmt;
+use std::rc::Rc;
+
+/// What went wrong and where: message, span and its file, hint and the active calls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorInfo {
+    pub message: String,
+    pub span: Option<Span>,
+    pub module: Option<Rc<SourceFile>>,
+    pub hint: Option<String>,
+    pub stack: Vec<StackFrame>,
+}
+
+/// A function call active when an error was raised, with where it was called from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    pub module: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}:{}:{})",
+            self.function, self.module, self.line, self.column
+        )
+    }
+}
+
+/// Every error the parser, checkers and interpreter can report.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenicsError {
+    /// The source doesn't match the grammar.
+    Parse(ErrorInfo),
+    /// A value had the wrong type for an operation, annotation or call.
+    Type(ErrorInfo),
+    /// A name is unknown, or a binding rule (such as `const`) was broken.
+    Name(ErrorInfo),
+    /// An index, key or property doesn't exist.
+    Index(ErrorInfo),
+    /// Reading or writing a file failed.
+    Io(ErrorInfo),
+    /// A request made through the `http` bridge failed.
+    Http(ErrorInfo),
+    /// A Rust bridge module reported a failure.
+    Bridge(ErrorInfo),
+    /// Raised by the program itself with `throw`, carrying the thrown value.
+    Thrown(ErrorInfo, Box<Value>),
+    /// Any other failure while running, such as division by zero.
+    Runtime(ErrorInfo),
+}
+
+fn info(message: impl Into<String>) -> ErrorInfo {
+    ErrorInfo {
+        message: message.into(),
+        span: None,
+        module: None,
+        hint: None,
+        stack: Vec::new(),
+    }
+}
+
+impl FenicsError {
+    pub fn parse(message: impl Into<String>) -> Self {
+        FenicsError::Parse(info(message))
+    }
+
+    pub fn type_error(message: impl Into<String>) -> Self {
+        FenicsError::Type(info(message))
+    }
+
+    pub fn name(message: impl Into<String>) -> Self {
+        FenicsError::Name(info(message))
+    }
+
+    pub fn index(message: impl Into<String>) -> Self {
+        FenicsError::Index(info(message))
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        FenicsError::Io(info(message))
+    }
+
+    pub fn http(message: impl Into<String>) -> Self {
+        FenicsError::Http(info(message))
+    }
+
+    pub fn bridge(message: impl Into<String>) -> Self {
+        FenicsError::Bridge(info(message))
+    }
+
+    /// An error raised by `throw value`, with the value as `print` shows it.
+    pub fn thrown(value: Value) -> Self {
+        FenicsError::Thrown(info(value.to_string()), Box::new(value))
+    }
+
+    pub fn runtime(message: impl Into<String>) -> Self {
+        FenicsError::Runtime(info(message))
+    }
+
+    pub fn info(&self) -> &ErrorInfo {
+        match self {
+            FenicsError::Parse(info)
+            | FenicsError::Type(info)
+            | FenicsError::Name(info)
+            | FenicsError::Index(info)
+            | FenicsError::Io(info)
+            | FenicsError::Http(info)
+            | FenicsError::Bridge(info)
+            | FenicsError::Thrown(info, _)
+            | FenicsError::Runtime(info) => info,
+        }
+    }
+
+    fn info_mut(&mut self) -> &mut ErrorInfo {
+        match self {
+            FenicsError::Parse(info)
+            | FenicsError::Type(info)
+            | FenicsError::Name(info)
+            | FenicsError::Index(info)
+            | FenicsError::Io(info)
+            | FenicsError::Http(info)
+            | FenicsError::Bridge(info)
+            | FenicsError::Thrown(info, _)
+            | FenicsError::Runtime(info) => info,
+        }
+    }
+
+    /// Short lowercase name of the error's kind, as shown in `error[kind]`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            FenicsError::Parse(_) => "parse",
+            FenicsError::Type(_) => "type",
+            FenicsError::Name(_) => "name",
+            FenicsError::Index(_) => "index",
+            FenicsError::Io(_) => "io",
+            FenicsError::Http(_) => "http",
+            FenicsError::Bridge(_) => "bridge",
+            FenicsError::Thrown(..) => "thrown",
+            FenicsError::Runtime(_) => "runtime",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.info().message
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.info().span
+    }
+
+    pub fn stack(&self) -> &[StackFrame] {
+        &self.info().stack
+    }
+
+    /// The value given to `throw`, for errors raised by the program.
+    pub fn thrown_value(&self) -> Option<&Value> {
+        match self {
+            FenicsError::Thrown(_, value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Point the error at `span` unless it already points at an inner node.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.info_mut().span.get_or_insert(span);
+        self
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.info_mut().hint = Some(hint.into());
+        self
+    }
+
+    /// Record the active calls unless a backtrace was already taken.
+    pub fn with_stack(mut self, stack: Vec<StackFrame>) -> Self {
+        let info = self.info_mut();
+        if info.stack.is_empty() {
+            info.stack = stack;
+        }
+        self
+    }
+
+    /// Record the file the span points into, unless one already was.
+    pub(crate) fn with_module(mut self, module: Rc<SourceFile>) -> Self {
+        let info = self.info_mut();
+        if info.span.is_some() && info.module.is_none() {
+            info.module = Some(module);
+        }
+        self
+    }
+
+    /// Prefix the message with `context` and drop the span, for another source file.
+    pub(crate) fn with_context(mut self, context: &str) -> Self {
+        let info = self.info_mut();
+        info.message = format!("{}: {}", context, info.message);
+        info.span = None;
+        info.module = None;
+        self
+    }
+
+    /// Format the error like rustc: header, location, underlined line, hint and backtrace.
+    pub fn render(&self, source: &str, filename: &str) -> String {
+        let info = self.info();
+        let (source, filename) = match &info.module {
+            Some(module) => (module.text.as_str(), module.path.as_str()),
+            None => (source, filename),
+        };
+        let mut out = format!("error[{}]: {}\n", self.kind(), info.message);
+
+        match info.span.filter(|span| span.start <= source.len()) {
+            Some(span) => {
+                let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+                let line_end = source[span.start..]
+                    .find('\n')
+                    .map_or(source.len(), |i| span.start + i);
+                let line = source[line_start..line_end].trim_end_matches('\r');
+                let (line_number, column) = line_and_column(source, span.start);
+                let underline = source[span.start..span.end.clamp(span.start, line_end)]
+                    .chars()
+                    .count()
+                    .max(1);
+
+                let gutter = " ".repeat(line_number.to_string().len());
+                out.push_str(&format!(
+                    "{}--> {}:{}:{}\n",
+                    gutter, filename, line_number, column
+                ));
+                out.push_str(&format!("{} |\n", gutter));
+                out.push_str(&format!("{} | {}\n", line_number, line));
+                out.push_str(&format!(
+                    "{} | {}{}\n",
+                    gutter,
+                    " ".repeat(column - 1),
+                    "^".repeat(underline)
+                ));
+                Self::render_notes(&mut out, info, &gutter);
+            }
+            None => {
+                out.push_str(&format!(" --> {}\n", filename));
+                Self::render_notes(&mut out, info, " ");
+            }
+        }
+        out
+    }
+
+    fn render_notes(out: &mut String, info: &ErrorInfo, gutter: &str) {
+        if let Some(hint) = &info.hint {
+            out.push_str(&format!("{} = hint: {}\n", gutter, hint));
+        }
+        if !info.stack.is_empty() {
+            out.push_str(&format!("{} = backtrace:\n", gutter));
+            // Runs of the same frame, as in deep recursion, are shown once
+            let mut frames = info.stack.iter().peekable();
+            while let Some(frame) = frames.next() {
+                out.push_str(&format!("{}       at {}\n", gutter, frame));
+                let mut repeats = 0;
+                while frames.next_if_eq(&frame).is_some() {
+                    repeats += 1;
+                }
+                if repeats > 0 {
+                    out.push_str(&format!(
+                        "{}       ... repeated {} more times\n",
+                        gutter, repeats
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for FenicsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for FenicsError {}
+
+impl From<String> for FenicsError {
+    fn from(message: String) -> Self {
+        FenicsError::runtime(message)
+    }
+}
+
+impl From<&str> for FenicsError {
+    fn from(message: &str) -> Self {
+        FenicsError::runtime(message)
+    }
+}
+
+/// 1-based line and column (in characters) of the byte `offset` in `source`.
+pub(crate) fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = source[..line_start].matches('\n').count() + 1;
+    let column = source[line_start..offset].chars().count() + 1;
+    (line, column)
+}
+
+/// The candidate closest to `name`, if it is close enough to be a typo.
+pub(crate) fn closest_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit distance where swapping adjacent characters also counts as one edit.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<usize>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let above = &rows[i - 1];
+            row[j] = (above[j - 1] + usize::from(a[i - 1] != b[j - 1]))
+                .min(above[j] + 1)
+                .min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_underlines_the_span() {
+        let source = "count : 1\nprint(cuont)\n";
+        let err = FenicsError::name("Variable 'cuont' not found")
+            .with_span(Span::new(16, 21))
+            .with_hint("did you mean 'count'?");
+        assert_eq!(
+            err.render(source, "main.fenics"),
+            "error[name]: Variable 'cuont' not found\n \
+             --> main.fenics:2:7\n  \
+             |\n\
+             2 | print(cuont)\n  \
+             |       ^^^^^\n  \
+             = hint: did you mean 'count'?\n"
+        );
+    }
+
+    #[test]
+    fn innermost_span_wins() {
+        let err = FenicsError::runtime("Division by zero")
+            .with_span(Span::new(4, 9))
+            .with_span(Span::new(0, 9));
+        assert_eq!(err.span(), Some(Span::new(4, 9)));
+    }
+
+    #[test]
+    fn closest_name_allows_small_typos() {
+        let names = ["count", "total", "x"];
+        assert_eq!(closest_name("cuont", names), Some("count"));
+        assert_eq!(closest_name("totl", names), Some("total"));
+        assert_eq!(closest_name("y", names), Some("x"));
+        assert_eq!(closest_name("average", names), None);
+    }
+}
@@ -0,0 +1,139 @@
+//! Equality, ordering and identity of values.
+//!
+//! `==`, `is`, `has`, `unique` and `sort` all go through `Value::compare`:
+//!
+//! - Ints, Decimals and Floats compare by numeric value, so `1 == 1.0`;
+//!   NaN equals itself and sorts after every other number.
+//! - Arrays compare item by item, then by length. Objects compare their
+//!   fields sorted by key, so field order doesn't matter, but a field
+//!   holding null is not the same as a missing field.
+//! - An array or object met again while comparing it (a cycle) counts as
+//!   equal at that point, so cyclic values compare without recursing forever.
+//! - Functions are equal only to the same definition closed over the same frames.
+//! - Values of different types are never equal and are ordered by type:
+//!   Null, Boolean, numbers, String, Array, Object, Range, Regex, Function,
+//!   Error, Bridge.
+//!
+//! `===` is identity for arrays and objects, and `==` with the same type otherwise.
+
+use crate::features::{numbers, Value};
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+impl Value {
+    /// Structural equality, as `==` sees it.
+    pub fn equals(&self, other: &Value) -> bool {
+        self.compare(other) == Ordering::Equal
+    }
+
+    /// A total order over all values, agreeing with `equals`.
+    pub fn compare(&self, other: &Value) -> Ordering {
+        self.compare_in(other, &mut Vec::new())
+    }
+
+    /// `compare`, treating a pair of arrays or objects already in `open` as equal.
+    fn compare_in(&self, other: &Value, open: &mut Vec<(*const (), *const ())>) -> Ordering {
+        match (self, other) {
+            (a, b) if a.is_number() && b.is_number() => numbers::compare(a, b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Array(a), Value::Array(b)) => {
+                let key = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+                if Rc::ptr_eq(a, b) || open.contains(&key) {
+                    return Ordering::Equal;
+                }
+                open.push(key);
+                let (a, b) = (a.borrow(), b.borrow());
+                let order = a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|(x, y)| x.compare_in(y, open))
+                    .find(|order| order.is_ne())
+                    .unwrap_or_else(|| a.len().cmp(&b.len()));
+                open.pop();
+                order
+            }
+            (Value::Object(a), Value::Object(b)) => {
+                let key = (Rc::as_ptr(a) as *const (), Rc::as_ptr(b) as *const ());
+                if Rc::ptr_eq(a, b) || open.contains(&key) {
+                    return Ordering::Equal;
+                }
+                open.push(key);
+                let sorted = |fields: &indexmap::IndexMap<String, Value>| {
+                    let mut entries: Vec<(String, Value)> = fields
+                        .iter()
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect();
+                    entries.sort_by(|x, y| x.0.cmp(&y.0));
+                    entries
+                };
+                let (a, b) = (sorted(&a.borrow()), sorted(&b.borrow()));
+                let order = a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| va.compare_in(vb, open)))
+                    .find(|order| order.is_ne())
+                    .unwrap_or_else(|| a.len().cmp(&b.len()));
+                open.pop();
+                order
+            }
+            (Value::Range(a), Value::Range(b)) => {
+                (a.start, a.end, a.step, a.inclusive).cmp(&(b.start, b.end, b.step, b.inclusive))
+            }
+            (Value::Regex(a), Value::Regex(b)) => {
+                (&a.pattern, &a.flags).cmp(&(&b.pattern, &b.flags))
+            }
+            (Value::Function { .. }, Value::Function { .. }) => {
+                function_key(self).cmp(&function_key(other))
+            }
+            (Value::Error(a), Value::Error(b)) => {
+                (a.kind(), a.message()).cmp(&(b.kind(), b.message()))
+            }
+            (Value::BridgeModule(a), Value::BridgeModule(b)) => a.cmp(b),
+            _ => type_rank(self).cmp(&type_rank(other)),
+        }
+    }
+
+    /// Identity, as `===` sees it.
+    pub fn is_identical(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
+            _ => self.type_name() == other.type_name() && self.equals(other),
+        }
+    }
+}
+
+fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Boolean(_) => 1,
+        Value::Integer(_) | Value::BigInt(_) | Value::Decimal(_) | Value::Float(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+        Value::Range(_) => 6,
+        Value::Regex(_) => 7,
+        Value::Function { .. } => 8,
+        Value::Error(_) => 9,
+        Value::BridgeModule(_) => 10,
+    }
+}
+
+/// What tells functions apart: their definition and the frames they closed over.
+fn function_key(func: &Value) -> (&str, Option<(usize, usize)>, Vec<usize>) {
+    match func {
+        Value::Function {
+            body, env, module, ..
+        } => (
+            module.path.as_str(),
+            body.first().map(|stmt| (stmt.span.start, stmt.span.end)),
+            env.0
+                .iter()
+                .map(|scope| Rc::as_ptr(scope) as usize)
+                .collect(),
+        ),
+        _ => unreachable!("function_key called on {}", func.type_name()),
+    }
+}
@@ -0,0 +1,49 @@
+use crate::ast::Type;
+use crate::features::Value;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A variable's value, whether it is `const`, and its declared type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub value: Value,
+    pub is_const: bool,
+    pub type_annotation: Option<Type>,
+}
+
+impl Binding {
+    pub fn new(value: Value, is_const: bool) -> Self {
+        Binding {
+            value,
+            is_const,
+            type_annotation: None,
+        }
+    }
+
+    pub fn mutable(value: Value) -> Self {
+        Self::new(value, false)
+    }
+}
+
+/// A frame of local variables, shared so closures capture it by reference.
+pub type Scope = Rc<RefCell<HashMap<String, Binding>>>;
+
+/// The local frames a closure captured, outermost first.
+#[derive(Clone, Default)]
+pub struct Environment(pub Vec<Scope>);
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Frames can contain the closure itself, so never print their contents
+        write!(f, "Environment({} frames)", self.0.len())
+    }
+}
+
+impl PartialEq for Environment {
+    /// Two environments are equal when they share the very same frames.
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(&other.0).all(|(a, b)| Rc::ptr_eq(a, b))
+    }
+}
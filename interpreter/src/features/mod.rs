@@ -1,5 +1,15 @@
 pub mod bridges;
+pub mod compare;
+pub mod environment;
+pub mod numbers;
+pub mod range;
+pub mod regex;
+pub mod source;
 pub mod value;
 
+pub use self::regex::Regex;
 pub use bridges::Bridge;
-pub use value::Value;
+pub use environment::{Binding, Environment, Scope};
+pub use range::Range;
+pub use source::SourceFile;
+pub use value::{SharedArray, SharedObject, Value};
@@ -0,0 +1,305 @@
+//! Arithmetic and comparison across Int (i64 or BigInt), Decimal and Float.
+
+use crate::ast::BinaryOperator;
+use crate::error::FenicsError;
+use crate::features::Value;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{FromPrimitive, One, Signed, ToPrimitive, Zero};
+use rust_decimal::{Decimal, MathematicalOps};
+use std::cmp::Ordering;
+
+/// Size in bits past which `Int ^ Int` gives up instead of allocating.
+const MAX_POWER_BITS: u64 = 1 << 20;
+
+/// A number widened for arithmetic or comparison.
+enum Number {
+    Int(BigInt),
+    Decimal(Decimal),
+    Float(f64),
+}
+
+impl Number {
+    fn of(value: &Value) -> Option<Number> {
+        match value {
+            Value::Integer(i) => Some(Number::Int(BigInt::from(*i))),
+            Value::BigInt(i) => Some(Number::Int(i.clone())),
+            Value::Decimal(d) => Some(Number::Decimal(*d)),
+            Value::Float(f) => Some(Number::Float(*f)),
+            _ => None,
+        }
+    }
+}
+
+impl Value {
+    pub fn is_number(&self) -> bool {
+        matches!(
+            self,
+            Value::Integer(_) | Value::BigInt(_) | Value::Decimal(_) | Value::Float(_)
+        )
+    }
+
+    pub(crate) fn is_nan(&self) -> bool {
+        matches!(self, Value::Float(f) if f.is_nan())
+    }
+}
+
+/// An Int as a value: an `Integer` when it fits in an `i64`.
+pub fn int_value(n: BigInt) -> Value {
+    match i64::try_from(&n) {
+        Ok(small) => Value::Integer(small),
+        Err(_) => Value::BigInt(n),
+    }
+}
+
+/// `n` as a Decimal, if it has at most 28 digits.
+pub(crate) fn int_to_decimal(n: &BigInt) -> Result<Decimal, FenicsError> {
+    i128::try_from(n)
+        .ok()
+        .and_then(|n| Decimal::try_from_i128_with_scale(n, 0).ok())
+        .ok_or_else(|| FenicsError::runtime(format!("{} is too large for a Decimal", n)))
+}
+
+pub(crate) fn int_to_float(n: &BigInt) -> f64 {
+    n.to_f64().unwrap_or(f64::NAN)
+}
+
+/// Apply an arithmetic operator to two numbers, or `None` if either isn't one.
+pub(crate) fn arithmetic(
+    op: &BinaryOperator,
+    left: &Value,
+    right: &Value,
+) -> Option<Result<Value, FenicsError>> {
+    if let (Value::Integer(a), Value::Integer(b)) = (left, right) {
+        if let Some(result) = small_int(op, *a, *b) {
+            return Some(result);
+        }
+    }
+    Some(match (Number::of(left)?, Number::of(right)?) {
+        (Number::Int(a), Number::Int(b)) => int(op, a, b),
+        (Number::Decimal(a), Number::Decimal(b)) => decimal(op, a, b),
+        (Number::Float(a), Number::Float(b)) => float(op, a, b),
+        (Number::Int(a), Number::Decimal(b)) => int_to_decimal(&a).and_then(|a| decimal(op, a, b)),
+        (Number::Decimal(a), Number::Int(b)) => int_to_decimal(&b).and_then(|b| decimal(op, a, b)),
+        (Number::Int(a), Number::Float(b)) => float(op, int_to_float(&a), b),
+        (Number::Float(a), Number::Int(b)) => float(op, a, int_to_float(&b)),
+        (Number::Decimal(_), Number::Float(_)) | (Number::Float(_), Number::Decimal(_)) => {
+            Err(FenicsError::type_error("Cannot mix Decimal and Float")
+                .with_hint("convert one side with decimal() or float()"))
+        }
+    })
+}
+
+fn division_by_zero(op: &BinaryOperator) -> FenicsError {
+    match op {
+        BinaryOperator::Modulo => FenicsError::runtime("Modulo by zero"),
+        _ => FenicsError::runtime("Division by zero"),
+    }
+}
+
+fn invalid_operator() -> FenicsError {
+    FenicsError::runtime("Invalid binary operator")
+}
+
+/// Int arithmetic on `i64`s, or `None` when the result needs a BigInt.
+fn small_int(op: &BinaryOperator, a: i64, b: i64) -> Option<Result<Value, FenicsError>> {
+    let result = match op {
+        BinaryOperator::Add => a.checked_add(b),
+        BinaryOperator::Subtract => a.checked_sub(b),
+        BinaryOperator::Multiply => a.checked_mul(b),
+        BinaryOperator::Divide | BinaryOperator::FloorDivide | BinaryOperator::Modulo if b == 0 => {
+            return Some(Err(division_by_zero(op)))
+        }
+        BinaryOperator::Divide => return Some(Ok(Value::Float(a as f64 / b as f64))),
+        // Only i64::MIN by -1 overflows
+        BinaryOperator::FloorDivide => a.checked_div(b).map(|_| Integer::div_floor(&a, &b)),
+        BinaryOperator::Modulo => a.checked_rem(b).map(|_| a.mod_floor(&b)),
+        BinaryOperator::Power if b < 0 => return Some(Ok(Value::Float((a as f64).powf(b as f64)))),
+        BinaryOperator::Power => u32::try_from(b).ok().and_then(|b| a.checked_pow(b)),
+        _ => None,
+    };
+    result.map(|n| Ok(Value::Integer(n)))
+}
+
+fn int(op: &BinaryOperator, a: BigInt, b: BigInt) -> Result<Value, FenicsError> {
+    match op {
+        BinaryOperator::Add => Ok(int_value(a + b)),
+        BinaryOperator::Subtract => Ok(int_value(a - b)),
+        BinaryOperator::Multiply => Ok(int_value(a * b)),
+        BinaryOperator::Divide | BinaryOperator::FloorDivide | BinaryOperator::Modulo
+            if b.is_zero() =>
+        {
+            Err(division_by_zero(op))
+        }
+        BinaryOperator::Divide => Ok(Value::Float(int_to_float(&a) / int_to_float(&b))),
+        BinaryOperator::FloorDivide => Ok(int_value(a.div_floor(&b))),
+        BinaryOperator::Modulo => Ok(int_value(a.mod_floor(&b))),
+        BinaryOperator::Power => int_power(a, b),
+        _ => Err(invalid_operator()),
+    }
+}
+
+fn int_power(base: BigInt, exponent: BigInt) -> Result<Value, FenicsError> {
+    if exponent.is_negative() {
+        let (base, exponent) = (int_to_float(&base), int_to_float(&exponent));
+        return Ok(Value::Float(base.powf(exponent)));
+    }
+    if exponent.is_zero() {
+        return Ok(Value::Integer(1));
+    }
+    // 0, 1 and -1 stay small whatever the exponent
+    if base.is_zero() || base.is_one() {
+        return Ok(int_value(base));
+    }
+    if base == -BigInt::one() {
+        return Ok(Value::Integer(if exponent.is_odd() { -1 } else { 1 }));
+    }
+    match exponent.to_u32() {
+        Some(exp) if base.bits() * u64::from(exp) <= MAX_POWER_BITS => Ok(int_value(base.pow(exp))),
+        _ => Err(FenicsError::runtime(format!(
+            "{} ^ {} is too large",
+            base, exponent
+        ))),
+    }
+}
+
+fn decimal(op: &BinaryOperator, a: Decimal, b: Decimal) -> Result<Value, FenicsError> {
+    let result = match op {
+        BinaryOperator::Add => a.checked_add(b),
+        BinaryOperator::Subtract => a.checked_sub(b),
+        BinaryOperator::Multiply => a.checked_mul(b),
+        BinaryOperator::Divide | BinaryOperator::FloorDivide | BinaryOperator::Modulo
+            if b.is_zero() =>
+        {
+            return Err(division_by_zero(op))
+        }
+        BinaryOperator::Divide => a.checked_div(b),
+        BinaryOperator::FloorDivide => a.checked_div(b).map(|q| q.floor()),
+        BinaryOperator::Modulo => a.checked_rem(b).map(|r| {
+            if !r.is_zero() && r.is_sign_negative() != b.is_sign_negative() {
+                r + b
+            } else {
+                r
+            }
+        }),
+        BinaryOperator::Power => {
+            if !b.fract().is_zero() {
+                return Err(FenicsError::type_error(format!(
+                    "Decimal powers need a whole exponent, got {}",
+                    b
+                )));
+            }
+            b.to_i64().and_then(|exp| a.checked_powi(exp))
+        }
+        _ => return Err(invalid_operator()),
+    };
+    result
+        .map(Value::Decimal)
+        .ok_or_else(|| FenicsError::runtime("Decimal result out of range"))
+}
+
+fn float(op: &BinaryOperator, a: f64, b: f64) -> Result<Value, FenicsError> {
+    let result = match op {
+        BinaryOperator::Add => a + b,
+        BinaryOperator::Subtract => a - b,
+        BinaryOperator::Multiply => a * b,
+        BinaryOperator::Divide => a / b,
+        BinaryOperator::FloorDivide => (a / b).floor(),
+        BinaryOperator::Modulo => {
+            let r = a % b;
+            if r != 0.0 && (r < 0.0) != (b < 0.0) {
+                r + b
+            } else {
+                r
+            }
+        }
+        BinaryOperator::Power => a.powf(b),
+        _ => return Err(invalid_operator()),
+    };
+    Ok(Value::Float(result))
+}
+
+/// Order two numbers by value without rounding; NaN sorts after every other number.
+pub(crate) fn compare(left: &Value, right: &Value) -> Ordering {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+        (Value::Float(a), Value::Float(b)) => compare_floats(*a, *b),
+        (Value::Integer(a), Value::Float(b)) => compare_int_float(*a, *b),
+        (Value::Float(a), Value::Integer(b)) => compare_int_float(*b, *a).reverse(),
+        _ => match (Number::of(left), Number::of(right)) {
+            (Some(a), Some(b)) => compare_numbers(a, b),
+            _ => unreachable!(
+                "compare called on {} and {}",
+                left.type_name(),
+                right.type_name()
+            ),
+        },
+    }
+}
+
+fn compare_numbers(a: Number, b: Number) -> Ordering {
+    match (a, b) {
+        (Number::Int(a), Number::Int(b)) => a.cmp(&b),
+        (Number::Decimal(a), Number::Decimal(b)) => a.cmp(&b),
+        (Number::Float(a), Number::Float(b)) => compare_floats(a, b),
+        (Number::Int(a), Number::Decimal(b)) => compare_int_decimal(&a, b),
+        (Number::Decimal(a), Number::Int(b)) => compare_int_decimal(&b, a).reverse(),
+        (Number::Int(a), Number::Float(b)) => compare_big_float(&a, b),
+        (Number::Float(a), Number::Int(b)) => compare_big_float(&b, a).reverse(),
+        (Number::Decimal(a), Number::Float(b)) => compare_floats(decimal_to_float(a), b),
+        (Number::Float(a), Number::Decimal(b)) => compare_floats(a, decimal_to_float(b)),
+    }
+}
+
+pub(crate) fn decimal_to_float(d: Decimal) -> f64 {
+    d.to_f64().unwrap_or(f64::NAN)
+}
+
+fn compare_floats(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        // Only NaN makes partial_cmp fail
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// Compare without rounding `i` to a Float.
+fn compare_int_float(i: i64, f: f64) -> Ordering {
+    // 2^63, the first Float past i64::MAX
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if f.is_nan() || f >= LIMIT {
+        return Ordering::Less;
+    }
+    if f < -LIMIT {
+        return Ordering::Greater;
+    }
+    let whole = f.trunc();
+    i.cmp(&(whole as i64))
+        .then_with(|| compare_floats(0.0, f - whole))
+}
+
+fn compare_big_float(i: &BigInt, f: f64) -> Ordering {
+    if f.is_nan() {
+        return Ordering::Less;
+    }
+    let whole = f.trunc();
+    match BigInt::from_f64(whole) {
+        Some(whole_int) => i
+            .cmp(&whole_int)
+            .then_with(|| compare_floats(0.0, f - whole)),
+        // Infinite
+        None if f > 0.0 => Ordering::Less,
+        None => Ordering::Greater,
+    }
+}
+
+/// Ints too large for a Decimal are past every Decimal.
+fn compare_int_decimal(i: &BigInt, d: Decimal) -> Ordering {
+    match int_to_decimal(i) {
+        Ok(i) => i.cmp(&d),
+        Err(_) if i.is_negative() => Ordering::Less,
+        Err(_) => Ordering::Greater,
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+/// Lazy integer range from `start..end`, `start..=end` or `start..end step n`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub step: i64,
+    pub inclusive: bool,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64, step: i64, inclusive: bool) -> Result<Self, String> {
+        if step == 0 {
+            return Err("Range step cannot be zero".to_string());
+        }
+        Ok(Range {
+            start,
+            end,
+            step,
+            inclusive,
+        })
+    }
+
+    /// Number of elements the range yields, saturating at `usize::MAX`.
+    pub fn len(&self) -> usize {
+        usize::try_from(self.count()).unwrap_or(usize::MAX)
+    }
+
+    /// Exact number of elements the range yields.
+    pub fn count(&self) -> u128 {
+        let (start, end, step) = (self.start as i128, self.end as i128, self.step as i128);
+        // Distance to the exclusive bound, in the direction of the step
+        let span = if step > 0 {
+            end - start + if self.inclusive { 1 } else { 0 }
+        } else {
+            start - end + if self.inclusive { 1 } else { 0 }
+        };
+        if span <= 0 {
+            0
+        } else {
+            let step = step.abs();
+            ((span + step - 1) / step) as u128
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
+    /// The element at position `index`, if the range is that long.
+    pub fn get(&self, index: usize) -> Option<i64> {
+        if index < self.len() {
+            Some((self.start as i128 + index as i128 * self.step as i128) as i64)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        let offset = value as i128 - self.start as i128;
+        let step = self.step as i128;
+        offset % step == 0 && offset / step >= 0 && ((offset / step) as u128) < self.count()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len()).filter_map(move |i| self.get(i))
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{}{}", self.start, op, self.end)?;
+        if self.step != 1 {
+            write!(f, " step {}", self.step)?;
+        }
+        Ok(())
+    }
+}
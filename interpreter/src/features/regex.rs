@@ -0,0 +1,123 @@
+use crate::features::Value;
+use indexmap::IndexMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Regular expression from a `/pattern/flags` literal, compiled once and shared.
+#[derive(Clone)]
+pub struct Regex {
+    pub pattern: String,
+    pub flags: String,
+    compiled: Rc<::regex::Regex>,
+}
+
+impl Regex {
+    /// Compile `pattern` with any of the flags `i`, `m`, `s` and `x`.
+    pub fn new(pattern: &str, flags: &str) -> Result<Self, String> {
+        let mut builder = ::regex::RegexBuilder::new(pattern);
+        for flag in flags.chars() {
+            match flag {
+                'i' => builder.case_insensitive(true),
+                'm' => builder.multi_line(true),
+                's' => builder.dot_matches_new_line(true),
+                'x' => builder.ignore_whitespace(true),
+                _ => {
+                    return Err(format!(
+                        "Unknown regex flag '{}', expected i, m, s or x",
+                        flag
+                    ))
+                }
+            };
+        }
+        let compiled = builder.build().map_err(|err| {
+            // Syntax errors draw the pattern over several lines and end
+            // with what went wrong
+            let message = err.to_string();
+            let reason = message.lines().last().unwrap_or_default();
+            format!(
+                "Invalid regex /{}/: {}",
+                pattern,
+                reason.trim_start_matches("error: ")
+            )
+        })?;
+        Ok(Regex {
+            pattern: pattern.to_string(),
+            flags: flags.to_string(),
+            compiled: Rc::new(compiled),
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.compiled.is_match(text)
+    }
+
+    /// The first match in `text`, as returned by `captures`, or null.
+    pub fn find(&self, text: &str) -> Value {
+        self.compiled
+            .captures(text)
+            .map_or(Value::Null, |caps| self.captures(&caps))
+    }
+
+    /// Every match in `text`, as returned by `captures`.
+    pub fn find_all(&self, text: &str) -> Value {
+        Value::array(
+            self.compiled
+                .captures_iter(text)
+                .map(|caps| self.captures(&caps))
+                .collect(),
+        )
+    }
+
+    /// `text` with the first or every match replaced; `$1` or `${name}` name a group.
+    pub fn replace(&self, text: &str, replacement: &str, all: bool) -> String {
+        let limit = if all { 0 } else { 1 };
+        self.compiled
+            .replacen(text, limit, replacement)
+            .into_owned()
+    }
+
+    pub fn split(&self, text: &str) -> Value {
+        Value::array(
+            self.compiled
+                .split(text)
+                .map(|part| Value::String(part.to_string()))
+                .collect(),
+        )
+    }
+
+    /// A match as an object of named groups, or an array of the match and its groups.
+    fn captures(&self, caps: &::regex::Captures) -> Value {
+        let text = |m: Option<::regex::Match>| {
+            m.map_or(Value::Null, |m| Value::String(m.as_str().to_string()))
+        };
+        let names: Vec<&str> = self.compiled.capture_names().flatten().collect();
+        if names.is_empty() {
+            Value::array(caps.iter().map(text).collect())
+        } else {
+            Value::object(
+                names
+                    .into_iter()
+                    .map(|name| (name.to_string(), text(caps.name(name))))
+                    .collect::<IndexMap<_, _>>(),
+            )
+        }
+    }
+}
+
+impl PartialEq for Regex {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.flags == other.flags
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Regex({})", self)
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "/{}/{}", self.pattern, self.flags)
+    }
+}
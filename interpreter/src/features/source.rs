@@ -0,0 +1,40 @@
+use crate::error::line_and_column;
+use std::fmt;
+
+/// A Fenics source file being run, remembered by the functions defined in it.
+pub struct SourceFile {
+    pub path: String,
+    pub text: String,
+}
+
+impl SourceFile {
+    pub fn new(path: impl Into<String>, text: impl Into<String>) -> Self {
+        SourceFile {
+            path: path.into(),
+            text: text.into(),
+        }
+    }
+
+    /// 1-based line and column of the byte `offset`.
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        line_and_column(&self.text, offset)
+    }
+}
+
+impl Default for SourceFile {
+    fn default() -> Self {
+        Self::new("<main>", "")
+    }
+}
+
+impl fmt::Debug for SourceFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SourceFile({})", self.path)
+    }
+}
+
+impl PartialEq for SourceFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
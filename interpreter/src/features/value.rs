@@ -1,44 +1,183 @@
-use crate::ast::{Parameter, Statement};
+use crate::ast::{Parameter, Statement, Type};
+use crate::error::FenicsError;
+use crate::features::{Environment, Range, Regex, SourceFile};
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use rust_decimal::Decimal;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Arrays and objects are shared by reference; `copy()` and `deep_copy()` make new ones.
+pub type SharedArray = Rc<RefCell<Vec<Value>>>;
+pub type SharedObject = Rc<RefCell<IndexMap<String, Value>>>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Integer(i64),
+    // An Int too large for an i64; smaller results are always `Integer`
+    BigInt(BigInt),
     Float(f64),
+    Decimal(Decimal),
     String(String),
     Boolean(bool),
     Null,
-    Array(Vec<Value>),
-    Object(HashMap<String, Value>),
+    Array(SharedArray),
+    Object(SharedObject),
+    Range(Range),
+    Regex(Regex),
     // Reference to a registered Rust bridge module by name
     BridgeModule(String),
     Function {
+        // None for anonymous functions
+        name: Option<String>,
         params: Vec<Parameter>,
+        return_type: Option<Type>,
         body: Vec<Statement>,
+        // Local frames in scope where the function was defined
+        env: Environment,
+        // File the function was defined in
+        module: Rc<SourceFile>,
     },
+    // An error caught by `catch`
+    Error(Box<FenicsError>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, &mut Vec::new())
+    }
 }
 
 impl Value {
-    pub fn to_string(&self) -> String {
+    /// A new array, not shared with any other value.
+    pub fn array(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(RefCell::new(items)))
+    }
+
+    /// A new object, not shared with any other value.
+    pub fn object(fields: IndexMap<String, Value>) -> Value {
+        Value::Object(Rc::new(RefCell::new(fields)))
+    }
+
+    /// `copy(value)`: a new array or object holding the same, still shared, items.
+    pub fn shallow_copy(&self) -> Value {
         match self {
-            Value::Integer(i) => i.to_string(),
-            Value::Float(f) => f.to_string(),
-            Value::String(s) => s.clone(),
-            Value::Boolean(b) => b.to_string(),
-            Value::Null => "null".to_string(),
-            Value::Array(arr) => {
-                let items: Vec<String> = arr.iter().map(|v| v.to_string()).collect();
-                format!("[{}]", items.join(", "))
+            Value::Array(items) => Value::array(items.borrow().clone()),
+            Value::Object(fields) => Value::object(fields.borrow().clone()),
+            other => other.clone(),
+        }
+    }
+
+    /// `deep_copy(value)`: copies nested values, keeping shared and cyclic structure.
+    pub fn deep_copy(&self) -> Value {
+        self.deep_copy_into(&mut HashMap::new())
+    }
+
+    fn deep_copy_into(&self, copies: &mut HashMap<*const (), Value>) -> Value {
+        match self {
+            Value::Array(items) => {
+                let key = Rc::as_ptr(items) as *const ();
+                if let Some(copy) = copies.get(&key) {
+                    return copy.clone();
+                }
+                let copy: SharedArray = Rc::default();
+                copies.insert(key, Value::Array(copy.clone()));
+                let copied = items
+                    .borrow()
+                    .iter()
+                    .map(|item| item.deep_copy_into(copies))
+                    .collect();
+                *copy.borrow_mut() = copied;
+                Value::Array(copy)
             }
-            Value::Object(obj) => {
-                let items: Vec<String> = obj
+            Value::Object(fields) => {
+                let key = Rc::as_ptr(fields) as *const ();
+                if let Some(copy) = copies.get(&key) {
+                    return copy.clone();
+                }
+                let copy: SharedObject = Rc::default();
+                copies.insert(key, Value::Object(copy.clone()));
+                let copied = fields
+                    .borrow()
                     .iter()
-                    .map(|(k, v)| format!("{}: {}", k, v.to_string()))
+                    .map(|(key, item)| (key.clone(), item.deep_copy_into(copies)))
                     .collect();
-                format!("{{{}}}", items.join(", "))
+                *copy.borrow_mut() = copied;
+                Value::Object(copy)
             }
-            Value::BridgeModule(name) => format!("<bridge:{}>", name),
-            Value::Function { .. } => "<function>".to_string(),
+            other => other.clone(),
+        }
+    }
+
+    /// Display `self`, writing a value nested in itself as `[...]` or `{...}`.
+    fn write(&self, f: &mut fmt::Formatter<'_>, open: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::BigInt(i) => write!(f, "{}", i),
+            Value::Float(fl) => write!(f, "{}", fl),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Null => write!(f, "null"),
+            Value::Array(arr) => {
+                let key = Rc::as_ptr(arr) as *const ();
+                if open.contains(&key) {
+                    return write!(f, "[...]");
+                }
+                open.push(key);
+                write!(f, "[")?;
+                for (i, item) in arr.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.write(f, open)?;
+                }
+                open.pop();
+                write!(f, "]")
+            }
+            Value::Object(obj) => {
+                let key = Rc::as_ptr(obj) as *const ();
+                if open.contains(&key) {
+                    return write!(f, "{{...}}");
+                }
+                open.push(key);
+                write!(f, "{{")?;
+                for (i, (key, item)) in obj.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    item.write(f, open)?;
+                }
+                open.pop();
+                write!(f, "}}")
+            }
+            Value::Range(range) => write!(f, "{}", range),
+            Value::Regex(regex) => write!(f, "{}", regex),
+            Value::BridgeModule(name) => write!(f, "<bridge:{}>", name),
+            Value::Function { .. } => write!(f, "<function>"),
+            Value::Error(err) => write!(f, "{}", err.message()),
+        }
+    }
+
+    /// Name of the value's runtime type, spelled like the matching `ast::Type`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Integer(_) | Value::BigInt(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Decimal(_) => "Decimal",
+            Value::String(_) => "String",
+            Value::Boolean(_) => "Boolean",
+            Value::Null => "Null",
+            Value::Array(_) => "Array",
+            Value::Object(_) => "Object",
+            Value::Range(_) => "Range",
+            Value::Regex(_) => "Regex",
+            Value::BridgeModule(_) => "Bridge",
+            Value::Function { .. } => "Function",
+            Value::Error(_) => "Error",
         }
     }
 
@@ -48,8 +187,10 @@ impl Value {
             Value::Null => false,
             Value::Integer(0) => false,
             Value::Float(f) if *f == 0.0 => false,
+            Value::Decimal(d) if d.is_zero() => false,
             Value::String(s) if s.is_empty() => false,
-            Value::Array(a) if a.is_empty() => false,
+            Value::Array(a) if a.borrow().is_empty() => false,
+            Value::Range(r) if r.is_empty() => false,
             _ => true,
         }
     }
@@ -1,20 +1,92 @@
 use crate::ast::*;
+use crate::error::{FenicsError, StackFrame};
 use crate::features::bridges::{Bridge, FsBridge, HttpBridge};
-use crate::features::Value;
+use crate::features::{Binding, Scope, SourceFile, Value};
+use crate::utils::type_checker::CollectionTypes;
 use std::collections::HashMap;
+use std::rc::Rc;
+
+/// How a statement finished: normally, or by transferring control elsewhere.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlFlow {
+    Normal,
+    // The label aimed at, if any, and where the statement is
+    Break(Option<String>, Span),
+    Continue(Option<String>, Span),
+    Return(Value),
+}
+
+impl ControlFlow {
+    /// Whether this is a `break` aimed at the loop carrying `loop_label`.
+    pub(crate) fn breaks_loop(&self, loop_label: &Option<String>) -> bool {
+        matches!(self, ControlFlow::Break(label, _) if label.is_none() || label == loop_label)
+    }
+
+    /// Whether this finishes the current iteration of the loop carrying `loop_label`.
+    pub(crate) fn continues_loop(&self, loop_label: &Option<String>) -> bool {
+        match self {
+            ControlFlow::Normal => true,
+            ControlFlow::Continue(label, _) => label.is_none() || label == loop_label,
+            _ => false,
+        }
+    }
+
+    /// Error for a `break`/`continue` that escaped every enclosing loop.
+    pub(crate) fn escaped_loop_error(&self) -> FenicsError {
+        let (keyword, label, span) = match self {
+            ControlFlow::Break(label, span) => ("break", label, *span),
+            ControlFlow::Continue(label, span) => ("continue", label, *span),
+            _ => return FenicsError::runtime("Unexpected control flow outside of a loop"),
+        };
+        FenicsError::runtime(match label {
+            Some(name) => format!(
+                "'{} @{}' has no enclosing loop labeled '@{}'",
+                keyword, name, name
+            ),
+            None => format!("'{}' outside of a loop", keyword),
+        })
+        .with_span(span)
+    }
+}
+
+/// A function call in progress: the callee, and the file and span of the call.
+pub(crate) struct CallFrame {
+    pub(crate) function: String,
+    pub(crate) module: Rc<SourceFile>,
+    pub(crate) call_site: Span,
+}
 
 pub struct Interpreter {
-    pub(crate) globals: HashMap<String, Value>,
-    pub(crate) locals: Vec<HashMap<String, Value>>,
+    pub(crate) globals: HashMap<String, Binding>,
+    pub(crate) locals: Vec<Scope>,
+    // File whose code is running: the program itself, or the file that
+    // defined the function being executed
+    pub(crate) module: Rc<SourceFile>,
+    pub(crate) call_stack: Vec<CallFrame>,
+    pub(crate) collection_types: CollectionTypes,
     pub(crate) ephemerals: HashMap<String, Value>,
     pub(crate) bridges: HashMap<String, Box<dyn Bridge>>,
 }
 
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Interpreter {
     pub fn new() -> Self {
+        Self::with_source(SourceFile::default())
+    }
+
+    /// An interpreter for the program in `source`, used to report backtraces.
+    pub fn with_source(source: SourceFile) -> Self {
         let mut interp = Self {
             globals: HashMap::new(),
             locals: Vec::new(),
+            module: Rc::new(source),
+            call_stack: Vec::new(),
+            collection_types: CollectionTypes::default(),
             ephemerals: HashMap::new(),
             bridges: HashMap::new(),
         };
@@ -22,23 +94,1568 @@ impl Interpreter {
         // Register default bridges and expose them as globals
         let fs_bridge = Box::new(FsBridge::new());
         interp.bridges.insert("fs".to_string(), fs_bridge);
-        interp
-            .globals
-            .insert("fs".to_string(), Value::BridgeModule("fs".to_string()));
+        interp.globals.insert(
+            "fs".to_string(),
+            Binding::mutable(Value::BridgeModule("fs".to_string())),
+        );
 
         let http_bridge = Box::new(HttpBridge::new());
         interp.bridges.insert("http".to_string(), http_bridge);
-        interp
-            .globals
-            .insert("http".to_string(), Value::BridgeModule("http".to_string()));
+        interp.globals.insert(
+            "http".to_string(),
+            Binding::mutable(Value::BridgeModule("http".to_string())),
+        );
 
         interp
     }
 
-    pub fn interpret(&mut self, program: &Program) -> Result<(), String> {
+    pub fn interpret(&mut self, program: &Program) -> Result<(), FenicsError> {
         for statement in &program.statements {
-            self.execute_statement(statement)?;
+            if let flow @ (ControlFlow::Break(..) | ControlFlow::Continue(..)) =
+                self.execute_statement(statement)?
+            {
+                return Err(flow.escaped_loop_error());
+            }
         }
         Ok(())
     }
+
+    /// The active calls, innermost first.
+    pub(crate) fn backtrace(&self) -> Vec<StackFrame> {
+        self.call_stack
+            .iter()
+            .rev()
+            .map(|frame| {
+                let (line, column) = frame.module.position(frame.call_site.start);
+                StackFrame {
+                    function: frame.function.clone(),
+                    module: frame.module.path.clone(),
+                    line,
+                    column,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+    use crate::utils::function_store::MAX_CALL_DEPTH;
+    use indexmap::IndexMap;
+
+    fn run(src: &str) -> Result<Interpreter, String> {
+        try_run(src).map_err(|e| e.message().to_string())
+    }
+
+    fn try_run(src: &str) -> Result<Interpreter, FenicsError> {
+        let program = parse_program(src)?;
+        let mut interp = Interpreter::new();
+        interp.interpret(&program)?;
+        Ok(interp)
+    }
+
+    fn global(interp: &Interpreter, name: &str) -> Value {
+        interp
+            .globals
+            .get(name)
+            .map(|binding| binding.value.clone())
+            .unwrap_or(Value::Null)
+    }
+
+    fn shown(interp: &Interpreter, name: &str) -> String {
+        global(interp, name).to_string()
+    }
+
+    fn strings(items: &[&str]) -> Value {
+        Value::array(items.iter().map(|s| Value::String(s.to_string())).collect())
+    }
+
+    fn ints(items: &[i64]) -> Value {
+        Value::array(items.iter().map(|&i| Value::Integer(i)).collect())
+    }
+
+    #[test]
+    fn callee_cannot_read_caller_locals() {
+        let err = run(r#"
+fn inner():
+    return secret
+
+fn outer():
+    secret : 42
+    return inner()
+
+outer()
+"#)
+        .err()
+        .unwrap();
+        assert_eq!(err, "Variable 'secret' not found");
+    }
+
+    #[test]
+    fn callee_cannot_mutate_caller_locals() {
+        let interp = run(r#"
+fn bump():
+    x : 1
+
+fn caller():
+    x : 10
+    bump()
+    return x
+
+x : 100
+seen : caller()
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "seen"), Value::Integer(10));
+        assert_eq!(global(&interp, "x"), Value::Integer(100));
+    }
+
+    #[test]
+    fn functions_write_globals_only_through_global() {
+        let interp = run(r#"
+fn reset():
+    i : 0
+
+fn bump():
+    global i : i + 1
+
+i : 100
+reset()
+bump()
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "i"), Value::Integer(101));
+
+        let err = run("fn bump():\n    i +: 1\n\ni : 100\nbump()\n")
+            .err()
+            .unwrap();
+        assert_eq!(err, "Cannot assign to global 'i' inside a function");
+    }
+
+    #[test]
+    fn nested_function_declarations_are_local() {
+        let interp = run(r#"
+fn outer():
+    fn helper(n):
+        return n <= 0 ? 0 : n + helper(n - 1)
+
+    return helper(3)
+
+result : outer()
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "result"), Value::Integer(6));
+        assert!(!interp.globals.contains_key("helper"));
+        assert_eq!(
+            run("fn outer():\n    fn helper():\n        return 1\n\n\nouter()\nhelper()\n")
+                .err()
+                .unwrap(),
+            "Variable 'helper' not found"
+        );
+    }
+
+    #[test]
+    fn functions_see_their_lexical_parents() {
+        let interp = run(r#"
+fn make_adder(k):
+    fn add(x):
+        return x + k
+
+    return add
+
+k : 1000
+add2 : make_adder(2)
+result : add2(5)
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "result"), Value::Integer(7));
+    }
+
+    #[test]
+    fn closures_see_later_changes_to_captured_variables() {
+        let interp = run(r#"
+fn make():
+    k : 1
+    get : fn(): k
+    k : 5
+    return get
+
+get : make()
+seen : get()
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "seen"), Value::Integer(5));
+    }
+
+    #[test]
+    fn plain_and_compound_assignment_write_the_same_variable() {
+        let interp = run(r#"
+fn totals():
+    total : 0
+    sum : 0
+    [1, 2, 3].each(fn(x): total : total + x)
+    [1, 2, 3].each(fn(x): sum +: x)
+    count : 0
+    fn add_to_count():
+        count : count + 1
+        count +: 1
+        fresh : 1
+
+    add_to_count()
+    return [total, sum, count]
+
+seen : totals()
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "seen"), ints(&[6, 6, 2]));
+        assert!(!interp.globals.contains_key("fresh"));
+    }
+
+    #[test]
+    fn returned_counters_keep_their_own_state() {
+        let interp = run(r#"
+fn make_counter():
+    n : 0
+    fn next():
+        n : n + 1
+        return n
+
+    return next
+
+fn make_stepper():
+    n : 0
+    return fn(): n +: 10
+
+a : make_counter()
+b : make_counter()
+step : make_stepper()
+a()
+a()
+step()
+counts : [a(), b(), step()]
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "counts"), ints(&[3, 1, 20]));
+    }
+
+    #[test]
+    fn block_bodies_have_their_own_scope() {
+        let err = run("if true:\n    inner : 1\n\nprint(inner)\n")
+            .err()
+            .unwrap();
+        assert_eq!(err, "Variable 'inner' not found");
+
+        let err = run("n : 0\nwhile n < 2:\n    n +: 1\n    tmp : n\n\nprint(tmp)\n")
+            .err()
+            .unwrap();
+        assert_eq!(err, "Variable 'tmp' not found");
+    }
+
+    #[test]
+    fn plain_binding_updates_enclosing_block_variables() {
+        let interp = run(r#"
+fn count_to(limit):
+    count : 0
+    while count < limit:
+        count : count + 1
+
+    return count
+
+total : 0
+for i in 0..4:
+    total : total + i
+
+result : count_to(3)
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "total"), Value::Integer(6));
+        assert_eq!(global(&interp, "result"), Value::Integer(3));
+    }
+
+    #[test]
+    fn frames_are_restored_after_errors() {
+        let interp = run(r#"
+fn fail():
+    x : 1
+    return missing
+
+try:
+    fail()
+catch (e):
+    message : e
+
+after : 1
+"#)
+        .unwrap();
+        assert!(interp.locals.is_empty());
+        assert!(interp.call_stack.is_empty());
+        assert_eq!(global(&interp, "after"), Value::Integer(1));
+    }
+
+    #[test]
+    fn constants_declared_after_use_are_checked_at_runtime() {
+        let err = run(r#"
+fn bump():
+    limit +: 1
+
+const limit : 3
+bump()
+"#)
+        .err();
+        assert_eq!(err.as_deref(), Some("Cannot assign to constant 'limit'"));
+    }
+
+    #[test]
+    fn constants_protect_the_binding_not_the_value() {
+        let interp = run(r#"
+fn rename():
+    config.name : "b"
+
+const config : obj:
+- name: "a"
+rename()
+const xs : [1, 2]
+xs[0] : 5
+alias : xs
+alias[1] : 6
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "config"), "{name: b}");
+        assert_eq!(global(&interp, "xs"), ints(&[5, 6]));
+
+        assert_eq!(
+            run("const xs : [1]\nxs[0] : 2\nxs : [2]\n")
+                .err()
+                .as_deref(),
+            Some("Cannot assign to constant 'xs'")
+        );
+    }
+
+    #[test]
+    fn constants_can_be_shadowed_in_inner_scopes() {
+        let interp = run(r#"
+const x : 1
+fn f():
+    const x : 2
+    return x
+
+inner : f()
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "inner"), Value::Integer(2));
+        assert_eq!(global(&interp, "x"), Value::Integer(1));
+    }
+
+    #[test]
+    fn annotated_declarations_are_checked() {
+        let interp = run("Float ratio : 2\nList(Int) xs : [1, 2]\nxs[0] : 5\n").unwrap();
+        assert_eq!(global(&interp, "ratio"), Value::Float(2.0));
+
+        assert_eq!(
+            run("Int count : \"three\"\n").err().as_deref(),
+            Some("Type mismatch for 'count': expected Int, got String")
+        );
+        assert_eq!(
+            run("Int count : 1\ncount : true\n").err().as_deref(),
+            Some("Type mismatch for 'count': expected Int, got Boolean")
+        );
+        assert_eq!(
+            run("List(Int) xs : [1, 2]\nxs[1] : \"x\"\n")
+                .err()
+                .as_deref(),
+            Some("Type mismatch for 'xs': expected List(Int), got Array with String at index 1")
+        );
+        assert_eq!(
+            run("Pairs(String, Int) ages :\n- bob: \"old\"\n").err().as_deref(),
+            Some("Type mismatch for 'ages': expected Pairs(String, Int), got Object with String at key 'bob'")
+        );
+    }
+
+    #[test]
+    fn increment_and_decrement_variables_and_fields() {
+        let interp = run(r#"
+x : 5
+x--
+x--
+y : 1
+y++
+row :
+- n: 3
+rows : [row]
+i : 0
+rows[i].n--
+rows[0]["n"]--
+counts : [1, 2]
+counts[1]++
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "x"), Value::Integer(3));
+        assert_eq!(global(&interp, "y"), Value::Integer(2));
+        assert_eq!(shown(&interp, "rows"), "[{n: 1}]");
+        assert_eq!(global(&interp, "counts"), ints(&[1, 3]));
+    }
+
+    #[test]
+    fn only_existing_fields_can_be_updated() {
+        let interp = run("o :\n- a: 1\no.zz : 5\n").unwrap();
+        assert_eq!(shown(&interp, "o"), "{a: 1, zz: 5}");
+
+        for (src, message) in [
+            ("o.zz -: 5\n", "Property 'zz' not found"),
+            ("o.zz++\n", "Property 'zz' not found"),
+            ("o[\"zz\"] +: 1\n", "Key 'zz' not found"),
+        ] {
+            let src = format!("o :\n- a: 1\n{}", src);
+            assert_eq!(run(&src).err().as_deref(), Some(message), "{}", src);
+        }
+    }
+
+    #[test]
+    fn element_writes_are_checked_through_aliases() {
+        let interp =
+            run("List(Float) fs : [1.5]\nalias : fs\nalias[0] : 2\nkind : type(fs[0])\n").unwrap();
+        assert_eq!(shown(&interp, "kind"), "Float");
+
+        assert_eq!(
+            run("List(Int) nums : [1]\nother : nums\nother[0] : \"s\"\n")
+                .err()
+                .as_deref(),
+            Some("Type mismatch for 'nums': expected List(Int), got Array with String at index 0")
+        );
+        assert_eq!(
+            run("Pairs(String, Int) ages :\n- bob: 1\nsame : ages\nsame.ann : \"x\"\n").err().as_deref(),
+            Some("Type mismatch for 'ages': expected Pairs(String, Int), got Object with String at key 'ann'")
+        );
+        assert_eq!(
+            run("fn fill(xs: List(Int)):\n    xs[0] : \"s\"\n\nfill([1])\n")
+                .err()
+                .as_deref(),
+            Some("Type mismatch for 'xs': expected List(Int), got Array with String at index 0")
+        );
+    }
+
+    #[test]
+    fn annotations_do_not_convert_shared_collections() {
+        let interp = run(r#"
+a : [1, 2]
+List(Float) b : a
+fn total(xs: List(Float)):
+    return xs
+
+c : [3]
+total(c)
+d : total([4])
+kinds : [type(a[0]), type(b[0]), type(c[0]), type(d[0])]
+"#)
+        .unwrap();
+        assert!(global(&interp, "a").is_identical(&global(&interp, "b")));
+        assert_eq!(
+            global(&interp, "kinds"),
+            strings(&["Int", "Int", "Int", "Float"])
+        );
+    }
+
+    #[test]
+    fn parameters_and_return_values_are_checked() {
+        let src = r#"
+fn half(n: Int) -> Float:
+    return n / 2
+
+fn name() -> String:
+    return 42
+
+"#;
+        assert_eq!(
+            run(&format!("{}x : half(4)\n", src)).map(|i| global(&i, "x")),
+            Ok(Value::Float(2.0))
+        );
+        assert_eq!(
+            run(&format!("{}half(\"3\")\n", src)).err().as_deref(),
+            Some("Type mismatch for parameter 'n' of 'half': expected Int, got String")
+        );
+        assert_eq!(
+            run(&format!("{}name()\n", src)).err().as_deref(),
+            Some("Type mismatch for return value of 'name': expected String, got Int")
+        );
+        assert_eq!(
+            run("f : fn(s: String): s\nf(1)\n").err().as_deref(),
+            Some("Type mismatch for parameter 's' of anonymous function: expected String, got Int")
+        );
+    }
+
+    #[test]
+    fn break_and_continue_leave_the_innermost_loop() {
+        let interp = run(r#"
+seen : ""
+for i in 0..10:
+    if i % 2 == 0:
+        continue
+    if i > 6:
+        break
+    seen +: str(i)
+
+n : 0
+while true:
+    n +: 1
+    if n == 3:
+        break
+
+spins : 0
+loop 10:
+    spins +: 1
+    if spins < 4:
+        continue
+    break
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "seen"), "135");
+        assert_eq!(global(&interp, "n"), Value::Integer(3));
+        assert_eq!(global(&interp, "spins"), Value::Integer(4));
+    }
+
+    #[test]
+    fn labels_pick_the_loop_to_leave() {
+        let interp = run(r#"
+pairs : ""
+@outer for i in 0..3:
+    for j in 0..3:
+        if j == 1:
+            continue @outer
+        if i == 2:
+            break @outer
+        pairs +: str(i) + str(j) + " "
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "pairs"), "00 10 ");
+    }
+
+    #[test]
+    fn loops_inside_try_run_finally_on_break_and_continue() {
+        let interp = run(r#"
+log : ""
+for i in 0..5:
+    try:
+        if i == 1:
+            continue
+        if i == 3:
+            break
+        log +: "t" + str(i) + " "
+    catch (e):
+        log +: "c "
+    finally:
+        log +: "f" + str(i) + " "
+
+overridden : 0
+for i in 0..5:
+    overridden : i
+    try:
+        continue
+    catch (e):
+        overridden : -1
+    finally:
+        break
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "log"), "t0 f0 f1 t2 f2 f3 ");
+        assert_eq!(global(&interp, "overridden"), Value::Integer(0));
+    }
+
+    #[test]
+    fn escaped_break_and_continue_point_at_the_statement() {
+        let src = "for i in 0..2:\n    break @nope\n";
+        let err = try_run(src).err().unwrap();
+        assert_eq!(
+            err.message(),
+            "'break @nope' has no enclosing loop labeled '@nope'"
+        );
+        assert_eq!(
+            err.span().map(|s| &src[s.start..s.end]),
+            Some("break @nope")
+        );
+
+        let src = "fn skip():\n    continue\n\nfor i in 0..2:\n    skip()\n";
+        let err = try_run(src).err().unwrap();
+        assert_eq!(err.message(), "'continue' outside of a loop");
+        assert_eq!(err.span().map(|s| &src[s.start..s.end]), Some("continue"));
+    }
+
+    #[test]
+    fn runtime_errors_carry_kind_span_and_hint() {
+        let src = "total : 1
+print(totl + 1)
+";
+        let err = try_run(src).err().unwrap();
+        assert_eq!(err.kind(), "name");
+        let span = err.span().unwrap();
+        assert_eq!(&src[span.start..span.end], "totl");
+        assert_eq!(err.info().hint.as_deref(), Some("did you mean 'total'?"));
+
+        let src = "xs : [1]
+y : xs[3]
+";
+        let err = try_run(src).err().unwrap();
+        assert_eq!(err.kind(), "index");
+        let span = err.span().unwrap();
+        assert_eq!(&src[span.start..span.end], "xs[3]");
+    }
+
+    #[test]
+    fn caught_errors_expose_message_and_stack() {
+        let src = r#"
+fn inner(n):
+    return n / 0
+
+fn outer():
+    return inner(1)
+
+message : null
+stack : null
+try:
+    outer()
+catch (e):
+    message : e.message
+    stack : e.stack
+"#;
+        let program = parse_program(src).unwrap();
+        let mut interp = Interpreter::with_source(SourceFile::new("main.fenics", src));
+        interp.interpret(&program).unwrap();
+        assert_eq!(
+            global(&interp, "message"),
+            Value::String("Division by zero".to_string())
+        );
+        assert_eq!(
+            global(&interp, "stack"),
+            Value::array(vec![
+                Value::String("inner (main.fenics:6:12)".to_string()),
+                Value::String("outer (main.fenics:11:5)".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn errors_in_imported_functions_render_against_the_library() {
+        let lib = std::env::temp_dir().join(format!("fenics_lib_{}.fenics", std::process::id()));
+        std::fs::write(&lib, "fn boom(x):\n    return x / 0\n").unwrap();
+        let path = lib.to_str().unwrap().to_string();
+        let src = format!("import \"{}\" as mylib\nmylib.boom(1)\n", path);
+        let program = parse_program(&src).unwrap();
+        let mut interp = Interpreter::with_source(SourceFile::new("main.fenics", src.as_str()));
+        let err = interp.interpret(&program).unwrap_err();
+        std::fs::remove_file(&lib).unwrap();
+        assert_eq!(
+            err.render(&src, "main.fenics"),
+            format!(
+                "error[runtime]: Division by zero\n \
+                 --> {}:2:12\n  \
+                 |\n\
+                 2 |     return x / 0\n  \
+                 |            ^^^^^\n  \
+                 = backtrace:\n        \
+                 at boom (main.fenics:2:1)\n",
+                path
+            )
+        );
+    }
+
+    #[test]
+    fn runaway_recursion_stops_at_the_call_depth_limit() {
+        let src = "fn f(n):\n    return f(n + 1)\n\nf(0)\n";
+        // Test threads have too little stack for the deepest allowed recursion
+        let (message, depth, rendered) = std::thread::Builder::new()
+            .stack_size(256 << 20)
+            .spawn(move || {
+                let program = parse_program(src).unwrap();
+                let mut interp = Interpreter::with_source(SourceFile::new("main.fenics", src));
+                let err = interp.interpret(&program).err().unwrap();
+                let rendered = err.render(src, "main.fenics");
+                (err.message().to_string(), err.stack().len(), rendered)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(message, "Maximum call depth of 1000 exceeded in 'f'");
+        assert_eq!(depth, MAX_CALL_DEPTH);
+        assert!(rendered.ends_with(
+            "at f (main.fenics:2:12)\n        \
+             ... repeated 998 more times\n        \
+             at f (main.fenics:4:1)\n"
+        ));
+    }
+
+    #[test]
+    fn throw_raises_any_value() {
+        let interp = run(r#"
+kind : null
+message : null
+code : null
+try:
+    throw obj:
+    - code: 404
+catch (e):
+    kind : e.kind
+    message : e.message
+    code : e.code
+
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "kind"), Value::String("thrown".to_string()));
+        assert_eq!(
+            global(&interp, "message"),
+            Value::String("{code: 404}".to_string())
+        );
+        assert_eq!(global(&interp, "code"), Value::Integer(404));
+
+        let err = try_run("throw \"boom\"\n").err().unwrap();
+        assert_eq!(err.kind(), "thrown");
+        assert_eq!(err.message(), "boom");
+        assert_eq!(err.thrown_value(), Some(&Value::String("boom".to_string())));
+    }
+
+    #[test]
+    fn caught_errors_can_be_read_like_objects() {
+        let interp = run(r#"
+kind : null
+keys : null
+found : null
+code : null
+fallback : null
+try:
+    throw obj:
+    - code: 404
+catch (e):
+    kind : type(e)
+    keys : e.keys()
+    found : [e.has("message"), e.has("code"), e.has("nope")]
+    code : e.get("code")
+    fallback : e.get("nope", 0)
+
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "kind"), "Error");
+        assert_eq!(
+            global(&interp, "keys"),
+            strings(&["message", "kind", "span", "stack", "value", "code"])
+        );
+        assert_eq!(shown(&interp, "found"), "[true, true, false]");
+        assert_eq!(global(&interp, "code"), Value::Integer(404));
+        assert_eq!(global(&interp, "fallback"), Value::Integer(0));
+
+        assert_eq!(
+            run("try:\n    throw 1\ncatch (e):\n    e.remove(\"kind\")\n\n")
+                .err()
+                .as_deref(),
+            Some("Method 'remove' not found")
+        );
+    }
+
+    #[test]
+    fn caught_errors_carry_kind_and_span() {
+        let src = r#"
+kind : null
+span : null
+xs : [1]
+try:
+    x : xs[4]
+catch (e):
+    kind : e.kind
+    span : e.span
+
+"#;
+        let interp = run(src).unwrap();
+        let start = src.find("xs[4]").unwrap() as i64;
+        assert_eq!(global(&interp, "kind"), Value::String("index".to_string()));
+        assert_eq!(
+            global(&interp, "span"),
+            Value::object(IndexMap::from([
+                ("start".to_string(), Value::Integer(start)),
+                ("end".to_string(), Value::Integer(start + 5)),
+            ]))
+        );
+
+        let interp = run(r#"
+kind : null
+try:
+    fs.read("/definitely/not/here.txt")
+catch (e):
+    kind : e.kind
+
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "kind"), Value::String("io".to_string()));
+    }
+
+    #[test]
+    fn rethrow_keeps_the_original_error() {
+        let src = "try:\n    missing\ncatch (e):\n    throw e\n";
+        let err = try_run(src).err().unwrap();
+        assert_eq!(err.kind(), "name");
+        assert_eq!(err.message(), "Variable 'missing' not found");
+        let span = err.span().unwrap();
+        assert_eq!(&src[span.start..span.end], "missing");
+    }
+
+    #[test]
+    fn finally_always_runs() {
+        let interp = run(r#"
+runs : 0
+fn attempt(fail):
+    try:
+        x : fail ? missing : 1
+        return "ok"
+    catch (e):
+        return "caught"
+    finally:
+        global runs : runs + 1
+
+
+first : attempt(false)
+second : attempt(true)
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "first"), Value::String("ok".to_string()));
+        assert_eq!(
+            global(&interp, "second"),
+            Value::String("caught".to_string())
+        );
+        assert_eq!(global(&interp, "runs"), Value::Integer(2));
+
+        let err =
+            run("try:\n    throw \"inner\"\ncatch (e):\n    throw e\nfinally:\n    done : true\n")
+                .err();
+        assert_eq!(err.as_deref(), Some("inner"));
+    }
+
+    #[test]
+    fn regex_match_operators() {
+        let interp = run(r#"
+s : "Order 66, then 12"
+found : s ~ /\d+/
+missed : s !~ /order/i
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "found"), Value::Boolean(true));
+        assert_eq!(global(&interp, "missed"), Value::Boolean(false));
+    }
+
+    #[test]
+    fn regex_match_and_match_all() {
+        let interp = run(r#"
+s : "Order 66, then 12"
+first : s.match(/(\d)(\d)/)
+named : s.match(/(?P<tens>\d)(?P<ones>\d)/)
+all : s.match_all(/\d+/)
+none : s.match(/xyz/)
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "first"), strings(&["66", "6", "6"]));
+        let Value::Object(named) = global(&interp, "named") else {
+            panic!("named groups should give an object");
+        };
+        assert_eq!(named.borrow()["tens"], Value::String("6".to_string()));
+        assert_eq!(
+            global(&interp, "all"),
+            Value::array(vec![strings(&["66"]), strings(&["12"])])
+        );
+        assert_eq!(global(&interp, "none"), Value::Null);
+    }
+
+    #[test]
+    fn regex_replace_and_split() {
+        let interp = run(r#"
+s : "Order 66, then 12"
+swapped : s.replace_all(/(\d)(\d)/, "$2$1")
+once : s.replace(/\d+/, "N")
+parts : "a, b,c".split(/,\s*/)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "swapped"), "Order 66, then 21");
+        assert_eq!(shown(&interp, "once"), "Order N, then 12");
+        assert_eq!(global(&interp, "parts"), strings(&["a", "b", "c"]));
+    }
+
+    #[test]
+    fn regex_errors() {
+        assert_eq!(
+            run("x : 1 ~ /1/").err().as_deref(),
+            Some("Regex matching needs a String on the left and a Regex on the right")
+        );
+        assert_eq!(
+            run("x : /(a/").err().as_deref(),
+            Some("Invalid regex /(a/: unclosed group")
+        );
+    }
+
+    #[test]
+    fn user_functions_shadow_builtins() {
+        let interp = run(r#"
+fn copy(x):
+    return 42
+
+fn str(x):
+    return "custom"
+
+fn outer():
+    len : fn(x): -1
+    return len([1, 2])
+
+results : [copy(1), str(2), outer(), len([1, 2])]
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "results"), "[42, custom, -1, 2]");
+    }
+
+    #[test]
+    fn type_and_str_builtins() {
+        let interp = run(r#"
+kinds : [type(1), type(1.5), type("s"), type([]), type(/a/), type(null)]
+text : str(12) + str(true)
+"#)
+        .unwrap();
+        assert_eq!(
+            global(&interp, "kinds"),
+            strings(&["Int", "Float", "String", "Array", "Regex", "Null"])
+        );
+        assert_eq!(shown(&interp, "text"), "12true");
+    }
+
+    #[test]
+    fn int_float_and_bool_builtins() {
+        let interp = run(r#"
+ints : [int(" 42 "), int(3.9), int(-3.9), int(true)]
+floats : [float("2.5"), float(2)]
+truthy : [bool(0), bool("x")]
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "ints"), ints(&[42, 3, -3, 1]));
+        assert_eq!(
+            global(&interp, "floats"),
+            Value::array(vec![Value::Float(2.5), Value::Float(2.0)])
+        );
+        assert_eq!(
+            global(&interp, "truthy"),
+            Value::array(vec![Value::Boolean(false), Value::Boolean(true)])
+        );
+    }
+
+    #[test]
+    fn parse_int_takes_a_radix() {
+        let interp =
+            run("parsed : [parse_int(\"ff\", 16), parse_int(\"-101\", 2), parse_int(\"12\")]\n")
+                .unwrap();
+        assert_eq!(global(&interp, "parsed"), ints(&[255, -5, 12]));
+    }
+
+    #[test]
+    fn conversion_errors() {
+        for (src, message) in [
+            ("int(\"3.5\")", "Cannot convert '3.5' to Int"),
+            ("int([1])", "Cannot convert Array to Int"),
+            ("float(\"x\")", "Cannot convert 'x' to Float"),
+            (
+                "parse_int(\"z\", 40)",
+                "parse_int() radix must be between 2 and 36, got 40",
+            ),
+            (
+                "parse_int(\"12\", 2)",
+                "Cannot parse '12' as an Int in base 2",
+            ),
+            ("int(float(\"inf\"))", "Cannot convert inf to Int"),
+        ] {
+            let err = try_run(&format!("x : {}", src)).err().unwrap();
+            assert_eq!(err.kind(), "type", "{}", src);
+            assert_eq!(err.message(), message);
+        }
+    }
+
+    #[test]
+    fn string_length_and_search_count_characters() {
+        let interp = run(r#"
+s : " Héllo "
+t : s.trim()
+length : [s.length, len(s), t.index_of("l"), t.index_of("z")]
+checks : [t.starts_with("Hé"), t.ends_with("lo"), t.contains("ll"), t.contains(/L/)]
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "length"), ints(&[7, 7, 2, -1]));
+        assert_eq!(shown(&interp, "checks"), "[true, true, true, false]");
+    }
+
+    #[test]
+    fn string_case_and_trimming() {
+        let interp = run(r#"
+s : " Héllo "
+t : s.trim()
+changed : [t.upper(), t.lower(), s.trim_start(), s.trim_end()]
+"#)
+        .unwrap();
+        assert_eq!(
+            global(&interp, "changed"),
+            strings(&["HÉLLO", "héllo", "Héllo ", " Héllo"])
+        );
+    }
+
+    #[test]
+    fn string_slice_pad_and_repeat() {
+        let interp = run(r#"
+t : "Héllo"
+parts : [t.slice(1, 3), t.slice(-2), t.slice(3, 1), "ab".repeat(2)]
+padded : ["5".pad_start(3, "0"), "ab".pad_end(5, "xy"), "abc".pad_start(2)]
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "parts"), strings(&["él", "lo", "", "abab"]));
+        assert_eq!(global(&interp, "padded"), strings(&["005", "abxyx", "abc"]));
+    }
+
+    #[test]
+    fn strings_split_into_parts() {
+        let interp = run(r#"
+chars : "Héllo".chars()
+lines : "a\nb\r\nc".lines()
+bytes : "é".bytes()
+joined : ["a", 1, true].join(", ")
+"#)
+        .unwrap();
+        assert_eq!(
+            global(&interp, "chars"),
+            strings(&["H", "é", "l", "l", "o"])
+        );
+        assert_eq!(global(&interp, "lines"), strings(&["a", "b", "c"]));
+        assert_eq!(global(&interp, "bytes"), ints(&[195, 169]));
+        assert_eq!(shown(&interp, "joined"), "a, 1, true");
+    }
+
+    #[test]
+    fn string_method_errors() {
+        assert_eq!(
+            run("x : \"a\".repeat(-1)").err().as_deref(),
+            Some("repeat() count can't be negative, got -1")
+        );
+        assert_eq!(
+            run("x : \"a\".upper(1)").err().as_deref(),
+            Some("upper() takes no arguments")
+        );
+        for src in [
+            "\"ab\".repeat(9223372036854775807)",
+            "\"ab\".repeat(2000000000)",
+            "\"a\".pad_start(9223372036854775807)",
+            "\"a\".pad_end(2000000000, \"é\")",
+        ] {
+            let err = try_run(&format!("x : {}", src)).err().unwrap();
+            assert_eq!(err.kind(), "runtime", "{}", src);
+            assert!(
+                err.message().ends_with("result would be too long"),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn ranges_iterate_with_bounds_and_steps() {
+        let interp = run(r#"
+fn items(r):
+    out : ""
+    for i in r:
+        out +: str(i) + " "
+    return out
+
+exclusive : items(0..4)
+inclusive : items(0..=4)
+stepped : items(0..10 step 3)
+down : items(5..=1 step -2)
+empty : [items(3..3), items(4..0), items(0..4 step -1)]
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "exclusive"), "0 1 2 3 ");
+        assert_eq!(shown(&interp, "inclusive"), "0 1 2 3 4 ");
+        assert_eq!(shown(&interp, "stepped"), "0 3 6 9 ");
+        assert_eq!(shown(&interp, "down"), "5 3 1 ");
+        assert_eq!(global(&interp, "empty"), strings(&["", "", ""]));
+    }
+
+    #[test]
+    fn range_length_and_has() {
+        let interp = run(r#"
+evens : 0..10 step 2
+whole : -9223372036854775808..=9223372036854775807
+none : 3..3
+lengths : [evens.length, len(0..=4), none.length, whole.length, len(whole)]
+has : [evens.has(4), evens.has(5), evens.has(10), whole.has(9223372036854775807)]
+"#)
+        .unwrap();
+        assert_eq!(
+            shown(&interp, "lengths"),
+            "[5, 5, 0, 18446744073709551616, 18446744073709551616]"
+        );
+        assert_eq!(shown(&interp, "has"), "[true, false, false, true]");
+    }
+
+    #[test]
+    fn ranges_slice_arrays_and_strings() {
+        let interp = run(r#"
+nums : [10, 20, 30, 40]
+middle : nums[1..3]
+every_other : nums[0..4 step 2]
+reversed : nums[3..=0 step -1]
+text : "hello"[1..=3]
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "middle"), ints(&[20, 30]));
+        assert_eq!(global(&interp, "every_other"), ints(&[10, 30]));
+        assert_eq!(global(&interp, "reversed"), ints(&[40, 30, 20, 10]));
+        assert_eq!(shown(&interp, "text"), "ell");
+    }
+
+    #[test]
+    fn map_filter_and_reduce() {
+        let interp = run(r#"
+nums : [3, 1, 4, 1, 5]
+doubled : nums.map(fn(x): x * 2)
+indexed : nums.map(fn(x, i): x * i)
+odd : nums.filter(fn(x): x % 2 == 1)
+total : nums.reduce(fn(acc, x): acc + x)
+product : [].reduce(fn(acc, x): acc * x, 1)
+each_result : nums.each(fn(x): x)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "doubled"), "[6, 2, 8, 2, 10]");
+        assert_eq!(shown(&interp, "indexed"), "[0, 1, 8, 3, 20]");
+        assert_eq!(shown(&interp, "odd"), "[3, 1, 1, 5]");
+        assert_eq!(shown(&interp, "total"), "14");
+        assert_eq!(shown(&interp, "product"), "1");
+        assert_eq!(shown(&interp, "each_result"), "null");
+    }
+
+    #[test]
+    fn find_any_and_all() {
+        let interp = run(r#"
+nums : [3, 1, 4, 1, 5]
+found : [nums.find(fn(x): x > 3), nums.find(fn(x): x > 9)]
+checks : [nums.any(fn(x): x > 4), nums.all(fn(x): x > 1), [].all(fn(x): false)]
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "found"), "[4, null]");
+        assert_eq!(shown(&interp, "checks"), "[true, false, true]");
+    }
+
+    #[test]
+    fn sort_by_comparator_key_or_order() {
+        let interp = run(r#"
+nums : [3, 1, 4, 1, 5]
+by_comparator : nums.sort(fn(a, b): b - a)
+by_key : ["pear", "fig", "apple"].sort(fn(w): w.length)
+by_order : nums.sort("0-9")
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "by_comparator"), "[5, 4, 3, 1, 1]");
+        assert_eq!(shown(&interp, "by_key"), "[fig, pear, apple]");
+        assert_eq!(shown(&interp, "by_order"), "[1, 1, 3, 4, 5]");
+    }
+
+    #[test]
+    fn arrays_reshape() {
+        let interp = run(r#"
+nums : [3, 1, 4, 1, 5]
+words : ["pear", "fig", "apple"]
+flat : [1, 2].flat_map(fn(x): [x, x * 10])
+zipped : words.zip(nums)
+numbered : words.enumerate()
+chunks : nums.chunk(2)
+unique : nums.unique()
+groups : nums.group_by(fn(x): x % 2)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "flat"), "[1, 10, 2, 20]");
+        assert_eq!(
+            shown(&interp, "zipped"),
+            "[[pear, 3], [fig, 1], [apple, 4]]"
+        );
+        assert_eq!(
+            shown(&interp, "numbered"),
+            "[[0, pear], [1, fig], [2, apple]]"
+        );
+        assert_eq!(shown(&interp, "chunks"), "[[3, 1], [4, 1], [5]]");
+        assert_eq!(shown(&interp, "unique"), "[3, 1, 4, 5]");
+        assert_eq!(shown(&interp, "groups"), "{1: [3, 1, 1, 5], 0: [4]}");
+    }
+
+    #[test]
+    fn array_method_errors() {
+        assert_eq!(
+            run("x : [].reduce(fn(a, b): a + b)").err().as_deref(),
+            Some("reduce() of an empty array needs an initial value")
+        );
+        assert_eq!(
+            run("x : [1].map(2)").err().as_deref(),
+            Some("map() requires a function, got Int")
+        );
+        assert_eq!(
+            run("x : [1, 2].sort(fn(a, b): \"no\")").err().as_deref(),
+            Some("sort() comparator must return a number, got String")
+        );
+        assert_eq!(
+            run("x : [1].chunk(0)").err().as_deref(),
+            Some("chunk() size must be a positive Int, got 0")
+        );
+    }
+
+    #[test]
+    fn nested_assignment_updates_shared_values() {
+        let interp = run(r#"
+inner :
+- c: 0
+a :
+- b: inner
+a.b.c : 1
+first :
+- name: "a"
+rows : [first, [1, 2]]
+i : 0
+rows[i].name : "x"
+rows[1][0] +: 10
+fn tag(o):
+    o.tagged : true
+tag(first)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "inner"), "{c: 1}");
+        assert_eq!(shown(&interp, "first"), "{name: x, tagged: true}");
+        assert_eq!(shown(&interp, "rows"), "[{name: x, tagged: true}, [11, 2]]");
+    }
+
+    #[test]
+    fn copy_shares_nested_values_and_deep_copy_does_not() {
+        let interp = run(r#"
+first :
+- name: "a"
+rows : [first, [1, 2]]
+shallow : copy(rows)
+shallow[1][1] : 20
+deep : deep_copy(rows)
+deep[1][1] : 30
+"#)
+        .unwrap();
+        let Value::Object(first) = global(&interp, "first") else {
+            panic!("first should be an object");
+        };
+        let items = |name: &str| match global(&interp, name) {
+            Value::Array(items) => items.borrow().clone(),
+            other => panic!("{} should be an array, got {}", name, other),
+        };
+        assert_eq!(items("rows")[1].to_string(), "[1, 20]");
+        assert_eq!(items("deep")[1].to_string(), "[1, 30]");
+        match (&items("rows")[0], &items("shallow")[0], &items("deep")[0]) {
+            (Value::Object(row), Value::Object(shallow), Value::Object(deep)) => {
+                assert!(Rc::ptr_eq(row, &first) && Rc::ptr_eq(shallow, &first));
+                assert!(!Rc::ptr_eq(deep, &first));
+            }
+            other => panic!("rows should hold objects, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_writes_are_checked_against_declared_types() {
+        let err = run("List(Int) nums : [1, 2]\nnums[0] : \"s\"\n").err();
+        assert_eq!(
+            err.as_deref(),
+            Some("Type mismatch for 'nums': expected List(Int), got Array with String at index 0")
+        );
+    }
+
+    #[test]
+    fn object_methods() {
+        let interp = run(r#"
+point :
+- x: 1
+- y: 2
+has : [point.has("x"), point.has("z")]
+got : [point.get("x"), point.get("z"), point.get("z", 0)]
+removed : [point.remove("y"), point.remove("y")]
+keys : point.keys()
+values : point.values()
+entries : point.entries()
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "has"), "[true, false]");
+        assert_eq!(shown(&interp, "got"), "[1, null, 0]");
+        assert_eq!(shown(&interp, "removed"), "[2, null]");
+        assert_eq!(shown(&interp, "keys"), "[x]");
+        assert_eq!(shown(&interp, "values"), "[1]");
+        assert_eq!(shown(&interp, "entries"), "[[x, 1]]");
+    }
+
+    #[test]
+    fn objects_keep_insertion_order() {
+        let interp = run(r#"
+obj :
+- zeta: 1
+- alpha: 2
+- mid: 3
+obj.beta : 4
+obj.zeta : 5
+obj.remove("alpha")
+visited : ""
+for k, v in obj:
+    visited +: k + "=" + str(v) + " "
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "obj"), "{zeta: 5, mid: 3, beta: 4}");
+        assert_eq!(shown(&interp, "visited"), "zeta=5 mid=3 beta=4 ");
+    }
+
+    #[test]
+    fn merge_keeps_existing_positions() {
+        let interp = run(r#"
+obj :
+- zeta: 5
+- mid: 3
+extra :
+- mid: 30
+- last: 6
+merged : obj.merge(extra)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "merged"), "{zeta: 5, mid: 30, last: 6}");
+        assert_eq!(shown(&interp, "obj"), "{zeta: 5, mid: 3}");
+    }
+
+    #[test]
+    fn equality_is_structural() {
+        let interp = run(r#"
+a :
+- x: 1
+- y: [1, 2]
+b :
+- y: [1, 2]
+- x: 1.0
+c :
+- x: 1
+- y: [1, 2]
+- z: null
+same : [[1, 2] == [1, 2], a == b, a == c, a is b, 1 == 1.0, [1] != [1, 0]]
+"#)
+        .unwrap();
+        assert_eq!(
+            shown(&interp, "same"),
+            "[true, true, false, true, true, true]"
+        );
+    }
+
+    #[test]
+    fn identity_compares_references() {
+        let interp = run(r#"
+a :
+- x: 1
+b :
+- x: 1
+identity : [1 === 1.0, 1 === 1, a === a, a === copy(a), a !== b]
+"#)
+        .unwrap();
+        assert_eq!(
+            shown(&interp, "identity"),
+            "[false, true, true, false, true]"
+        );
+    }
+
+    #[test]
+    fn has_unique_and_sort_use_structural_comparison() {
+        let interp = run(r#"
+found : [[1, 2], [3]].has([3])
+unique : [[1], [1], [2], [1.0]].unique()
+mixed : [[1], "b", 2.5, null, true, "a", 1].sort(fn(v): v)
+keyed : [[2, 1], [1, 9], [1, 2]].sort(fn(p): p)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "found"), "true");
+        assert_eq!(shown(&interp, "unique"), "[[1], [2]]");
+        assert_eq!(shown(&interp, "mixed"), "[null, true, 1, 2.5, a, b, [1]]");
+        assert_eq!(shown(&interp, "keyed"), "[[1, 2], [1, 9], [2, 1]]");
+    }
+
+    #[test]
+    fn cyclic_values_compare_without_overflowing() {
+        let interp = run(r#"
+a : [1]
+a[0] : a
+b : [1]
+b[0] : b
+c : [2, 1]
+c[1] : c
+o :
+- me: null
+o.me : o
+p :
+- me: null
+p.me : p
+same : [a == b, o == p, a == c, [a].has(b)]
+unique : [a, b, c].unique()
+sorted : [c, a].sort(fn(v): v)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "same"), "[true, true, false, true]");
+        assert_eq!(shown(&interp, "unique"), "[[[...]], [2, [...]]]");
+        assert_eq!(shown(&interp, "sorted"), "[[2, [...]], [[...]]]");
+    }
+
+    #[test]
+    fn integers_promote_past_i64() {
+        let interp = run(r#"
+max : 9223372036854775807
+big : max + 1
+back : big - 1
+min : -9223372036854775808
+negated : -min
+squared : big * big
+count : max
+count++
+parsed : [int("123456789012345678901234567890"), parse_int("ffffffffffffffffff", 16)]
+kind : type(big)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "big"), "9223372036854775808");
+        assert_eq!(global(&interp, "back"), Value::Integer(i64::MAX));
+        assert_eq!(global(&interp, "min"), Value::Integer(i64::MIN));
+        assert_eq!(shown(&interp, "negated"), "9223372036854775808");
+        assert_eq!(
+            shown(&interp, "squared"),
+            "85070591730234615865843651857942052864"
+        );
+        assert_eq!(shown(&interp, "count"), "9223372036854775808");
+        assert_eq!(
+            shown(&interp, "parsed"),
+            "[123456789012345678901234567890, 4722366482869645213695]"
+        );
+        assert_eq!(shown(&interp, "kind"), "Int");
+    }
+
+    #[test]
+    fn integer_powers_are_exact() {
+        let interp = run(r#"
+power : 2 ^ 100
+small_power : 3 ^ 4
+inverse : 2 ^ -1
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "power"), "1267650600228229401496703205376");
+        assert_eq!(global(&interp, "small_power"), Value::Integer(81));
+        assert_eq!(global(&interp, "inverse"), Value::Float(0.5));
+    }
+
+    #[test]
+    fn division_and_floor_modulo() {
+        let interp = run(r#"
+halves : [7 / 2, 8 / 2, 7 div 2, -7 div 2, 7.5 div 2]
+mods : [7 % 3, -7 % 3, 7 % -3, -7.5 % 2]
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "halves"), "[3.5, 4, 3, -4, 3]");
+        assert_eq!(shown(&interp, "mods"), "[1, 2, -2, 0.5]");
+    }
+
+    #[test]
+    fn decimals_are_exact() {
+        let interp = run(r#"
+Decimal price : 19.99
+total : price * 3 + decimal("0.03")
+split : decimal("10") / 4
+tenth : decimal(0.1) + decimal(0.2)
+ordered : [decimal("2.5") < 3, 1 == decimal("1.00"), 9223372036854775808 == 2.0 ^ 63]
+kind : type(price)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "total"), "60.00");
+        assert_eq!(shown(&interp, "split"), "2.50");
+        assert_eq!(shown(&interp, "tenth"), "0.3");
+        assert_eq!(shown(&interp, "ordered"), "[true, true, true]");
+        assert_eq!(shown(&interp, "kind"), "Decimal");
+    }
+
+    #[test]
+    fn numeric_errors() {
+        for (src, message) in [
+            ("1 % 0", "Modulo by zero"),
+            ("1 div 0", "Division by zero"),
+            ("decimal(\"1\") / 0", "Division by zero"),
+            ("decimal(\"1.5\") + 1.5", "Cannot mix Decimal and Float"),
+            ("2 ^ 10000000", "2 ^ 10000000 is too large"),
+        ] {
+            assert_eq!(
+                run(&format!("x : {}", src)).err().as_deref(),
+                Some(message),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn and_or_short_circuit_and_return_operands() {
+        let interp = run(r#"
+x : null
+calls : 0
+fn touch(v):
+    global calls : calls + 1
+    return v
+
+guarded : x != null and x.length > 0
+name : "" or "default"
+kept : "ann" or touch("never")
+first_falsy : 1 and 0 and touch(2)
+last : 1 and "yes"
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "guarded"), Value::Boolean(false));
+        assert_eq!(shown(&interp, "name"), "default");
+        assert_eq!(shown(&interp, "kept"), "ann");
+        assert_eq!(global(&interp, "first_falsy"), Value::Integer(0));
+        assert_eq!(shown(&interp, "last"), "yes");
+        assert_eq!(global(&interp, "calls"), Value::Integer(0));
+    }
+
+    #[test]
+    fn coalesce_only_replaces_null() {
+        let interp = run(r#"
+calls : 0
+fn touch(v):
+    global calls : calls + 1
+    return v
+
+fallback : null ?? "none"
+zero : 0 ?? touch(5)
+"#)
+        .unwrap();
+        assert_eq!(shown(&interp, "fallback"), "none");
+        assert_eq!(global(&interp, "zero"), Value::Integer(0));
+        assert_eq!(global(&interp, "calls"), Value::Integer(0));
+    }
+
+    #[test]
+    fn optional_access_gives_null_for_missing_properties() {
+        let interp = run(r#"
+user :
+- profile: null
+- tags: [1]
+city : user?.profile?.city
+missing : user?.address ?? "unknown"
+tags : user?.tags?.length
+five : 5
+text : "s"
+list : [1]
+others : [five?.b, text?.nope, list?.size, text?.length]
+"#)
+        .unwrap();
+        assert_eq!(global(&interp, "city"), Value::Null);
+        assert_eq!(shown(&interp, "missing"), "unknown");
+        assert_eq!(global(&interp, "tags"), Value::Integer(1));
+        assert_eq!(shown(&interp, "others"), "[null, null, null, 1]");
+
+        assert_eq!(
+            run("user :\n- name: \"a\"\nx : user.address\n")
+                .err()
+                .as_deref(),
+            Some("Property 'address' not found")
+        );
+    }
 }
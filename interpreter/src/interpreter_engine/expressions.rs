@@ -1,44 +1,61 @@
 use crate::ast::*;
-use crate::features::Value;
+use crate::error::FenicsError;
+use crate::features::{Environment, Range, Value};
 use crate::interpreter::Interpreter;
 use crate::utils::string_interpolation::evaluate_string_parts;
 
 impl Interpreter {
-    pub fn evaluate_expression(&mut self, expr: &Expression) -> Result<Value, String> {
+    pub fn evaluate_expression(&mut self, expr: &Expression) -> Result<Value, FenicsError> {
+        self.evaluate_expression_kind(&expr.kind, expr.span)
+            .map_err(|e| e.with_span(expr.span))
+    }
+
+    fn evaluate_expression_kind(
+        &mut self,
+        expr: &ExpressionKind,
+        span: Span,
+    ) -> Result<Value, FenicsError> {
         match expr {
-            Expression::Literal(lit) => self.evaluate_literal(lit),
+            ExpressionKind::Literal(lit) => self.evaluate_literal(lit),
 
-            Expression::Identifier(name) => self.get_variable(name),
+            ExpressionKind::Identifier(name) => self.get_variable(name),
 
-            Expression::EphemeralVar(name) => self
-                .ephemerals
-                .get(name)
-                .cloned()
-                .ok_or_else(|| format!("Ephemeral variable '{}' not found", name)),
+            ExpressionKind::EphemeralVar(name) => {
+                self.ephemerals.get(name).cloned().ok_or_else(|| {
+                    FenicsError::name(format!("Ephemeral variable '{}' not found", name))
+                })
+            }
 
-            Expression::FunctionCall { name, args } => self.call_function(name, args),
+            ExpressionKind::FunctionCall { name, args } => self.call_function(name, args, span),
 
-            Expression::MethodCall {
+            ExpressionKind::MethodCall {
                 object,
                 method,
                 args,
             } => {
                 let obj_value = self.evaluate_expression(object)?;
-                self.call_method(&obj_value, method, args)
+                self.call_method(&obj_value, method, args, span)
             }
 
-            Expression::PropertyAccess { object, property } => {
+            ExpressionKind::PropertyAccess { object, property } => {
                 let obj_value = self.evaluate_expression(object)?;
                 self.get_property(&obj_value, property)
             }
 
-            Expression::BracketAccess { object, index } => {
+            ExpressionKind::OptionalPropertyAccess { object, property } => {
+                let obj_value = self.evaluate_expression(object)?;
+                Ok(self
+                    .find_property(&obj_value, property)?
+                    .unwrap_or(Value::Null))
+            }
+
+            ExpressionKind::BracketAccess { object, index } => {
                 let obj_value = self.evaluate_expression(object)?;
                 let index_value = self.evaluate_expression(index)?;
                 self.get_bracket_access(&obj_value, &index_value)
             }
 
-            Expression::BinaryOp { left, op, right } => {
+            ExpressionKind::BinaryOp { left, op, right } => {
                 // Handle assignment operations specially
                 match op {
                     BinaryOperator::Assign
@@ -50,6 +67,21 @@ impl Interpreter {
                         let right_val = self.evaluate_expression(right)?;
                         self.assign_value(left, op, right_val)
                     }
+                    // The right operand only runs when the left one doesn't
+                    // decide the result
+                    BinaryOperator::And | BinaryOperator::Or | BinaryOperator::Coalesce => {
+                        let left_val = self.evaluate_expression(left)?;
+                        let decided = match op {
+                            BinaryOperator::And => !left_val.is_truthy(),
+                            BinaryOperator::Or => left_val.is_truthy(),
+                            _ => !matches!(left_val, Value::Null),
+                        };
+                        if decided {
+                            Ok(left_val)
+                        } else {
+                            self.evaluate_expression(right)
+                        }
+                    }
                     _ => {
                         let left_val = self.evaluate_expression(left)?;
                         let right_val = self.evaluate_expression(right)?;
@@ -58,7 +90,7 @@ impl Interpreter {
                 }
             }
 
-            Expression::UnaryOp { op, operand } => match op {
+            ExpressionKind::UnaryOp { op, operand } => match op {
                 UnaryOperator::Increment | UnaryOperator::Decrement => {
                     self.increment_decrement(operand, op)
                 }
@@ -68,12 +100,12 @@ impl Interpreter {
                 }
             },
 
-            Expression::TernaryThen {
+            ExpressionKind::TernaryThen {
                 condition,
                 true_expr,
                 false_expr,
             }
-            | Expression::TernaryQuestion {
+            | ExpressionKind::TernaryQuestion {
                 condition,
                 true_expr,
                 false_expr,
@@ -86,12 +118,12 @@ impl Interpreter {
                 }
             }
 
-            Expression::StringInterpolation { parts } => {
+            ExpressionKind::StringInterpolation { parts } => {
                 let rendered =
                     evaluate_string_parts(parts, |expr| match self.evaluate_expression(expr) {
                         Ok(val) => Ok(val.to_string()),
                         Err(e) => {
-                            if let Expression::Identifier(name) = expr {
+                            if let ExpressionKind::Identifier(name) = &expr.kind {
                                 if let Some(ev) = self.ephemerals.get(name) {
                                     return Ok(ev.to_string());
                                 }
@@ -102,6 +134,45 @@ impl Interpreter {
 
                 Ok(Value::String(rendered))
             }
+
+            ExpressionKind::Lambda {
+                parameters,
+                return_type,
+                body,
+            } => Ok(Value::Function {
+                name: None,
+                params: parameters.clone(),
+                return_type: return_type.clone(),
+                body: body.clone(),
+                env: Environment(self.locals.clone()),
+                module: self.module.clone(),
+            }),
+
+            ExpressionKind::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                let start = self.evaluate_range_bound(start, "start")?;
+                let end = self.evaluate_range_bound(end, "end")?;
+                let step = match step {
+                    Some(step) => self.evaluate_range_bound(step, "step")?,
+                    None => 1,
+                };
+                Ok(Value::Range(Range::new(start, end, step, *inclusive)?))
+            }
+        }
+    }
+
+    fn evaluate_range_bound(&mut self, expr: &Expression, what: &str) -> Result<i64, FenicsError> {
+        match self.evaluate_expression(expr)? {
+            Value::Integer(i) => Ok(i),
+            other => Err(FenicsError::type_error(format!(
+                "Range {} must be an Int, got {}",
+                what,
+                other.type_name()
+            ))),
         }
     }
 }
@@ -1,30 +1,32 @@
 use crate::ast::Literal;
+use crate::error::FenicsError;
 use crate::features::Value;
 use crate::interpreter::Interpreter;
-use std::collections::HashMap;
+use indexmap::IndexMap;
 
 impl Interpreter {
-    pub fn evaluate_literal(&mut self, lit: &Literal) -> Result<Value, String> {
+    pub fn evaluate_literal(&mut self, lit: &Literal) -> Result<Value, FenicsError> {
         match lit {
             Literal::Integer(i) => Ok(Value::Integer(*i)),
+            Literal::BigInt(i) => Ok(Value::BigInt(i.clone())),
             Literal::Float(f) => Ok(Value::Float(*f)),
             Literal::String(s) => Ok(Value::String(s.clone())),
             Literal::Boolean(b) => Ok(Value::Boolean(*b)),
             Literal::Null | Literal::Undefined | Literal::Nil => Ok(Value::Null),
-            Literal::Regex(_) => Err("Regex not yet supported".to_string()),
+            Literal::Regex(regex) => Ok(Value::Regex(regex.clone())),
             Literal::Array(arr) => {
                 let mut values = Vec::new();
                 for expr in arr {
                     values.push(self.evaluate_expression(expr)?);
                 }
-                Ok(Value::Array(values))
+                Ok(Value::array(values))
             }
             Literal::Object(obj) => {
-                let mut map = HashMap::new();
+                let mut map = IndexMap::new();
                 for (k, v) in obj {
                     map.insert(k.clone(), self.evaluate_expression(v)?);
                 }
-                Ok(Value::Object(map))
+                Ok(Value::object(map))
             }
         }
     }
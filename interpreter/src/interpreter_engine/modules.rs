@@ -1,7 +1,8 @@
+use crate::error::FenicsError;
 use crate::interpreter::Interpreter;
 
 impl Interpreter {
-    pub fn resolve_import_path(&self, path: &str) -> Result<String, String> {
+    pub fn resolve_import_path(&self, path: &str) -> Result<String, FenicsError> {
         // If path contains slashes or backslashes, treat as literal path
         if path.contains('/') || path.contains('\\') {
             return Ok(path.to_string());
@@ -22,9 +23,9 @@ impl Interpreter {
             }
         }
 
-        Err(format!(
+        Err(FenicsError::io(format!(
             "Module '{}' not found in search paths: ./libs/, ../libs/, ./samples/, ../samples/, or current directory",
             path
-        ))
+        )))
     }
 }
@@ -1,6 +1,9 @@
 use crate::ast::{BinaryOperator, UnaryOperator};
+use crate::error::FenicsError;
+use crate::features::numbers::{self, int_value};
 use crate::features::Value;
 use crate::interpreter::Interpreter;
+use num_bigint::BigInt;
 
 impl Interpreter {
     pub fn evaluate_binary_op(
@@ -8,123 +11,116 @@ impl Interpreter {
         left: &Value,
         op: &BinaryOperator,
         right: &Value,
-    ) -> Result<Value, String> {
+    ) -> Result<Value, FenicsError> {
         match op {
-            // Arithmetic
+            // Arithmetic, on Ints, Decimals and Floats; see `features::numbers`
             BinaryOperator::Add => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a + b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Float(*a as f64 + b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Float(a + *b as f64)),
                 (Value::String(a), Value::String(b)) => Ok(Value::String(format!("{}{}", a, b))),
-                _ => Err("Invalid types for addition".to_string()),
-            },
-            BinaryOperator::Subtract => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a - b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Float(*a as f64 - b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Float(a - *b as f64)),
-                _ => Err("Invalid types for subtraction".to_string()),
-            },
-            BinaryOperator::Multiply => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a * b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Float(*a as f64 * b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Float(a * *b as f64)),
-                _ => Err("Invalid types for multiplication".to_string()),
-            },
-            BinaryOperator::Divide => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => {
-                    if *b == 0 {
-                        Err("Division by zero".to_string())
-                    } else {
-                        Ok(Value::Integer(a / b))
-                    }
-                }
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Float(*a as f64 / b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Float(a / *b as f64)),
-                _ => Err("Invalid types for division".to_string()),
-            },
-            BinaryOperator::Modulo => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Integer(a % b)),
-                _ => Err("Modulo only supports integers".to_string()),
-            },
-            BinaryOperator::Power => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => {
-                    Ok(Value::Float((*a as f64).powf(*b as f64)))
-                }
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Float(a.powf(*b))),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Float((*a as f64).powf(*b))),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Float(a.powf(*b as f64))),
-                _ => Err("Invalid types for power".to_string()),
+                _ => numbers::arithmetic(op, left, right)
+                    .unwrap_or_else(|| Err(FenicsError::type_error("Invalid types for addition"))),
             },
+            BinaryOperator::Subtract
+            | BinaryOperator::Multiply
+            | BinaryOperator::Divide
+            | BinaryOperator::FloorDivide
+            | BinaryOperator::Modulo
+            | BinaryOperator::Power => numbers::arithmetic(op, left, right).unwrap_or_else(|| {
+                Err(FenicsError::type_error(format!(
+                    "Invalid types for {}",
+                    arithmetic_name(op)
+                )))
+            }),
 
             // Comparison
-            BinaryOperator::Equal => Ok(Value::Boolean(self.values_equal(left, right))),
-            BinaryOperator::NotEqual => Ok(Value::Boolean(!self.values_equal(left, right))),
-            BinaryOperator::LessThan => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a < b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a < b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean((*a as f64) < *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a < (*b as f64))),
-                _ => Err("Invalid types for comparison".to_string()),
-            },
-            BinaryOperator::GreaterThan => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a > b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a > b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean((*a as f64) > *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a > (*b as f64))),
-                _ => Err("Invalid types for comparison".to_string()),
-            },
-            BinaryOperator::LessThanOrEqual => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a <= b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a <= b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean((*a as f64) <= *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a <= (*b as f64))),
-                _ => Err("Invalid types for comparison".to_string()),
-            },
-            BinaryOperator::GreaterThanOrEqual => match (left, right) {
-                (Value::Integer(a), Value::Integer(b)) => Ok(Value::Boolean(a >= b)),
-                (Value::Float(a), Value::Float(b)) => Ok(Value::Boolean(a >= b)),
-                (Value::Integer(a), Value::Float(b)) => Ok(Value::Boolean((*a as f64) >= *b)),
-                (Value::Float(a), Value::Integer(b)) => Ok(Value::Boolean(*a >= (*b as f64))),
-                _ => Err("Invalid types for comparison".to_string()),
-            },
-            BinaryOperator::Is => Ok(Value::Boolean(self.values_equal(left, right))),
-            BinaryOperator::IsNot => Ok(Value::Boolean(!self.values_equal(left, right))),
-            BinaryOperator::Match | BinaryOperator::NotMatch => {
-                Err("Regex matching not yet implemented".to_string())
+            BinaryOperator::Equal => Ok(Value::Boolean(left.equals(right))),
+            BinaryOperator::NotEqual => Ok(Value::Boolean(!left.equals(right))),
+            BinaryOperator::Identical => Ok(Value::Boolean(left.is_identical(right))),
+            BinaryOperator::NotIdentical => Ok(Value::Boolean(!left.is_identical(right))),
+            BinaryOperator::LessThan
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessThanOrEqual
+            | BinaryOperator::GreaterThanOrEqual => {
+                if !(left.is_number() && right.is_number()) {
+                    return Err(FenicsError::type_error("Invalid types for comparison"));
+                }
+                // NaN is unordered, so every comparison with it is false
+                if left.is_nan() || right.is_nan() {
+                    return Ok(Value::Boolean(false));
+                }
+                let ordering = numbers::compare(left, right);
+                Ok(Value::Boolean(match op {
+                    BinaryOperator::LessThan => ordering.is_lt(),
+                    BinaryOperator::GreaterThan => ordering.is_gt(),
+                    BinaryOperator::LessThanOrEqual => ordering.is_le(),
+                    _ => ordering.is_ge(),
+                }))
             }
+            BinaryOperator::Is => Ok(Value::Boolean(left.equals(right))),
+            BinaryOperator::IsNot => Ok(Value::Boolean(!left.equals(right))),
+            BinaryOperator::Match | BinaryOperator::NotMatch => match (left, right) {
+                (Value::String(text), Value::Regex(regex)) => Ok(Value::Boolean(
+                    regex.is_match(text) == (*op == BinaryOperator::Match),
+                )),
+                _ => Err(FenicsError::type_error(
+                    "Regex matching needs a String on the left and a Regex on the right",
+                )),
+            },
 
             // Logical
-            BinaryOperator::And => Ok(Value::Boolean(left.is_truthy() && right.is_truthy())),
-            BinaryOperator::Or => Ok(Value::Boolean(left.is_truthy() || right.is_truthy())),
+            // `evaluate_expression` short-circuits these; both sides are
+            // known here, so only the choice of operand is left
+            BinaryOperator::And => Ok(if left.is_truthy() { right } else { left }.clone()),
+            BinaryOperator::Or => Ok(if left.is_truthy() { left } else { right }.clone()),
+            BinaryOperator::Coalesce => Ok(match left {
+                Value::Null => right.clone(),
+                _ => left.clone(),
+            }),
 
             // Assignments should not reach here
-            _ => Err("Invalid binary operator".to_string()),
-        }
-    }
-
-    pub fn values_equal(&self, left: &Value, right: &Value) -> bool {
-        match (left, right) {
-            (Value::Integer(a), Value::Integer(b)) => a == b,
-            (Value::Float(a), Value::Float(b)) => a == b,
-            (Value::Integer(a), Value::Float(b)) => *a as f64 == *b,
-            (Value::Float(a), Value::Integer(b)) => *a == *b as f64,
-            (Value::String(a), Value::String(b)) => a == b,
-            (Value::Boolean(a), Value::Boolean(b)) => a == b,
-            (Value::Null, Value::Null) => true,
-            _ => false,
+            _ => Err(FenicsError::runtime("Invalid binary operator")),
         }
     }
 
-    pub fn evaluate_unary_op(&self, op: &UnaryOperator, operand: &Value) -> Result<Value, String> {
+    pub fn evaluate_unary_op(
+        &self,
+        op: &UnaryOperator,
+        operand: &Value,
+    ) -> Result<Value, FenicsError> {
         match (op, operand) {
             (UnaryOperator::Not, val) => Ok(Value::Boolean(!val.is_truthy())),
-            (UnaryOperator::Negate, Value::Integer(i)) => Ok(Value::Integer(-i)),
+            (UnaryOperator::Negate, Value::Integer(i)) => Ok(i
+                .checked_neg()
+                .map(Value::Integer)
+                .unwrap_or_else(|| int_value(-BigInt::from(*i)))),
+            (UnaryOperator::Negate, Value::BigInt(i)) => Ok(int_value(-i)),
             (UnaryOperator::Negate, Value::Float(f)) => Ok(Value::Float(-f)),
-            _ => Err("Unary operation not supported".to_string()),
+            (UnaryOperator::Negate, Value::Decimal(d)) => Ok(Value::Decimal(-d)),
+            (UnaryOperator::Increment, val) if val.is_number() => {
+                self.evaluate_binary_op(val, &BinaryOperator::Add, &Value::Integer(1))
+            }
+            (UnaryOperator::Decrement, val) if val.is_number() => {
+                self.evaluate_binary_op(val, &BinaryOperator::Subtract, &Value::Integer(1))
+            }
+            (UnaryOperator::Negate, val) => Err(FenicsError::type_error(format!(
+                "Cannot negate a value of type {}",
+                val.type_name()
+            ))),
+            (UnaryOperator::Increment | UnaryOperator::Decrement, val) => {
+                Err(FenicsError::type_error(format!(
+                    "Increment/decrement only works on numbers, got {}",
+                    val.type_name()
+                )))
+            }
         }
     }
 }
+
+fn arithmetic_name(op: &BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Subtract => "subtraction",
+        BinaryOperator::Multiply => "multiplication",
+        BinaryOperator::Divide | BinaryOperator::FloorDivide => "division",
+        BinaryOperator::Modulo => "modulo",
+        _ => "power",
+    }
+}
@@ -1,50 +1,85 @@
 use crate::ast::*;
-use crate::features::Value;
-use crate::interpreter::Interpreter;
-use std::collections::HashMap;
+use crate::error::FenicsError;
+use crate::features::{Binding, Environment, SourceFile, Value};
+use crate::interpreter::{ControlFlow, Interpreter};
+use crate::utils::type_checker::conform;
+use indexmap::IndexMap;
 
 impl Interpreter {
-    pub fn execute_statement(&mut self, statement: &Statement) -> Result<Option<Value>, String> {
+    pub fn execute_statement(&mut self, statement: &Statement) -> Result<ControlFlow, FenicsError> {
+        self.execute_statement_kind(&statement.kind, statement.span)
+            .map_err(|e| e.with_span(statement.span))
+    }
+
+    fn execute_statement_kind(
+        &mut self,
+        statement: &StatementKind,
+        span: Span,
+    ) -> Result<ControlFlow, FenicsError> {
         match statement {
-            Statement::VariableDeclaration {
-                type_annotation: _,
-                is_const: _,
+            StatementKind::VariableDeclaration {
+                type_annotation,
+                is_const,
                 is_global,
                 name,
                 value,
             } => {
-                let val = self.evaluate_expression(value)?;
-                if *is_global || self.locals.is_empty() {
-                    self.globals.insert(name.clone(), val);
+                let mut val = self.evaluate_expression(value)?;
+                if let Some(ty) = type_annotation {
+                    val = conform(val, ty).map_err(|found| {
+                        FenicsError::type_error(format!(
+                            "Type mismatch for '{}': expected {}, got {}",
+                            name, ty, found
+                        ))
+                    })?;
+                    self.collection_types.register(name, &val, ty);
+                }
+                if type_annotation.is_none() && !*is_const && !*is_global {
+                    self.bind_variable(name, val)?;
                 } else {
-                    self.locals.last_mut().unwrap().insert(name.clone(), val);
+                    let binding = Binding {
+                        value: val,
+                        is_const: *is_const,
+                        type_annotation: type_annotation.clone(),
+                    };
+                    self.declare_variable(name, binding, *is_global)?;
                 }
-                Ok(None)
+                Ok(ControlFlow::Normal)
             }
 
-            Statement::FunctionDeclaration {
+            StatementKind::FunctionDeclaration {
                 name,
                 parameters,
-                return_type: _,
+                return_type,
                 body,
             } => {
+                // The function captures the frames it is declared in (which will
+                // also hold the function itself, so local recursion works)
                 let func = Value::Function {
+                    name: Some(name.clone()),
                     params: parameters.clone(),
+                    return_type: return_type.clone(),
                     body: body.clone(),
+                    env: Environment(self.locals.clone()),
+                    module: self.module.clone(),
                 };
-                self.globals.insert(name.clone(), func);
-                Ok(None)
+                self.declare_variable(name, Binding::mutable(func), false)?;
+                Ok(ControlFlow::Normal)
             }
 
-            Statement::Return(expr) => {
+            StatementKind::Return(expr) => {
                 if let Some(e) = expr {
-                    Ok(Some(self.evaluate_expression(e)?))
+                    Ok(ControlFlow::Return(self.evaluate_expression(e)?))
                 } else {
-                    Ok(Some(Value::Null))
+                    Ok(ControlFlow::Return(Value::Null))
                 }
             }
 
-            Statement::If {
+            StatementKind::Break(label) => Ok(ControlFlow::Break(label.clone(), span)),
+
+            StatementKind::Continue(label) => Ok(ControlFlow::Continue(label.clone(), span)),
+
+            StatementKind::If {
                 condition,
                 then_branch,
                 else_ifs,
@@ -53,36 +88,24 @@ impl Interpreter {
                 let cond_value = self.evaluate_expression(condition)?;
 
                 if cond_value.is_truthy() {
-                    for stmt in then_branch {
-                        if let Some(ret) = self.execute_statement(stmt)? {
-                            return Ok(Some(ret));
-                        }
-                    }
-                } else {
-                    for (else_if_cond, else_if_body) in else_ifs {
-                        let else_if_value = self.evaluate_expression(else_if_cond)?;
-                        if else_if_value.is_truthy() {
-                            for stmt in else_if_body {
-                                if let Some(ret) = self.execute_statement(stmt)? {
-                                    return Ok(Some(ret));
-                                }
-                            }
-                            return Ok(None);
-                        }
-                    }
+                    return self.execute_scoped_block(then_branch);
+                }
 
-                    if let Some(else_body) = else_branch {
-                        for stmt in else_body {
-                            if let Some(ret) = self.execute_statement(stmt)? {
-                                return Ok(Some(ret));
-                            }
-                        }
+                for (else_if_cond, else_if_body) in else_ifs {
+                    let else_if_value = self.evaluate_expression(else_if_cond)?;
+                    if else_if_value.is_truthy() {
+                        return self.execute_scoped_block(else_if_body);
                     }
                 }
-                Ok(None)
+
+                match else_branch {
+                    Some(else_body) => self.execute_scoped_block(else_body),
+                    None => Ok(ControlFlow::Normal),
+                }
             }
 
-            Statement::ForLoop {
+            StatementKind::ForLoop {
+                label,
                 key_var,
                 value_var,
                 iterable,
@@ -90,202 +113,246 @@ impl Interpreter {
             } => {
                 let iter_value = self.evaluate_expression(iterable)?;
 
-                match iter_value {
-                    Value::Array(arr) => {
-                        self.locals.push(HashMap::new());
-                        for (idx, val) in arr.iter().enumerate() {
-                            if let Some(key) = key_var {
-                                self.locals
-                                    .last_mut()
-                                    .unwrap()
-                                    .insert(key.clone(), Value::Integer(idx as i64));
-                            }
-                            self.locals
-                                .last_mut()
-                                .unwrap()
-                                .insert(value_var.clone(), val.clone());
-
-                            for stmt in body {
-                                if let Some(ret) = self.execute_statement(stmt)? {
-                                    self.locals.pop();
-                                    return Ok(Some(ret));
-                                }
-                            }
-                        }
-                        self.locals.pop();
+                // (key, value) pairs; ranges are produced lazily. Arrays and
+                // objects are iterated as they were when the loop started
+                let entries: Box<dyn Iterator<Item = (Value, Value)>> = match iter_value {
+                    Value::Array(arr) => Box::new(
+                        arr.borrow()
+                            .clone()
+                            .into_iter()
+                            .enumerate()
+                            .map(|(idx, val)| (Value::Integer(idx as i64), val)),
+                    ),
+                    Value::Object(obj) => Box::new(
+                        obj.borrow()
+                            .clone()
+                            .into_iter()
+                            .map(|(k, v)| (Value::String(k), v)),
+                    ),
+                    Value::Range(range) => Box::new(
+                        (0..range.len())
+                            .filter_map(move |idx| range.get(idx))
+                            .enumerate()
+                            .map(|(idx, i)| (Value::Integer(idx as i64), Value::Integer(i))),
+                    ),
+                    _ => {
+                        return Err(FenicsError::type_error(
+                            "For loop requires an array, object or range",
+                        ))
                     }
-                    Value::Object(obj) => {
-                        self.locals.push(HashMap::new());
-                        for (k, v) in obj.iter() {
-                            if let Some(key) = key_var {
-                                self.locals
-                                    .last_mut()
-                                    .unwrap()
-                                    .insert(key.clone(), Value::String(k.clone()));
-                            }
-                            self.locals
-                                .last_mut()
-                                .unwrap()
-                                .insert(value_var.clone(), v.clone());
-
-                            for stmt in body {
-                                if let Some(ret) = self.execute_statement(stmt)? {
-                                    self.locals.pop();
-                                    return Ok(Some(ret));
-                                }
-                            }
-                        }
-                        self.locals.pop();
+                };
+
+                // Each iteration gets its own frame so closures capture that
+                // iteration's variables
+                for (key, val) in entries {
+                    self.push_scope();
+                    if let Some(key_name) = key_var {
+                        self.declare_local(key_name, key);
+                    }
+                    self.declare_local(value_var, val);
+
+                    let outcome = self.execute_block(body);
+                    self.locals.pop();
+                    match outcome? {
+                        flow if flow.continues_loop(label) => {}
+                        flow if flow.breaks_loop(label) => break,
+                        flow => return Ok(flow),
                     }
-                    _ => return Err("For loop requires an array or object".to_string()),
                 }
-                Ok(None)
+                Ok(ControlFlow::Normal)
             }
 
-            Statement::WhileLoop { condition, body } => {
+            StatementKind::WhileLoop {
+                label,
+                condition,
+                body,
+            } => {
                 loop {
                     let cond_value = self.evaluate_expression(condition)?;
                     if !cond_value.is_truthy() {
                         break;
                     }
 
-                    for stmt in body {
-                        if let Some(ret) = self.execute_statement(stmt)? {
-                            return Ok(Some(ret));
-                        }
+                    match self.execute_scoped_block(body)? {
+                        flow if flow.continues_loop(label) => {}
+                        flow if flow.breaks_loop(label) => break,
+                        flow => return Ok(flow),
                     }
                 }
-                Ok(None)
+                Ok(ControlFlow::Normal)
             }
 
-            Statement::Loop { condition, body } => loop {
-                let cond_value = self.evaluate_expression(condition)?;
-                if cond_value.is_truthy() {
-                    for stmt in body {
-                        if let Some(ret) = self.execute_statement(stmt)? {
-                            return Ok(Some(ret));
+            StatementKind::Loop {
+                label,
+                condition,
+                body,
+            } => {
+                loop {
+                    let cond_value = self.evaluate_expression(condition)?;
+                    if cond_value.is_truthy() {
+                        match self.execute_scoped_block(body)? {
+                            flow if flow.continues_loop(label) => {}
+                            flow if flow.breaks_loop(label) => break,
+                            flow => return Ok(flow),
                         }
                     }
                 }
-            },
+                Ok(ControlFlow::Normal)
+            }
 
-            Statement::TryCatch {
+            StatementKind::TryCatch {
                 try_body,
                 error_var,
                 catch_body,
+                finally_body,
             } => {
-                let mut error = None;
-
-                for stmt in try_body {
-                    match self.execute_statement(stmt) {
-                        Ok(Some(ret)) => return Ok(Some(ret)),
-                        Ok(None) => {}
-                        Err(e) => {
-                            error = Some(e);
-                            break;
-                        }
+                // Break/continue/return leave the try body as signals, not errors,
+                // so they pass through to the enclosing loop or function untouched
+                let outcome = match self.execute_scoped_block(try_body) {
+                    Err(err) => {
+                        self.push_scope();
+                        self.declare_local(error_var, Value::Error(Box::new(err)));
+                        let outcome = self.execute_block(catch_body);
+                        self.locals.pop();
+                        outcome
                     }
-                }
+                    flow => flow,
+                };
 
-                if let Some(err_msg) = error {
-                    self.locals.push(HashMap::new());
-                    self.locals
-                        .last_mut()
-                        .unwrap()
-                        .insert(error_var.clone(), Value::String(err_msg));
-
-                    for stmt in catch_body {
-                        if let Some(ret) = self.execute_statement(stmt)? {
-                            self.locals.pop();
-                            return Ok(Some(ret));
-                        }
-                    }
-                    self.locals.pop();
+                // `finally` runs whatever happened above; an error or a
+                // break/continue/return of its own replaces that outcome
+                match finally_body {
+                    Some(finally_body) => match self.execute_scoped_block(finally_body)? {
+                        ControlFlow::Normal => outcome,
+                        flow => Ok(flow),
+                    },
+                    None => outcome,
                 }
-                Ok(None)
             }
 
-            Statement::Block { expression } => {
+            StatementKind::Throw(value) => Err(match self.evaluate_expression(value)? {
+                // Rethrowing a caught error keeps its kind, location and stack
+                Value::Error(err) => *err,
+                value => FenicsError::thrown(value),
+            }),
+
+            StatementKind::Block { expression } => {
                 self.evaluate_expression(expression)?;
-                Ok(None)
+                Ok(ControlFlow::Normal)
             }
 
-            Statement::LibExport { name, exports } => self.handle_lib_export(name, exports),
+            StatementKind::LibExport { name, exports } => self.handle_lib_export(name, exports),
 
-            Statement::Import { path, alias } => self.handle_import(path, alias),
+            StatementKind::Import { path, alias } => self.handle_import(path, alias),
 
-            Statement::Expression(expr) => {
+            StatementKind::Expression(expr) => {
                 self.evaluate_expression(expr)?;
-                Ok(None)
+                Ok(ControlFlow::Normal)
+            }
+        }
+    }
+
+    /// Run statements in a fresh block frame, discarding it even if one fails.
+    pub(crate) fn execute_scoped_block(
+        &mut self,
+        body: &[Statement],
+    ) -> Result<ControlFlow, FenicsError> {
+        self.push_scope();
+        let outcome = self.execute_block(body);
+        self.locals.pop();
+        outcome
+    }
+
+    /// Run statements in order, stopping at the first break, continue or return.
+    pub(crate) fn execute_block(&mut self, body: &[Statement]) -> Result<ControlFlow, FenicsError> {
+        for stmt in body {
+            match self.execute_statement(stmt)? {
+                ControlFlow::Normal => {}
+                flow => return Ok(flow),
             }
         }
+        Ok(ControlFlow::Normal)
     }
 
     fn handle_lib_export(
         &mut self,
         name: &str,
         exports: &[String],
-    ) -> Result<Option<Value>, String> {
-        let mut map: HashMap<String, Value> = HashMap::new();
+    ) -> Result<ControlFlow, FenicsError> {
+        let mut map = IndexMap::new();
         for fname in exports {
-            if let Some(Value::Function { params, body }) = self.globals.get(fname.as_str()) {
-                map.insert(
-                    fname.clone(),
-                    Value::Function {
-                        params: params.clone(),
-                        body: body.clone(),
-                    },
-                );
+            if let Some(func @ Value::Function { .. }) = self
+                .globals
+                .get(fname.as_str())
+                .map(|binding| &binding.value)
+            {
+                map.insert(fname.clone(), func.clone());
             } else {
-                return Err(format!("Export '{}' not found or not a function", fname));
+                return Err(FenicsError::name(format!(
+                    "Export '{}' not found or not a function",
+                    fname
+                )));
             }
         }
-        self.globals.insert(name.to_string(), Value::Object(map));
-        Ok(None)
+        self.globals
+            .insert(name.to_string(), Binding::mutable(Value::object(map)));
+        Ok(ControlFlow::Normal)
     }
 
     fn handle_import(
         &mut self,
         path: &str,
         alias: &Option<String>,
-    ) -> Result<Option<Value>, String> {
+    ) -> Result<ControlFlow, FenicsError> {
         let resolved_path = self.resolve_import_path(path)?;
-        let source = std::fs::read_to_string(&resolved_path)
-            .map_err(|e| format!("Error reading import '{}': {}", resolved_path, e))?;
-        let program = crate::parser::parse_program(&source)?;
+        let source = std::fs::read_to_string(&resolved_path).map_err(|e| {
+            FenicsError::io(format!("Error reading import '{}': {}", resolved_path, e))
+        })?;
+        // Spans of library errors point into the library's source, not ours
+        let context = format!("Error in library '{}'", path);
+        let program =
+            crate::parser::parse_program(&source).map_err(|e| e.with_context(&context))?;
 
         let mut lib_name: Option<String> = None;
         for stmt in &program.statements {
-            if let Statement::LibExport { name, .. } = stmt {
+            if let StatementKind::LibExport { name, .. } = &stmt.kind {
                 lib_name = Some(name.clone());
                 break;
             }
         }
 
-        let mut lib_interp = Interpreter::new();
-        lib_interp.interpret(&program)?;
+        let mut lib_interp = Interpreter::with_source(SourceFile::new(resolved_path, source));
+        lib_interp
+            .interpret(&program)
+            .map_err(|e| e.with_context(&context))?;
 
         let register_name = alias.clone().or(lib_name.clone()).ok_or_else(|| {
-            "Imported file does not declare a lib export; use 'as' to name it".to_string()
+            FenicsError::name("Imported file does not declare a lib export")
+                .with_hint("name the import with 'as'")
         })?;
 
         let module_value = if let Some(ref actual_name) = lib_name {
             lib_interp
                 .globals
                 .get(actual_name)
-                .cloned()
-                .ok_or_else(|| format!("Module '{}' not found in library", actual_name))?
+                .map(|binding| binding.value.clone())
+                .ok_or_else(|| {
+                    FenicsError::name(format!("Module '{}' not found in library", actual_name))
+                })?
         } else {
-            let mut map: HashMap<String, Value> = HashMap::new();
-            for (k, v) in lib_interp.globals.iter() {
-                if let Value::Function { .. } = v {
-                    map.insert(k.clone(), v.clone());
-                }
-            }
-            Value::Object(map)
+            // Every function, by name, when the library exports no list
+            let mut map: IndexMap<String, Value> = lib_interp
+                .globals
+                .iter()
+                .filter(|(_, binding)| matches!(binding.value, Value::Function { .. }))
+                .map(|(k, binding)| (k.clone(), binding.value.clone()))
+                .collect();
+            map.sort_keys();
+            Value::object(map)
         };
 
-        self.globals.insert(register_name, module_value);
-        Ok(None)
+        self.globals
+            .insert(register_name, Binding::mutable(module_value));
+        Ok(ControlFlow::Normal)
     }
 }
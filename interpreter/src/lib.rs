@@ -0,0 +1,9 @@
+pub mod ast;
+pub mod checker;
+pub mod error;
+pub mod features;
+pub mod interpreter;
+pub mod interpreter_engine;
+pub mod parser;
+pub mod resolver;
+pub mod utils;
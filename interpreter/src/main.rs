@@ -1,23 +1,28 @@
-pub mod ast;
-pub mod features;
-pub mod interpreter;
-pub mod interpreter_engine;
-pub mod parser;
-pub mod utils;
-
+use fenics_interpreter::features::SourceFile;
+use fenics_interpreter::utils::function_store::MAX_CALL_DEPTH;
+use fenics_interpreter::{checker, interpreter, parser};
 use std::env;
+use std::thread;
 use tokio::fs;
 
+const USAGE: &str =
+    "Usage: fenics-interpreter <file.fenics>\n       fenics-interpreter check <file.fenics>";
+
+// Stack reserved per active call, enough for unoptimized builds
+const STACK_PER_CALL: usize = 256 * 1024;
+
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() < 2 {
-        eprintln!("Usage: fenics-interpreter <file.fenics>");
-        std::process::exit(1);
-    }
-
-    let filename = &args[1];
+    let (check_only, filename) = match args.as_slice() {
+        [_, command, file] if command == "check" => (true, file),
+        [_, file] => (false, file),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
 
     let source = match fs::read_to_string(filename).await {
         Ok(s) => s,
@@ -27,18 +32,47 @@ async fn main() {
         }
     };
 
-    match parser::parse_program(&source) {
-        Ok(program) => {
-            let mut interpreter = interpreter::Interpreter::new();
+    // Run on a thread with room for the deepest recursion the interpreter allows
+    let filename = filename.clone();
+    let succeeded = thread::Builder::new()
+        .stack_size(MAX_CALL_DEPTH * STACK_PER_CALL)
+        .spawn(move || run(check_only, &filename, &source))
+        .expect("failed to start the interpreter thread")
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    if !succeeded {
+        std::process::exit(1);
+    }
+}
+
+/// Parse, then check or run, the program; false if any error was reported.
+fn run(check_only: bool, filename: &str, source: &str) -> bool {
+    let (program, parse_errors) = parser::parse_program_recovering(source);
+    for err in &parse_errors {
+        eprint!("{}", err.render(source, filename));
+    }
 
-            if let Err(err) = interpreter.interpret(&program) {
-                eprintln!("Runtime error: {}", err);
-                std::process::exit(1);
-            }
+    if check_only {
+        // Check what did parse too, so one run reports every problem
+        let errors = checker::check_program(&program);
+        for err in &errors {
+            eprint!("{}", err.render(source, filename));
         }
-        Err(err) => {
-            eprintln!("Parse error: {}", err);
-            std::process::exit(1);
+        if !parse_errors.is_empty() || !errors.is_empty() {
+            return false;
+        }
+    } else {
+        if !parse_errors.is_empty() {
+            return false;
+        }
+
+        let mut interpreter =
+            interpreter::Interpreter::with_source(SourceFile::new(filename, source));
+
+        if let Err(err) = interpreter.interpret(&program) {
+            eprint!("{}", err.render(source, filename));
+            return false;
         }
     }
+    true
 }
@@ -1,42 +1,123 @@
 use crate::ast::*;
+use crate::error::FenicsError;
+use crate::features::Regex;
+use crate::resolver;
 use crate::utils::{
     ast::{parse_array_literal, parse_object_literal, parse_pairs_literal},
     string_interpolation::parse_string_interpolation,
+    string_literal::string_value,
+    syntax_errors::{indentation_errors, next_statement, statement_start, syntax_error},
     type_finder::parse_type,
 };
+use num_bigint::BigInt;
+use pest::pratt_parser::{Assoc, Op, PrattParser};
 use pest::Parser;
 use pest_derive::Parser;
+use std::cell::Cell;
+use std::iter::Peekable;
+use std::sync::LazyLock;
 
 #[derive(Parser)]
 #[grammar = "../grammar/fenics.pest"]
 pub struct FenicsParser;
 
-pub fn parse_program(input: &str) -> Result<Program, String> {
-    let pairs =
-        FenicsParser::parse(Rule::main, input).map_err(|e| format!("Parse error: {}", e))?;
+/// Parse a whole program, failing with the first error found.
+pub fn parse_program(input: &str) -> Result<Program, FenicsError> {
+    let (program, errors) = parse_program_recovering(input);
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(program),
+    }
+}
 
+/// Parse a program, skipping top-level statements that fail and reporting every error.
+pub fn parse_program_recovering(input: &str) -> (Program, Vec<FenicsError>) {
     let mut statements = Vec::new();
+    let mut errors = Vec::new();
+    let mut offset = 0;
+
+    while offset < input.len() {
+        let err = match parse_statements(input, offset, input.len()) {
+            Ok(parsed) => {
+                statements.extend(parsed);
+                break;
+            }
+            Err(err) => err,
+        };
 
-    for pair in pairs {
-        match pair.as_rule() {
-            Rule::main => {
-                for inner_pair in pair.into_inner() {
-                    match inner_pair.as_rule() {
-                        Rule::statement => {
-                            if let Some(stmt) = parse_statement(inner_pair)? {
-                                statements.push(stmt);
-                            }
-                        }
-                        Rule::EOI => break,
-                        _ => {}
-                    }
+        let at = err.span().map_or(offset, |span| span.start);
+        let bad_start = statement_start(input, offset, at);
+        // Everything before the broken statement parses on its own
+        match parse_statements(input, offset, bad_start) {
+            Ok(parsed) => statements.extend(parsed),
+            Err(err) => errors.push(err),
+        }
+        errors.push(err);
+        offset = next_statement(input, at);
+    }
+
+    // A statement with badly mixed indentation gets that reported instead
+    // of whatever the parser made of it
+    let indentation = indentation_errors(input);
+    errors.retain(|err| {
+        let at = err.span().map_or(0, |span| span.start);
+        let statement = statement_start(input, 0, at)..next_statement(input, at);
+        !indentation
+            .iter()
+            .filter_map(FenicsError::span)
+            .any(|span| statement.contains(&span.start))
+    });
+    errors.extend(indentation);
+
+    let program = Program { statements };
+    if let Err(err) = resolver::check_constants(&program) {
+        errors.push(err);
+    }
+    errors.sort_by_key(|err| err.span().map_or(0, |span| span.start));
+    (program, errors)
+}
+
+/// Parse the statements in `input[start..end]`, which must start a line.
+fn parse_statements(input: &str, start: usize, end: usize) -> Result<Vec<Statement>, FenicsError> {
+    let source = &input[start..end];
+    with_span_offset(start, || {
+        let pairs =
+            FenicsParser::parse(Rule::main, source).map_err(|e| syntax_error(e, input, start))?;
+
+        let mut statements = Vec::new();
+        for pair in pairs.filter(|p| p.as_rule() == Rule::main) {
+            for inner_pair in pair.into_inner() {
+                if inner_pair.as_rule() == Rule::statement {
+                    let span = span_of(&inner_pair);
+                    let stmt = parse_statement(inner_pair)
+                        .map_err(|e| FenicsError::parse(e).with_span(span))?;
+                    statements.extend(stmt);
                 }
             }
-            _ => {}
         }
-    }
+        Ok(statements)
+    })
+}
+
+thread_local! {
+    // Added to every span; set while parsing a snippet cut out of a larger
+    // source, such as the expression inside `#{...}`
+    static SPAN_OFFSET: Cell<usize> = const { Cell::new(0) };
+}
 
-    Ok(Program { statements })
+/// Source span covered by `pair`.
+pub(crate) fn span_of(pair: &pest::iterators::Pair<Rule>) -> Span {
+    let offset = SPAN_OFFSET.with(Cell::get);
+    let span = pair.as_span();
+    Span::new(offset + span.start(), offset + span.end())
+}
+
+/// Run `parse` with spans shifted by `offset` bytes.
+pub(crate) fn with_span_offset<T>(offset: usize, parse: impl FnOnce() -> T) -> T {
+    let previous = SPAN_OFFSET.with(|o| o.replace(offset));
+    let result = parse();
+    SPAN_OFFSET.with(|o| o.set(previous));
+    result
 }
 
 fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Option<Statement>, String> {
@@ -47,41 +128,41 @@ fn parse_statement(pair: pest::iterators::Pair<Rule>) -> Result<Option<Statement
     }
 
     let inner = inner.unwrap();
+    let span = span_of(&inner);
+
+    let kind = match inner.as_rule() {
+        Rule::const_definition => parse_const_definition(inner)?,
+        Rule::mutable_definition => parse_mutable_definition(inner)?,
+        Rule::global_const_definition => parse_global_const_definition(inner)?,
+        Rule::global_mutable_definition => parse_global_mutable_definition(inner)?,
+        Rule::assignment => parse_assignment(inner)?,
+        Rule::increment_stmt => parse_increment_stmt(inner)?,
+        Rule::function_def => parse_function_def(inner)?,
+        Rule::if_stmt => parse_if_stmt(inner)?,
+        Rule::for_loop => parse_for_loop(inner)?,
+        Rule::while_loop => parse_while_loop(inner)?,
+        Rule::loop_stmt => parse_loop_stmt(inner)?,
+        Rule::try_catch => parse_try_catch(inner)?,
+        Rule::throw_stmt => parse_throw_stmt(inner)?,
+        Rule::return_stmt => parse_return_stmt(inner)?,
+        Rule::break_stmt => StatementKind::Break(parse_optional_label(inner)),
+        Rule::continue_stmt => StatementKind::Continue(parse_optional_label(inner)),
+        Rule::lib_export => parse_lib_export(inner)?,
+        Rule::import_stmt => parse_import_stmt(inner)?,
+        Rule::expression => StatementKind::Expression(parse_expression(inner)?),
+        _ => return Ok(None),
+    };
+    Ok(Some(Statement::new(kind, span)))
+}
 
-    match inner.as_rule() {
-        Rule::const_definition => Ok(Some(parse_const_definition(inner)?)),
-        Rule::mutable_definition => Ok(Some(parse_mutable_definition(inner)?)),
-        Rule::global_const_definition => Ok(Some(parse_global_const_definition(inner)?)),
-        Rule::global_mutable_definition => Ok(Some(parse_global_mutable_definition(inner)?)),
-        Rule::assignment => Ok(Some(parse_assignment(inner)?)),
-        Rule::increment_stmt => Ok(Some(parse_increment_stmt(inner)?)),
-        Rule::function_def => Ok(Some(parse_function_def(inner)?)),
-        Rule::if_stmt => Ok(Some(parse_if_stmt(inner)?)),
-        Rule::for_loop => Ok(Some(parse_for_loop(inner)?)),
-        Rule::while_loop => Ok(Some(parse_while_loop(inner)?)),
-        Rule::loop_stmt => Ok(Some(parse_loop_stmt(inner)?)),
-        Rule::try_catch => Ok(Some(parse_try_catch(inner)?)),
-        Rule::return_stmt => Ok(Some(parse_return_stmt(inner)?)),
-        Rule::lib_export => Ok(Some(parse_lib_export(inner)?)),
-        Rule::import_stmt => Ok(Some(parse_import_stmt(inner)?)),
-        Rule::expression => Ok(Some(Statement::Expression(parse_expression(inner)?))),
-        _ => Ok(None),
-    }
-}
-
-fn parse_lib_export(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_lib_export(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut name = String::new();
     let mut exports: Vec<String> = Vec::new();
 
     for inner in pair.into_inner() {
         match inner.as_rule() {
-            Rule::identifier => {
-                if name.is_empty() {
-                    name = inner.as_str().to_string();
-                } else {
-                    // Unexpected extra identifier
-                }
-            }
+            // Any extra identifier after the library name is ignored
+            Rule::identifier if name.is_empty() => name = inner.as_str().to_string(),
             Rule::lib_item => {
                 for item in inner.into_inner() {
                     if item.as_rule() == Rule::identifier {
@@ -93,20 +174,17 @@ fn parse_lib_export(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Stri
         }
     }
 
-    Ok(Statement::LibExport { name, exports })
+    Ok(StatementKind::LibExport { name, exports })
 }
 
-fn parse_import_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_import_stmt(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut path = None;
     let mut alias = None;
 
     let mut expect_alias = false;
     for inner in pair.into_inner() {
         match inner.as_rule() {
-            Rule::string => {
-                let s = inner.as_str();
-                path = Some(s[1..s.len() - 1].to_string());
-            }
+            Rule::string => path = Some(string_value(inner.as_str())?),
             Rule::identifier => {
                 if !expect_alias && path.is_none() {
                     // This is the module name (pathless import)
@@ -123,13 +201,13 @@ fn parse_import_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Str
         }
     }
 
-    Ok(Statement::Import {
+    Ok(StatementKind::Import {
         path: path.ok_or("Missing path in import statement")?,
         alias,
     })
 }
 
-fn parse_const_definition(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_const_definition(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut type_annotation = None;
     let mut name = String::new();
     let mut value = None;
@@ -143,7 +221,7 @@ fn parse_const_definition(pair: pest::iterators::Pair<Rule>) -> Result<Statement
         }
     }
 
-    Ok(Statement::VariableDeclaration {
+    Ok(StatementKind::VariableDeclaration {
         type_annotation,
         is_const: true,
         is_global: false,
@@ -152,7 +230,7 @@ fn parse_const_definition(pair: pest::iterators::Pair<Rule>) -> Result<Statement
     })
 }
 
-fn parse_mutable_definition(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_mutable_definition(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut type_annotation = None;
     let mut name = String::new();
     let mut value = None;
@@ -166,7 +244,7 @@ fn parse_mutable_definition(pair: pest::iterators::Pair<Rule>) -> Result<Stateme
         }
     }
 
-    Ok(Statement::VariableDeclaration {
+    Ok(StatementKind::VariableDeclaration {
         type_annotation,
         is_const: false,
         is_global: false,
@@ -175,7 +253,9 @@ fn parse_mutable_definition(pair: pest::iterators::Pair<Rule>) -> Result<Stateme
     })
 }
 
-fn parse_global_const_definition(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_global_const_definition(
+    pair: pest::iterators::Pair<Rule>,
+) -> Result<StatementKind, String> {
     let mut type_annotation = None;
     let mut name = String::new();
     let mut value = None;
@@ -189,7 +269,7 @@ fn parse_global_const_definition(pair: pest::iterators::Pair<Rule>) -> Result<St
         }
     }
 
-    Ok(Statement::VariableDeclaration {
+    Ok(StatementKind::VariableDeclaration {
         type_annotation,
         is_const: true,
         is_global: true,
@@ -198,7 +278,9 @@ fn parse_global_const_definition(pair: pest::iterators::Pair<Rule>) -> Result<St
     })
 }
 
-fn parse_global_mutable_definition(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_global_mutable_definition(
+    pair: pest::iterators::Pair<Rule>,
+) -> Result<StatementKind, String> {
     let mut type_annotation = None;
     let mut name = String::new();
     let mut value = None;
@@ -212,7 +294,7 @@ fn parse_global_mutable_definition(pair: pest::iterators::Pair<Rule>) -> Result<
         }
     }
 
-    Ok(Statement::VariableDeclaration {
+    Ok(StatementKind::VariableDeclaration {
         type_annotation,
         is_const: false,
         is_global: true,
@@ -221,7 +303,8 @@ fn parse_global_mutable_definition(pair: pest::iterators::Pair<Rule>) -> Result<
     })
 }
 
-fn parse_assignment(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_assignment(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
+    let span = span_of(&pair);
     let mut target = None;
     let mut op = BinaryOperator::Assign;
     let mut value = None;
@@ -229,13 +312,10 @@ fn parse_assignment(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Stri
     for inner in pair.into_inner() {
         match inner.as_rule() {
             Rule::identifier => {
-                target = Some(Expression::Identifier(inner.as_str().to_string()));
+                target = Some(parse_identifier(&inner));
             }
-            Rule::dot_access => {
-                target = Some(parse_dot_access(inner)?);
-            }
-            Rule::bracket_access => {
-                target = Some(parse_bracket_access(inner)?);
+            Rule::access => {
+                target = Some(parse_access(inner)?);
             }
             Rule::assign => op = BinaryOperator::Assign,
             Rule::add_assign => op = BinaryOperator::AddAssign,
@@ -253,33 +333,32 @@ fn parse_assignment(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Stri
     let target_expr = target.ok_or("Missing target in assignment")?;
     let value_expr = value.ok_or("Missing value in assignment")?;
 
-    Ok(Statement::Expression(Expression::BinaryOp {
-        left: Box::new(target_expr),
-        op,
-        right: Box::new(value_expr),
-    }))
+    Ok(StatementKind::Expression(Expression::new(
+        ExpressionKind::BinaryOp {
+            left: Box::new(target_expr),
+            op,
+            right: Box::new(value_expr),
+        },
+        span,
+    )))
 }
 
-fn parse_increment_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_increment_stmt(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
+    let span = span_of(&pair);
     let mut target = None;
     let mut is_increment = true;
 
     for inner in pair.into_inner() {
-        match inner.as_str() {
-            "++" => is_increment = true,
-            "--" => is_increment = false,
-            _ => match inner.as_rule() {
-                Rule::identifier => {
-                    target = Some(Expression::Identifier(inner.as_str().to_string()));
-                }
-                Rule::dot_access => {
-                    target = Some(parse_dot_access(inner)?);
-                }
-                Rule::bracket_access => {
-                    target = Some(parse_bracket_access(inner)?);
-                }
-                _ => {}
-            },
+        match inner.as_rule() {
+            Rule::identifier => {
+                target = Some(parse_identifier(&inner));
+            }
+            Rule::access => {
+                target = Some(parse_access(inner)?);
+            }
+            Rule::increment_op => is_increment = true,
+            Rule::decrement_op => is_increment = false,
+            _ => {}
         }
     }
 
@@ -290,13 +369,16 @@ fn parse_increment_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement,
         UnaryOperator::Decrement
     };
 
-    Ok(Statement::Expression(Expression::UnaryOp {
-        op,
-        operand: Box::new(target_expr),
-    }))
+    Ok(StatementKind::Expression(Expression::new(
+        ExpressionKind::UnaryOp {
+            op,
+            operand: Box::new(target_expr),
+        },
+        span,
+    )))
 }
 
-fn parse_function_def(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_function_def(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut name = String::new();
     let mut parameters = Vec::new();
     let mut return_type = None;
@@ -312,7 +394,7 @@ fn parse_function_def(pair: pest::iterators::Pair<Rule>) -> Result<Statement, St
         }
     }
 
-    Ok(Statement::FunctionDeclaration {
+    Ok(StatementKind::FunctionDeclaration {
         name,
         parameters,
         return_type,
@@ -320,6 +402,42 @@ fn parse_function_def(pair: pest::iterators::Pair<Rule>) -> Result<Statement, St
     })
 }
 
+fn parse_lambda(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
+    let span = span_of(&pair);
+    let mut parameters = Vec::new();
+    let mut return_type = None;
+    let mut body = Vec::new();
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::parameter => parameters.push(parse_parameter(inner)?),
+            Rule::r#type => return_type = Some(parse_type(inner)?),
+            Rule::block => body = parse_block(inner)?,
+            Rule::statement => {
+                // A single expression body is the lambda's return value
+                body = match parse_statement(inner)? {
+                    Some(Statement {
+                        kind: StatementKind::Expression(expr),
+                        span,
+                    }) => vec![Statement::new(StatementKind::Return(Some(expr)), span)],
+                    Some(stmt) => vec![stmt],
+                    None => Vec::new(),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Expression::new(
+        ExpressionKind::Lambda {
+            parameters,
+            return_type,
+            body,
+        },
+        span,
+    ))
+}
+
 fn parse_parameter(pair: pest::iterators::Pair<Rule>) -> Result<Parameter, String> {
     let mut name = String::new();
     let mut type_annotation = None;
@@ -338,7 +456,7 @@ fn parse_parameter(pair: pest::iterators::Pair<Rule>) -> Result<Parameter, Strin
     })
 }
 
-fn parse_if_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_if_stmt(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut condition = None;
     let mut then_branch = Vec::new();
     let mut else_ifs = Vec::new();
@@ -380,7 +498,7 @@ fn parse_if_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String>
         }
     }
 
-    Ok(Statement::If {
+    Ok(StatementKind::If {
         condition: condition.ok_or("Missing condition in if statement")?,
         then_branch,
         else_ifs,
@@ -388,13 +506,22 @@ fn parse_if_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String>
     })
 }
 
-fn parse_for_loop(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+/// Name of the `@label` among a statement's children, without the `@`.
+fn parse_optional_label(pair: pest::iterators::Pair<Rule>) -> Option<String> {
+    pair.into_inner()
+        .find(|inner| inner.as_rule() == Rule::loop_label)
+        .map(|label| label.as_str()[1..].to_string())
+}
+
+fn parse_for_loop(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
+    let mut label = None;
     let mut identifiers = Vec::new();
     let mut iterable = None;
     let mut body = Vec::new();
 
     for inner in pair.into_inner() {
         match inner.as_rule() {
+            Rule::loop_label => label = Some(inner.as_str()[1..].to_string()),
             Rule::identifier => identifiers.push(inner.as_str().to_string()),
             Rule::expression => iterable = Some(parse_expression(inner)?),
             Rule::block => body = parse_block(inner)?,
@@ -408,7 +535,8 @@ fn parse_for_loop(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String
         (None, identifiers[0].clone())
     };
 
-    Ok(Statement::ForLoop {
+    Ok(StatementKind::ForLoop {
+        label,
         key_var,
         value_var,
         iterable: iterable.ok_or("Missing iterable in for loop")?,
@@ -416,71 +544,87 @@ fn parse_for_loop(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String
     })
 }
 
-fn parse_while_loop(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_while_loop(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
+    let mut label = None;
     let mut condition = None;
     let mut body = Vec::new();
 
     for inner in pair.into_inner() {
         match inner.as_rule() {
+            Rule::loop_label => label = Some(inner.as_str()[1..].to_string()),
             Rule::expression => condition = Some(parse_expression(inner)?),
             Rule::block => body = parse_block(inner)?,
             _ => {}
         }
     }
 
-    Ok(Statement::WhileLoop {
+    Ok(StatementKind::WhileLoop {
+        label,
         condition: condition.ok_or("Missing condition in while loop")?,
         body,
     })
 }
 
-fn parse_loop_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_loop_stmt(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
+    let mut label = None;
     let mut condition = None;
     let mut body = Vec::new();
 
     for inner in pair.into_inner() {
         match inner.as_rule() {
+            Rule::loop_label => label = Some(inner.as_str()[1..].to_string()),
             Rule::expression => condition = Some(parse_expression(inner)?),
             Rule::block => body = parse_block(inner)?,
             _ => {}
         }
     }
 
-    Ok(Statement::Loop {
+    Ok(StatementKind::Loop {
+        label,
         condition: condition.ok_or("Missing condition in loop statement")?,
         body,
     })
 }
 
-fn parse_try_catch(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_try_catch(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut try_body = Vec::new();
     let mut error_var = String::new();
     let mut catch_body = Vec::new();
-    let mut is_catch = false;
+    let mut finally_body = None;
+    let mut blocks_seen = 0;
 
     for inner in pair.into_inner() {
         match inner.as_rule() {
             Rule::block => {
-                if !is_catch {
-                    try_body = parse_block(inner)?;
-                    is_catch = true;
-                } else {
-                    catch_body = parse_block(inner)?;
+                match blocks_seen {
+                    0 => try_body = parse_block(inner)?,
+                    1 => catch_body = parse_block(inner)?,
+                    _ => finally_body = Some(parse_block(inner)?),
                 }
+                blocks_seen += 1;
             }
             Rule::identifier => error_var = inner.as_str().to_string(),
             _ => {}
         }
     }
 
-    Ok(Statement::TryCatch {
+    Ok(StatementKind::TryCatch {
         try_body,
         error_var,
         catch_body,
+        finally_body,
     })
 }
 
-fn parse_return_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, String> {
+fn parse_throw_stmt(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
+    let expr = pair
+        .into_inner()
+        .find(|inner| inner.as_rule() == Rule::expression)
+        .ok_or("Missing value in throw statement")?;
+    Ok(StatementKind::Throw(parse_expression(expr)?))
+}
+
+fn parse_return_stmt(pair: pest::iterators::Pair<Rule>) -> Result<StatementKind, String> {
     let mut value = None;
 
     for inner in pair.into_inner() {
@@ -489,7 +633,7 @@ fn parse_return_stmt(pair: pest::iterators::Pair<Rule>) -> Result<Statement, Str
         }
     }
 
-    Ok(Statement::Return(value))
+    Ok(StatementKind::Return(value))
 }
 
 fn parse_block(pair: pest::iterators::Pair<Rule>) -> Result<Vec<Statement>, String> {
@@ -507,112 +651,205 @@ fn parse_block(pair: pest::iterators::Pair<Rule>) -> Result<Vec<Statement>, Stri
 }
 
 pub(crate) fn parse_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
-    let inner = pair.into_inner().next();
+    let span = span_of(&pair);
+    let mut inner = pair.into_inner();
 
-    if inner.is_none() {
-        return Err("Empty expression".to_string());
-    }
+    let first = match inner.next() {
+        Some(first) => first,
+        None => return Err("Empty expression".to_string()),
+    };
 
-    let inner = inner.unwrap();
+    let head = match first.as_rule() {
+        Rule::ternary_then => return parse_ternary_then(first),
+        Rule::binary_expression => parse_binary_expression(first)?,
+        Rule::primary_expression => parse_primary_expression(first)?,
+        _ => return Err(format!("Unexpected expression rule: {:?}", first.as_rule())),
+    };
 
-    match inner.as_rule() {
-        Rule::binary_expression => parse_binary_expression(inner),
-        Rule::primary_expression => parse_primary_expression(inner),
-        _ => Err(format!("Unexpected expression rule: {:?}", inner.as_rule())),
+    // A range suffix, or the two branches of `cond ? a : b`, may follow the head
+    match (inner.next(), inner.next()) {
+        (Some(range), None) if range.as_rule() == Rule::range => parse_range(head, range, span),
+        (Some(true_pair), Some(false_pair)) => Ok(Expression::new(
+            ExpressionKind::TernaryQuestion {
+                condition: Box::new(head),
+                true_expr: Box::new(parse_expression(true_pair)?),
+                false_expr: Box::new(parse_expression(false_pair)?),
+            },
+            span,
+        )),
+        _ => Ok(head),
     }
 }
 
-fn parse_binary_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
-    // Collect expressions and operators
-    let mut parts = pair.into_inner();
-    let mut exprs: Vec<Expression> = Vec::new();
-    let mut ops: Vec<BinaryOperator> = Vec::new();
-
-    exprs.push(parse_primary_expression(parts.next().unwrap())?);
-
-    while let Some(op_pair) = parts.next() {
-        if op_pair.as_rule() == Rule::binary_op {
-            let op = match op_pair.as_str() {
-                "+" => BinaryOperator::Add,
-                "-" => BinaryOperator::Subtract,
-                "*" => BinaryOperator::Multiply,
-                "/" => BinaryOperator::Divide,
-                "%" => BinaryOperator::Modulo,
-                "^" | "**" => BinaryOperator::Power,
-                "==" | "=" | "===" => BinaryOperator::Equal,
-                "!=" | "!==" => BinaryOperator::NotEqual,
-                "<" => BinaryOperator::LessThan,
-                ">" => BinaryOperator::GreaterThan,
-                "<=" => BinaryOperator::LessThanOrEqual,
-                ">=" => BinaryOperator::GreaterThanOrEqual,
-                "is" => BinaryOperator::Is,
-                "is not" => BinaryOperator::IsNot,
-                "and" => BinaryOperator::And,
-                "or" => BinaryOperator::Or,
-                _ => return Err(format!("Unknown binary operator: {}", op_pair.as_str())),
-            };
+fn parse_range(
+    start: Expression,
+    pair: pest::iterators::Pair<Rule>,
+    span: Span,
+) -> Result<Expression, String> {
+    let mut inclusive = false;
+    let mut bounds = Vec::new();
 
-            ops.push(op);
-            exprs.push(parse_primary_expression(parts.next().unwrap())?);
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::range_inclusive => inclusive = true,
+            Rule::range_exclusive => inclusive = false,
+            Rule::binary_expression => bounds.push(parse_binary_expression(inner)?),
+            _ => {}
         }
     }
 
-    // If no operators, return the single expression
-    if ops.is_empty() {
-        return Ok(exprs.remove(0));
-    }
+    let mut bounds = bounds.into_iter();
+    let end = bounds.next().ok_or("Missing end in range")?;
 
-    // First, fold segments separated by logical operators (and/or)
-    let mut segment_exprs: Vec<Expression> = Vec::new();
-    let mut logical_ops: Vec<BinaryOperator> = Vec::new();
-    let mut seg_start: usize = 0;
+    Ok(Expression::new(
+        ExpressionKind::Range {
+            start: Box::new(start),
+            end: Box::new(end),
+            step: bounds.next().map(Box::new),
+            inclusive,
+        },
+        span,
+    ))
+}
 
-    for (i, op) in ops.iter().enumerate() {
-        if matches!(op, BinaryOperator::And | BinaryOperator::Or) {
-            // Fold exprs[seg_start..=i] left-to-right
-            let mut seg = exprs[seg_start].clone();
-            for k in seg_start..i {
-                seg = Expression::BinaryOp {
-                    left: Box::new(seg),
-                    op: ops[k].clone(),
-                    right: Box::new(exprs[k + 1].clone()),
-                };
-            }
-            segment_exprs.push(seg);
-            logical_ops.push(op.clone());
-            seg_start = i + 1;
+fn parse_ternary_then(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
+    let span = span_of(&pair);
+    let mut condition = None;
+    let mut branches = Vec::new();
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::binary_expression => condition = Some(parse_binary_expression(inner)?),
+            Rule::expression => branches.push(parse_expression(inner)?),
+            _ => {}
         }
     }
 
-    // Fold the final segment
-    let mut seg = exprs[seg_start].clone();
-    for k in seg_start..ops.len() {
-        // Only non-logical ops should be here; but folding is safe
-        seg = Expression::BinaryOp {
-            left: Box::new(seg),
-            op: ops[k].clone(),
-            right: Box::new(exprs[k + 1].clone()),
-        };
+    let false_expr = branches
+        .pop()
+        .ok_or("Missing 'otherwise' branch in ternary")?;
+    let true_expr = branches.pop().ok_or("Missing 'then' branch in ternary")?;
+
+    Ok(Expression::new(
+        ExpressionKind::TernaryThen {
+            condition: Box::new(condition.ok_or("Missing condition in ternary")?),
+            true_expr: Box::new(true_expr),
+            false_expr: Box::new(false_expr),
+        },
+        span,
+    ))
+}
+
+/// Operator precedence table, lowest binding power first.
+static PRATT_PARSER: LazyLock<PrattParser<Rule>> = LazyLock::new(|| {
+    PrattParser::new()
+        .op(Op::infix(Rule::coalesce_op, Assoc::Left))
+        .op(Op::infix(Rule::or_op, Assoc::Left))
+        .op(Op::infix(Rule::and_op, Assoc::Left))
+        .op(Op::prefix(Rule::not_op))
+        .op(Op::infix(Rule::eq_op, Assoc::Left)
+            | Op::infix(Rule::neq_op, Assoc::Left)
+            | Op::infix(Rule::identical_op, Assoc::Left)
+            | Op::infix(Rule::not_identical_op, Assoc::Left)
+            | Op::infix(Rule::match_op, Assoc::Left)
+            | Op::infix(Rule::not_match_op, Assoc::Left)
+            | Op::infix(Rule::is_op, Assoc::Left)
+            | Op::infix(Rule::is_not_op, Assoc::Left))
+        .op(Op::infix(Rule::lt_op, Assoc::Left)
+            | Op::infix(Rule::gt_op, Assoc::Left)
+            | Op::infix(Rule::le_op, Assoc::Left)
+            | Op::infix(Rule::ge_op, Assoc::Left))
+        .op(Op::infix(Rule::add_op, Assoc::Left) | Op::infix(Rule::sub_op, Assoc::Left))
+        .op(Op::infix(Rule::mul_op, Assoc::Left)
+            | Op::infix(Rule::div_op, Assoc::Left)
+            | Op::infix(Rule::int_div_op, Assoc::Left)
+            | Op::infix(Rule::mod_op, Assoc::Left))
+        .op(Op::prefix(Rule::neg_op) | Op::prefix(Rule::bang_op))
+        .op(Op::infix(Rule::pow_op, Assoc::Right))
+});
+
+fn parse_binary_operator(pair: &pest::iterators::Pair<Rule>) -> Result<BinaryOperator, String> {
+    match pair.as_rule() {
+        Rule::add_op => Ok(BinaryOperator::Add),
+        Rule::sub_op => Ok(BinaryOperator::Subtract),
+        Rule::mul_op => Ok(BinaryOperator::Multiply),
+        Rule::div_op => Ok(BinaryOperator::Divide),
+        Rule::int_div_op => Ok(BinaryOperator::FloorDivide),
+        Rule::mod_op => Ok(BinaryOperator::Modulo),
+        Rule::pow_op => Ok(BinaryOperator::Power),
+        Rule::eq_op => Ok(BinaryOperator::Equal),
+        Rule::neq_op => Ok(BinaryOperator::NotEqual),
+        Rule::identical_op => Ok(BinaryOperator::Identical),
+        Rule::not_identical_op => Ok(BinaryOperator::NotIdentical),
+        Rule::match_op => Ok(BinaryOperator::Match),
+        Rule::not_match_op => Ok(BinaryOperator::NotMatch),
+        Rule::lt_op => Ok(BinaryOperator::LessThan),
+        Rule::gt_op => Ok(BinaryOperator::GreaterThan),
+        Rule::le_op => Ok(BinaryOperator::LessThanOrEqual),
+        Rule::ge_op => Ok(BinaryOperator::GreaterThanOrEqual),
+        Rule::is_op => Ok(BinaryOperator::Is),
+        Rule::is_not_op => Ok(BinaryOperator::IsNot),
+        Rule::and_op => Ok(BinaryOperator::And),
+        Rule::or_op => Ok(BinaryOperator::Or),
+        Rule::coalesce_op => Ok(BinaryOperator::Coalesce),
+        _ => Err(format!("Unknown binary operator: {}", pair.as_str())),
     }
-    segment_exprs.push(seg);
+}
 
-    // Now fold logical ops over the folded segments
-    let mut result = segment_exprs[0].clone();
-    for (i, seg) in segment_exprs.iter().enumerate().skip(1) {
-        let lop = logical_ops[i - 1].clone();
-        result = Expression::BinaryOp {
-            left: Box::new(result),
-            op: lop,
-            right: Box::new(seg.clone()),
-        };
+fn parse_unary_operator(pair: &pest::iterators::Pair<Rule>) -> Result<UnaryOperator, String> {
+    match pair.as_rule() {
+        Rule::not_op | Rule::bang_op => Ok(UnaryOperator::Not),
+        Rule::neg_op => Ok(UnaryOperator::Negate),
+        _ => Err(format!("Unknown unary operator: {}", pair.as_str())),
     }
+}
 
-    Ok(result)
+fn parse_binary_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
+    PRATT_PARSER
+        .map_primary(parse_primary_expression)
+        .map_prefix(|op_pair, operand| {
+            let op = parse_unary_operator(&op_pair)?;
+            let operand = operand?;
+            let span = Span::new(span_of(&op_pair).start, operand.span.end);
+            // Fold negated numeric literals so `-5` is a literal, not an operation
+            let kind = match (&op, operand.kind) {
+                (UnaryOperator::Negate, ExpressionKind::Literal(Literal::Integer(i))) => {
+                    ExpressionKind::Literal(Literal::Integer(-i))
+                }
+                // `-9223372036854775808` is i64::MIN, though its digits alone overflow
+                (UnaryOperator::Negate, ExpressionKind::Literal(Literal::BigInt(i))) => {
+                    match i64::try_from(-&i) {
+                        Ok(small) => ExpressionKind::Literal(Literal::Integer(small)),
+                        Err(_) => ExpressionKind::Literal(Literal::BigInt(-i)),
+                    }
+                }
+                (UnaryOperator::Negate, ExpressionKind::Literal(Literal::Float(f))) => {
+                    ExpressionKind::Literal(Literal::Float(-f))
+                }
+                (_, kind) => ExpressionKind::UnaryOp {
+                    op,
+                    operand: Box::new(Expression::new(kind, operand.span)),
+                },
+            };
+            Ok(Expression::new(kind, span))
+        })
+        .map_infix(|left, op_pair, right| {
+            let (left, right) = (left?, right?);
+            let span = Span::new(left.span.start, right.span.end);
+            let kind = ExpressionKind::BinaryOp {
+                left: Box::new(left),
+                op: parse_binary_operator(&op_pair)?,
+                right: Box::new(right),
+            };
+            Ok(Expression::new(kind, span))
+        })
+        .parse(pair.into_inner())
 }
 
 fn parse_primary_expression(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
     let pair_str = pair.as_str();
     let pair_span = pair.as_span();
+    let span = span_of(&pair);
     let inner = pair.into_inner().next();
 
     if inner.is_none() {
@@ -625,17 +862,25 @@ fn parse_primary_expression(pair: pest::iterators::Pair<Rule>) -> Result<Express
     let inner = inner.unwrap();
 
     match inner.as_rule() {
+        Rule::paren_expression => {
+            let expr = inner
+                .into_inner()
+                .next()
+                .ok_or("Missing expression in parentheses")?;
+            parse_expression(expr)
+        }
+        Rule::lambda => parse_lambda(inner),
         Rule::literal => parse_literal(inner),
-        Rule::identifier => Ok(Expression::Identifier(inner.as_str().to_string())),
+        Rule::identifier => Ok(parse_identifier(&inner)),
         Rule::ephemeral_var => {
             // ephemeral_var is "#" followed by identifier or digits
             // Since identifier is atomic, we need to parse from the string
-            let text = inner.as_str();
-            if text.starts_with('#') {
-                let var_name = text[1..].trim().to_string();
-                Ok(Expression::EphemeralVar(var_name))
-            } else {
-                Err("Invalid ephemeral variable format".to_string())
+            match inner.as_str().strip_prefix('#') {
+                Some(var_name) => Ok(Expression::new(
+                    ExpressionKind::EphemeralVar(var_name.trim().to_string()),
+                    span,
+                )),
+                None => Err("Invalid ephemeral variable format".to_string()),
             }
         }
         Rule::ephemeral_assignment => {
@@ -650,31 +895,35 @@ fn parse_primary_expression(pair: pest::iterators::Pair<Rule>) -> Result<Express
 
             // Parse the base expression
             let base_expr = match base.as_rule() {
-                Rule::identifier => Expression::Identifier(base.as_str().to_string()),
+                Rule::identifier => parse_identifier(&base),
                 Rule::literal => parse_literal(base)?,
                 _ => return Err("Unexpected ephemeral assignment base".to_string()),
             };
 
             // Parse the ephemeral variable name
-            let ephemeral_text = ephemeral.as_str();
-            let var_name = if ephemeral_text.starts_with('#') {
-                ephemeral_text[1..].trim().to_string()
-            } else {
-                return Err("Invalid ephemeral variable format".to_string());
+            let ephemeral_span = span_of(&ephemeral);
+            let var_name = match ephemeral.as_str().strip_prefix('#') {
+                Some(name) => name.trim().to_string(),
+                None => return Err("Invalid ephemeral variable format".to_string()),
             };
 
             // Create an assignment where the ephemeral var is the target (left side)
             // and the base expression is what gets evaluated and assigned (right side)
-            Ok(Expression::BinaryOp {
-                left: Box::new(Expression::EphemeralVar(var_name)),
-                op: BinaryOperator::Assign,
-                right: Box::new(base_expr),
-            })
+            Ok(Expression::new(
+                ExpressionKind::BinaryOp {
+                    left: Box::new(Expression::new(
+                        ExpressionKind::EphemeralVar(var_name),
+                        ephemeral_span,
+                    )),
+                    op: BinaryOperator::Assign,
+                    right: Box::new(base_expr),
+                },
+                span,
+            ))
         }
         Rule::function_call => parse_function_call(inner),
         Rule::method_call => parse_method_call(inner),
-        Rule::dot_access => parse_dot_access(inner),
-        Rule::bracket_access => parse_bracket_access(inner),
+        Rule::access => parse_access(inner),
         _ => Err(format!(
             "Unexpected primary expression rule: {:?}",
             inner.as_rule()
@@ -682,44 +931,55 @@ fn parse_primary_expression(pair: pest::iterators::Pair<Rule>) -> Result<Express
     }
 }
 
+fn parse_identifier(pair: &pest::iterators::Pair<Rule>) -> Expression {
+    Expression::new(
+        ExpressionKind::Identifier(pair.as_str().to_string()),
+        span_of(pair),
+    )
+}
+
 fn parse_literal(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
+    let span = span_of(&pair);
     let inner = pair.into_inner().next().unwrap();
+    let literal = |literal| Ok(Expression::new(ExpressionKind::Literal(literal), span));
 
     match inner.as_rule() {
         Rule::integer => {
             let s = inner.as_str();
             if s.contains('.') {
                 let val = s.parse::<f64>().map_err(|_| "Invalid float")?;
-                Ok(Expression::Literal(Literal::Float(val)))
+                literal(Literal::Float(val))
             } else {
-                let val = s.parse::<i64>().map_err(|_| "Invalid integer")?;
-                Ok(Expression::Literal(Literal::Integer(val)))
+                match s.parse::<i64>() {
+                    Ok(val) => literal(Literal::Integer(val)),
+                    Err(_) => {
+                        let val = s.parse::<BigInt>().map_err(|_| "Invalid integer")?;
+                        literal(Literal::BigInt(val))
+                    }
+                }
             }
         }
         Rule::float => {
             let val = inner.as_str().parse::<f64>().map_err(|_| "Invalid float")?;
-            Ok(Expression::Literal(Literal::Float(val)))
-        }
-        Rule::string => {
-            let s = inner.as_str();
-            let trimmed = &s[1..s.len() - 1]; // Remove quotes
-            Ok(Expression::Literal(Literal::String(trimmed.to_string())))
+            literal(Literal::Float(val))
         }
+        Rule::string => literal(Literal::String(string_value(inner.as_str())?)),
         Rule::string_interpolation => parse_string_interpolation(inner),
         Rule::boolean => {
             let val = inner.as_str() == "true";
-            Ok(Expression::Literal(Literal::Boolean(val)))
+            literal(Literal::Boolean(val))
         }
         Rule::not_defined => match inner.as_str() {
-            "null" => Ok(Expression::Literal(Literal::Null)),
-            "undefined" => Ok(Expression::Literal(Literal::Undefined)),
-            "nil" => Ok(Expression::Literal(Literal::Nil)),
+            "null" => literal(Literal::Null),
+            "undefined" => literal(Literal::Undefined),
+            "nil" => literal(Literal::Nil),
             _ => Err("Unknown not_defined value".to_string()),
         },
         Rule::regex => {
-            let s = inner.as_str();
-            let pattern = &s[1..s.len() - 1]; // Remove slashes
-            Ok(Expression::Literal(Literal::Regex(pattern.to_string())))
+            let mut parts = inner.into_inner();
+            let pattern = parts.next().map_or("", |p| p.as_str());
+            let flags = parts.next().map_or("", |p| p.as_str());
+            literal(Literal::Regex(Regex::new(pattern, flags)?))
         }
         Rule::array_literal => parse_array_literal(inner),
         Rule::object_literal => parse_object_literal(inner),
@@ -729,6 +989,7 @@ fn parse_literal(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String
 }
 
 fn parse_function_call(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
+    let span = span_of(&pair);
     let mut name = String::new();
     let mut args = Vec::new();
 
@@ -741,108 +1002,551 @@ fn parse_function_call(pair: pest::iterators::Pair<Rule>) -> Result<Expression,
         }
     }
 
-    Ok(Expression::FunctionCall { name, args })
+    Ok(Expression::new(
+        ExpressionKind::FunctionCall { name, args },
+        span,
+    ))
 }
 
 fn parse_method_call(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
-    let mut object = None;
-    let mut method = String::new();
-    let mut args = Vec::new();
+    let span = span_of(&pair);
+    let mut inner = pair.into_inner().peekable();
+    let object = parse_access_chain(&mut inner)?;
+    let method = inner
+        .next()
+        .ok_or("Missing method name in method call")?
+        .as_str()
+        .to_string();
+    let args = inner.map(parse_expression).collect::<Result<_, _>>()?;
+
+    Ok(Expression::new(
+        ExpressionKind::MethodCall {
+            object: Box::new(object),
+            method,
+            args,
+        },
+        span,
+    ))
+}
 
-    for inner in pair.into_inner() {
-        match inner.as_rule() {
-            Rule::identifier => {
-                if object.is_none() {
-                    object = Some(Box::new(Expression::Identifier(inner.as_str().to_string())));
+fn parse_access(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
+    parse_access_chain(&mut pair.into_inner().peekable())
+}
+
+/// An access chain's base with each following `.name` or `[index]` applied in turn.
+fn parse_access_chain(
+    pairs: &mut Peekable<pest::iterators::Pairs<Rule>>,
+) -> Result<Expression, String> {
+    let base = pairs.next().ok_or("Missing object in access")?;
+    let mut object = match base.as_rule() {
+        Rule::identifier => parse_identifier(&base),
+        Rule::string => parse_literal_string(&base)?,
+        Rule::string_interpolation => parse_string_interpolation(base)?,
+        Rule::array_literal => parse_array_literal(base)?,
+        rule => return Err(format!("Unexpected object in access: {:?}", rule)),
+    };
+
+    while let Some(suffix) = pairs.next_if(|p| {
+        matches!(
+            p.as_rule(),
+            Rule::property_suffix | Rule::optional_property_suffix | Rule::index_suffix
+        )
+    }) {
+        let span = Span::new(object.span.start, span_of(&suffix).end);
+        let rule = suffix.as_rule();
+        let kind = match rule {
+            Rule::property_suffix | Rule::optional_property_suffix => {
+                let object = Box::new(object);
+                let property = suffix
+                    .into_inner()
+                    .next()
+                    .ok_or("Missing property name")?
+                    .as_str()
+                    .to_string();
+                if rule == Rule::property_suffix {
+                    ExpressionKind::PropertyAccess { object, property }
                 } else {
-                    method = inner.as_str().to_string();
-                }
-            }
-            Rule::string => {
-                if object.is_none() {
-                    let s = inner.as_str();
-                    let trimmed = &s[1..s.len() - 1];
-                    object = Some(Box::new(Expression::Literal(Literal::String(
-                        trimmed.to_string(),
-                    ))));
-                }
-            }
-            Rule::string_interpolation => {
-                if object.is_none() {
-                    object = Some(Box::new(parse_string_interpolation(inner)?));
+                    ExpressionKind::OptionalPropertyAccess { object, property }
                 }
             }
-            Rule::array_literal => {
-                if object.is_none() {
-                    object = Some(Box::new(parse_array_literal(inner)?));
-                }
-            }
-            Rule::builtin_array_method => method = inner.as_str().to_string(),
-            Rule::expression => args.push(parse_expression(inner)?),
-            _ => {}
-        }
+            _ => ExpressionKind::BracketAccess {
+                object: Box::new(object),
+                index: Box::new(parse_expression(
+                    suffix
+                        .into_inner()
+                        .next()
+                        .ok_or("Missing index in bracket access")?,
+                )?),
+            },
+        };
+        object = Expression::new(kind, span);
     }
+    Ok(object)
+}
 
-    Ok(Expression::MethodCall {
-        object: object.ok_or("Missing object in method call")?,
-        method,
-        args,
-    })
+/// A plain `"..."` string token as a literal expression.
+fn parse_literal_string(pair: &pest::iterators::Pair<Rule>) -> Result<Expression, String> {
+    Ok(Expression::new(
+        ExpressionKind::Literal(Literal::String(string_value(pair.as_str())?)),
+        span_of(pair),
+    ))
 }
 
-fn parse_dot_access(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
-    let mut object = None;
-    let mut property = String::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for inner in pair.into_inner() {
-        match inner.as_rule() {
-            Rule::identifier => {
-                if object.is_none() {
-                    object = Some(Box::new(Expression::Identifier(inner.as_str().to_string())));
-                } else {
-                    property = inner.as_str().to_string();
-                }
+    fn parse_expr(src: &str) -> Expression {
+        let pair = FenicsParser::parse(Rule::expression, src)
+            .unwrap_or_else(|e| panic!("failed to parse '{}': {}", src, e))
+            .next()
+            .unwrap();
+        parse_expression(pair).unwrap()
+    }
+
+    fn op_symbol(op: &BinaryOperator) -> &'static str {
+        match op {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::FloorDivide => "div",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Power => "^",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Identical => "===",
+            BinaryOperator::NotIdentical => "!==",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::LessThanOrEqual => "<=",
+            BinaryOperator::GreaterThanOrEqual => ">=",
+            BinaryOperator::Is => "is",
+            BinaryOperator::IsNot => "is not",
+            BinaryOperator::And => "and",
+            BinaryOperator::Or => "or",
+            BinaryOperator::Coalesce => "??",
+            _ => "?",
+        }
+    }
+
+    /// Render an expression as a fully parenthesized s-expression.
+    fn sexpr(expr: &Expression) -> String {
+        match &expr.kind {
+            ExpressionKind::Literal(Literal::Integer(i)) => i.to_string(),
+            ExpressionKind::Literal(Literal::Boolean(b)) => b.to_string(),
+            ExpressionKind::Identifier(name) => name.clone(),
+            ExpressionKind::BinaryOp { left, op, right } => {
+                format!("({} {} {})", op_symbol(op), sexpr(left), sexpr(right))
             }
-            Rule::string => {
-                let s = inner.as_str();
-                let trimmed = &s[1..s.len() - 1];
-                object = Some(Box::new(Expression::Literal(Literal::String(
-                    trimmed.to_string(),
-                ))));
+            ExpressionKind::UnaryOp { op, operand } => match op {
+                UnaryOperator::Not => format!("(not {})", sexpr(operand)),
+                UnaryOperator::Negate => format!("(neg {})", sexpr(operand)),
+                _ => format!("{:?}", expr),
+            },
+            ExpressionKind::TernaryQuestion {
+                condition,
+                true_expr,
+                false_expr,
+            } => format!(
+                "(? {} {} {})",
+                sexpr(condition),
+                sexpr(true_expr),
+                sexpr(false_expr)
+            ),
+            ExpressionKind::TernaryThen {
+                condition,
+                true_expr,
+                false_expr,
+            } => format!(
+                "(if {} {} {})",
+                sexpr(condition),
+                sexpr(true_expr),
+                sexpr(false_expr)
+            ),
+            ExpressionKind::PropertyAccess { object, property } => {
+                format!("(. {} {})", sexpr(object), property)
             }
-            Rule::string_interpolation => {
-                object = Some(Box::new(parse_string_interpolation(inner)?));
+            ExpressionKind::OptionalPropertyAccess { object, property } => {
+                format!("(?. {} {})", sexpr(object), property)
             }
-            Rule::array_literal => {
-                object = Some(Box::new(parse_array_literal(inner)?));
+            ExpressionKind::BracketAccess { object, index } => {
+                format!("([] {} {})", sexpr(object), sexpr(index))
             }
-            Rule::builtin_property_name => property = inner.as_str().to_string(),
-            _ => {}
+            ExpressionKind::MethodCall {
+                object,
+                method,
+                args,
+            } => {
+                let args: Vec<String> = args.iter().map(sexpr).collect();
+                format!("(call {} {} [{}])", sexpr(object), method, args.join(" "))
+            }
+            other => format!("{:?}", other),
         }
     }
 
-    Ok(Expression::PropertyAccess {
-        object: object.ok_or("Missing object in property access")?,
-        property,
-    })
-}
+    fn assert_tree(src: &str, expected: &str) {
+        assert_eq!(sexpr(&parse_expr(src)), expected, "source: {}", src);
+    }
 
-fn parse_bracket_access(pair: pest::iterators::Pair<Rule>) -> Result<Expression, String> {
-    let mut object = None;
-    let mut index = None;
+    #[test]
+    fn property_and_index_access_chain_left_to_right() {
+        assert_tree("a.b.c", "(. (. a b) c)");
+        assert_tree("rows[i].name", "(. ([] rows i) name)");
+        assert_tree("cfg[k][0] + 1", "(+ ([] ([] cfg k) 0) 1)");
+        assert_tree("rows[0].keys()", "(call ([] rows 0) keys [])");
+        assert_tree("a.b.get(k, 1)", "(call (. a b) get [k 1])");
+        assert_tree("a?.b?.c", "(?. (?. a b) c)");
+        assert_tree("a?.b.keys()", "(call (?. a b) keys [])");
+    }
 
-    for inner in pair.into_inner() {
-        match inner.as_rule() {
-            Rule::identifier => {
-                object = Some(Box::new(Expression::Identifier(inner.as_str().to_string())));
-            }
-            Rule::expression => index = Some(Box::new(parse_expression(inner)?)),
-            _ => {}
-        }
+    #[test]
+    fn multiplicative_binds_tighter_than_additive() {
+        assert_tree("1 + 2 * 3", "(+ 1 (* 2 3))");
+        assert_tree("1 - 6 / 3", "(- 1 (/ 6 3))");
+        assert_tree("1 + 7 % 4", "(+ 1 (% 7 4))");
+        assert_tree("2 * 3 + 1", "(+ (* 2 3) 1)");
     }
 
-    Ok(Expression::BracketAccess {
-        object: object.ok_or("Missing object in bracket access")?,
-        index: index.ok_or("Missing index in bracket access")?,
-    })
+    #[test]
+    fn power_binds_tighter_than_multiplicative_and_is_right_associative() {
+        assert_tree("2 * 3 ^ 2", "(* 2 (^ 3 2))");
+        assert_tree("2 ^ 3 ^ 2", "(^ 2 (^ 3 2))");
+        assert_tree("2 ** 3 ** 2", "(^ 2 (^ 3 2))");
+    }
+
+    #[test]
+    fn same_level_operators_are_left_associative() {
+        assert_tree("10 - 4 - 3", "(- (- 10 4) 3)");
+        assert_tree("8 / 4 / 2", "(/ (/ 8 4) 2)");
+        assert_tree("8 % 5 * 2", "(* (% 8 5) 2)");
+        assert_tree("7 div 2 * 3", "(* (div 7 2) 3)");
+        assert_tree("1 + 7 div 2 // halved", "(+ 1 (div 7 2))");
+        assert_tree("a == b != c", "(!= (== a b) c)");
+    }
+
+    #[test]
+    fn additive_binds_tighter_than_comparison() {
+        assert_tree("a + 1 < b", "(< (+ a 1) b)");
+        assert_tree("a > b - 1", "(> a (- b 1))");
+        assert_tree("a <= b + 1", "(<= a (+ b 1))");
+        assert_tree("a - 1 >= b", "(>= (- a 1) b)");
+    }
+
+    #[test]
+    fn comparison_binds_tighter_than_equality() {
+        assert_tree("a < b == c > d", "(== (< a b) (> c d))");
+        assert_tree("a <= b != true", "(!= (<= a b) true)");
+        assert_tree("a >= b is true", "(is (>= a b) true)");
+        assert_tree("a < b is not c", "(is not (< a b) c)");
+    }
+
+    #[test]
+    fn equality_binds_tighter_than_and() {
+        assert_tree("a == 1 and b != 2", "(and (== a 1) (!= b 2))");
+        assert_tree("a is b and c is not d", "(and (is a b) (is not c d))");
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_tree("a or b and c", "(or a (and b c))");
+        assert_tree("a and b or c and d", "(or (and a b) (and c d))");
+        assert_tree("a ?? b or c", "(?? a (or b c))");
+        assert_tree("a ?? b ?? c", "(?? (?? a b) c)");
+        assert_tree("a ?? b ? c : d", "(? (?? a b) c d)");
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_tree("(1 + 2) * 3", "(* (+ 1 2) 3)");
+        assert_tree("2 ^ (1 + 1)", "(^ 2 (+ 1 1))");
+        assert_tree("(2 ^ 3) ^ 2", "(^ (^ 2 3) 2)");
+        assert_tree("(a or b) and c", "(and (or a b) c)");
+        assert_tree("((1))", "1");
+    }
+
+    #[test]
+    fn equivalent_operator_spellings() {
+        assert_tree("a === b", "(=== a b)");
+        assert_tree("a !== b", "(!== a b)");
+    }
+
+    #[test]
+    fn word_operators_need_a_word_boundary() {
+        assert_tree("island", "island");
+        assert_tree("a or order", "(or a order)");
+        assert_tree("a and android", "(and a android)");
+    }
+
+    #[test]
+    fn negative_literals_are_folded() {
+        assert_eq!(
+            parse_expr("-5").kind,
+            ExpressionKind::Literal(Literal::Integer(-5))
+        );
+        assert_eq!(
+            parse_expr("-2.5").kind,
+            ExpressionKind::Literal(Literal::Float(-2.5))
+        );
+        assert_tree("3 - -2", "(- 3 -2)");
+    }
+
+    #[test]
+    fn negation_binds_tighter_than_multiplicative_but_looser_than_power() {
+        assert_tree("-x * y", "(* (neg x) y)");
+        assert_tree("-(a + b)", "(neg (+ a b))");
+        assert_tree("-2 ^ 2", "(neg (^ 2 2))");
+        assert_tree("2 ^ -x", "(^ 2 (neg x))");
+        assert_tree("!a == b", "(== (not a) b)");
+    }
+
+    #[test]
+    fn not_binds_looser_than_equality_but_tighter_than_and() {
+        assert_tree("not a == b", "(not (== a b))");
+        assert_tree("not a and b", "(and (not a) b)");
+        assert_tree("a or not b", "(or a (not b))");
+        assert_tree("not not a", "(not (not a))");
+        assert_tree("nothing", "nothing");
+    }
+
+    #[test]
+    fn question_ternary_binds_looser_than_every_binary_operator() {
+        assert_tree("a ? 1 : 2", "(? a 1 2)");
+        assert_tree("a or b ? x + 1 : y * 2", "(? (or a b) (+ x 1) (* y 2))");
+        assert_tree("(a ? 1 : 2) + 3", "(+ (? a 1 2) 3)");
+    }
+
+    #[test]
+    fn question_ternary_nests_to_the_right() {
+        assert_tree("a ? 1 : b ? 2 : 3", "(? a 1 (? b 2 3))");
+        assert_tree("a ? b ? 1 : 2 : 3", "(? a (? b 1 2) 3)");
+        assert_tree("(a ? b : c) ? 1 : 2", "(? (? a b c) 1 2)");
+    }
+
+    #[test]
+    fn then_otherwise_ternary() {
+        assert_tree("if a then 1 otherwise 2", "(if a 1 2)");
+        assert_tree(
+            "if a == 1 then x + 1 otherwise -y",
+            "(if (== a 1) (+ x 1) (neg y))",
+        );
+        assert_tree(
+            "if a then 1 otherwise if b then 2 otherwise 3",
+            "(if a 1 (if b 2 3))",
+        );
+        assert_tree(
+            "if a then if b then 1 otherwise 2 otherwise 3",
+            "(if a (if b 1 2) 3)",
+        );
+        assert_tree("if a then b ? 1 : 2 otherwise 3", "(if a (? b 1 2) 3)");
+    }
+
+    #[test]
+    fn ternaries_in_arguments_assignments_and_interpolations() {
+        let program = parse_program(
+            "ok : false\n\
+             a : ok ? 1 : 2\n\
+             b : if ok then \"yes\" otherwise \"no\"\n\
+             c : len([ok ? 1 : 2, 3])\n\
+             d : \"n=#{if ok then 1 otherwise 0}\"",
+        )
+        .unwrap();
+        let mut interp = crate::interpreter::Interpreter::new();
+        interp.interpret(&program).unwrap();
+        let get = |name: &str| interp.get_variable(name).unwrap();
+        assert_eq!(get("a"), crate::features::Value::Integer(2));
+        assert_eq!(get("b"), crate::features::Value::String("no".to_string()));
+        assert_eq!(get("c"), crate::features::Value::Integer(2));
+        assert_eq!(get("d"), crate::features::Value::String("n=0".to_string()));
+    }
+
+    #[test]
+    fn evaluates_with_precedence() {
+        let program = parse_program("x : 1 + 2 * 3\ny : (1 + 2) * 3\nz : 2 ^ 3 ^ 2").unwrap();
+        let mut interp = crate::interpreter::Interpreter::new();
+        interp.interpret(&program).unwrap();
+        assert_eq!(
+            interp.get_variable("x").unwrap(),
+            crate::features::Value::Integer(7)
+        );
+        assert_eq!(
+            interp.get_variable("y").unwrap(),
+            crate::features::Value::Integer(9)
+        );
+        assert_eq!(
+            interp.get_variable("z").unwrap(),
+            crate::features::Value::Integer(512)
+        );
+    }
+
+    #[test]
+    fn string_escapes_raw_and_triple_quoted_strings() {
+        let src = "name : \"Ann\"\n\
+                   e : \"\"\n\
+                   a : \"say \\\"hi\\\"\\t\\\\ \\u{2603}\\n\"\n\
+                   b : \"\\#{name} is #{name}\"\n\
+                   c : r\"C:\\new #{name}\"\n\
+                   if true:\n\
+                   \x20   d : \"\"\"\n\
+                   \x20       Dear #{name},\n\
+                   \x20         \"hello\"\n\
+                   \x20       \"\"\"\n\
+                   \x20   e : d";
+        let program = parse_program(src).unwrap();
+        let mut interp = crate::interpreter::Interpreter::new();
+        interp.interpret(&program).unwrap();
+        let get = |name: &str| match interp.get_variable(name).unwrap() {
+            crate::features::Value::String(s) => s,
+            other => panic!("expected a string, got {:?}", other),
+        };
+        assert_eq!(get("a"), "say \"hi\"\t\\ \u{2603}\n");
+        assert_eq!(get("b"), "#{name} is Ann");
+        assert_eq!(get("c"), "C:\\new #{name}");
+        assert_eq!(get("e"), "Dear Ann,\n  \"hello\"");
+    }
+
+    #[test]
+    fn bad_strings_are_reported() {
+        assert_eq!(
+            diagnostics("a : \"\\q\"\n"),
+            [(
+                "Unknown escape sequence '\\q'".to_string(),
+                "a : \"\\q\"".to_string()
+            )]
+        );
+        assert_eq!(
+            diagnostics("a : 1\nb : \"\"\"\n  text\nprint(a)\n"),
+            [("Unterminated string".to_string(), "\"\"\"".to_string())]
+        );
+    }
+
+    /// Messages and the source text each error points at.
+    fn diagnostics(src: &str) -> Vec<(String, String)> {
+        parse_program_recovering(src)
+            .1
+            .iter()
+            .map(|err| {
+                let span = err.span().unwrap();
+                (
+                    err.message().to_string(),
+                    src[span.start..span.end].to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recovering_parse_reports_every_error_and_keeps_the_rest() {
+        let src = "a : 1\nif a > 0\n    print(a)\n\nb : 2\nc : 1 +\nd : 3\n";
+        let (program, errors) = parse_program_recovering(src);
+        assert_eq!(errors.len(), 2);
+        let names: Vec<&str> = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StatementKind::VariableDeclaration { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["a", "b", "d"]);
+
+        assert_eq!(
+            parse_program(src).err().map(|e| e.message().to_string()),
+            Some("Missing ':' after the `if` line".to_string())
+        );
+    }
+
+    #[test]
+    fn recovering_parse_reports_errors_in_source_order() {
+        let src = "const k : 1\nk : 2\nc : 1 +\n";
+        let (_, errors) = parse_program_recovering(src);
+        let messages: Vec<&str> = errors.iter().map(|e| e.message()).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], "Cannot assign to constant 'k'");
+        assert!(errors[0].span().unwrap().start < errors[1].span().unwrap().start);
+    }
+
+    #[test]
+    fn blocks_end_at_the_first_dedent() {
+        let src = "n : 0\n\
+                   for i in 0..3:\n\
+                   \x20   if i == 1:\n\
+                   \x20       n +: 1\n\
+                   \x20   else if i == 2:\n\
+                   \x20       for j in 0..2:\n\
+                   \x20           n +: 10\n\
+                   \n\
+                   \x20   else:\n\
+                   \x20       n +: 100\n\
+                   \x20   n +: 1000\n\
+                   done : n";
+        let program = parse_program(src).unwrap();
+        assert_eq!(program.statements.len(), 3);
+        let StatementKind::ForLoop { body, .. } = &program.statements[1].kind else {
+            panic!("expected a for loop, got {:?}", program.statements[1]);
+        };
+        assert_eq!(body.len(), 2);
+
+        let mut interp = crate::interpreter::Interpreter::new();
+        interp.interpret(&program).unwrap();
+        assert_eq!(
+            interp.get_variable("done").unwrap(),
+            crate::features::Value::Integer(3121)
+        );
+    }
+
+    #[test]
+    fn bad_indentation_is_reported() {
+        assert_eq!(
+            diagnostics("if true:\n    a : 1\n  b : 2\n"),
+            [(
+                "Indentation doesn't match any enclosing block".to_string(),
+                "  ".to_string()
+            )]
+        );
+        assert_eq!(
+            diagnostics("a : 1\n    b : 2\n"),
+            [("Unexpected indentation".to_string(), "    ".to_string())]
+        );
+        assert_eq!(
+            diagnostics("if true:\nb : 2\n"),
+            [(
+                "Expected an indented block after ':'".to_string(),
+                ":".to_string()
+            )]
+        );
+        assert_eq!(
+            diagnostics("if true:\n    a : 1\nwhile false:\n\tb : 2\n"),
+            [(
+                "Indentation mixes tabs and spaces".to_string(),
+                "\t".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn common_mistakes_get_friendly_messages() {
+        assert_eq!(
+            diagnostics("while x < 3\n    x +: 1\n"),
+            [(
+                "Missing ':' after the `while` line".to_string(),
+                String::new()
+            )]
+        );
+        assert_eq!(
+            diagnostics("x = 1\ny += 2\n"),
+            [
+                ("Use ':' to assign, not '='".to_string(), "=".to_string()),
+                ("Use '+:' to assign, not '+='".to_string(), "+=".to_string()),
+            ]
+        );
+        assert_eq!(
+            diagnostics("s : \"open\nprint(s)\nt : \"ok\"\n"),
+            [("Unterminated string".to_string(), "\"open".to_string())]
+        );
+        assert_eq!(
+            diagnostics("total : 1 *\n"),
+            [("Unexpected end of line".to_string(), String::new())]
+        );
+    }
 }
@@ -0,0 +1,390 @@
+//! Static `const` checks run on a parsed program before it is interpreted.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    BinaryOperator, Expression, ExpressionKind, Literal, Parameter, Program, Statement,
+    StatementKind, StringPart, UnaryOperator,
+};
+use crate::error::FenicsError;
+
+fn assign_to_constant(name: &str) -> FenicsError {
+    FenicsError::name(format!("Cannot assign to constant '{}'", name))
+        .with_hint("declare it without `const` if it needs to change")
+}
+
+fn assign_to_global(name: &str) -> FenicsError {
+    FenicsError::name(format!(
+        "Cannot assign to global '{}' inside a function",
+        name
+    ))
+    .with_hint(format!("write it with `global {} : ...`", name))
+}
+
+/// Check `program` for writes to constants and invalid redeclarations.
+pub fn check_constants(program: &Program) -> Result<(), FenicsError> {
+    let mut resolver = Resolver {
+        scopes: vec![Frame::default()],
+        functions: 0,
+    };
+    resolver.statements(&program.statements)
+}
+
+#[derive(Default)]
+struct Frame {
+    /// Declared names and whether each is constant.
+    names: HashMap<String, bool>,
+}
+
+struct Resolver {
+    scopes: Vec<Frame>,
+    /// How many function bodies enclose the code being checked.
+    functions: usize,
+}
+
+impl Resolver {
+    fn statements(&mut self, statements: &[Statement]) -> Result<(), FenicsError> {
+        statements.iter().try_for_each(|s| self.statement(s))
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<(), FenicsError> {
+        self.statement_kind(&statement.kind)
+            .map_err(|e| e.with_span(statement.span))
+    }
+
+    fn statement_kind(&mut self, statement: &StatementKind) -> Result<(), FenicsError> {
+        match statement {
+            StatementKind::VariableDeclaration {
+                type_annotation,
+                is_const,
+                is_global,
+                name,
+                value,
+            } => {
+                self.expression(value)?;
+                if type_annotation.is_none() && !*is_const && !*is_global {
+                    self.bind(name)
+                } else {
+                    let frame = if *is_global { 0 } else { self.scopes.len() - 1 };
+                    self.declare(frame, name, *is_const)
+                }
+            }
+            StatementKind::FunctionDeclaration {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                self.declare(self.scopes.len() - 1, name, false)?;
+                self.function_body(parameters, body)
+            }
+            StatementKind::Return(value) => value.iter().try_for_each(|e| self.expression(e)),
+            StatementKind::If {
+                condition,
+                then_branch,
+                else_ifs,
+                else_branch,
+            } => {
+                self.expression(condition)?;
+                self.block(&[], then_branch)?;
+                for (condition, body) in else_ifs {
+                    self.expression(condition)?;
+                    self.block(&[], body)?;
+                }
+                else_branch
+                    .iter()
+                    .try_for_each(|body| self.block(&[], body))
+            }
+            StatementKind::ForLoop {
+                key_var,
+                value_var,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable)?;
+                let mut vars: Vec<&str> = key_var.iter().map(String::as_str).collect();
+                vars.push(value_var);
+                self.block(&vars, body)
+            }
+            StatementKind::WhileLoop {
+                condition, body, ..
+            }
+            | StatementKind::Loop {
+                condition, body, ..
+            } => {
+                self.expression(condition)?;
+                self.block(&[], body)
+            }
+            StatementKind::TryCatch {
+                try_body,
+                error_var,
+                catch_body,
+                finally_body,
+            } => {
+                self.block(&[], try_body)?;
+                self.block(&[error_var], catch_body)?;
+                match finally_body {
+                    Some(finally_body) => self.block(&[], finally_body),
+                    None => Ok(()),
+                }
+            }
+            StatementKind::Throw(value) => self.expression(value),
+            StatementKind::Block { expression } | StatementKind::Expression(expression) => {
+                self.expression(expression)
+            }
+            StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::LibExport { .. }
+            | StatementKind::Import { .. } => Ok(()),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Result<(), FenicsError> {
+        self.expression_kind(&expression.kind)
+            .map_err(|e| e.with_span(expression.span))
+    }
+
+    fn expression_kind(&mut self, expression: &ExpressionKind) -> Result<(), FenicsError> {
+        match expression {
+            ExpressionKind::BinaryOp { left, op, right } => {
+                self.expression(right)?;
+                if matches!(
+                    op,
+                    BinaryOperator::Assign
+                        | BinaryOperator::AddAssign
+                        | BinaryOperator::SubAssign
+                        | BinaryOperator::MulAssign
+                        | BinaryOperator::DivAssign
+                        | BinaryOperator::ModAssign
+                ) {
+                    self.write(left)
+                } else {
+                    self.expression(left)
+                }
+            }
+            ExpressionKind::UnaryOp { op, operand } => match op {
+                UnaryOperator::Increment | UnaryOperator::Decrement => self.write(operand),
+                _ => self.expression(operand),
+            },
+            ExpressionKind::Lambda {
+                parameters, body, ..
+            } => self.function_body(parameters, body),
+            ExpressionKind::Literal(Literal::Array(items)) => {
+                items.iter().try_for_each(|e| self.expression(e))
+            }
+            ExpressionKind::Literal(Literal::Object(fields)) => {
+                fields.values().try_for_each(|e| self.expression(e))
+            }
+            ExpressionKind::Literal(_)
+            | ExpressionKind::Identifier(_)
+            | ExpressionKind::EphemeralVar(_) => Ok(()),
+            ExpressionKind::FunctionCall { args, .. } => {
+                args.iter().try_for_each(|e| self.expression(e))
+            }
+            ExpressionKind::MethodCall { object, args, .. } => {
+                self.expression(object)?;
+                args.iter().try_for_each(|e| self.expression(e))
+            }
+            ExpressionKind::PropertyAccess { object, .. }
+            | ExpressionKind::OptionalPropertyAccess { object, .. } => self.expression(object),
+            ExpressionKind::BracketAccess { object, index } => {
+                self.expression(object)?;
+                self.expression(index)
+            }
+            ExpressionKind::TernaryThen {
+                condition,
+                true_expr,
+                false_expr,
+            }
+            | ExpressionKind::TernaryQuestion {
+                condition,
+                true_expr,
+                false_expr,
+            } => {
+                self.expression(condition)?;
+                self.expression(true_expr)?;
+                self.expression(false_expr)
+            }
+            ExpressionKind::StringInterpolation { parts } => {
+                parts.iter().try_for_each(|part| match part {
+                    StringPart::Expression(e) => self.expression(e),
+                    StringPart::Text(_) => Ok(()),
+                })
+            }
+            ExpressionKind::Range {
+                start, end, step, ..
+            } => {
+                self.expression(start)?;
+                self.expression(end)?;
+                step.iter().try_for_each(|e| self.expression(e))
+            }
+        }
+    }
+
+    /// Check an assignment target; `const` protects the binding, not the array or object it holds.
+    fn write(&mut self, target: &Expression) -> Result<(), FenicsError> {
+        match &target.kind {
+            ExpressionKind::Identifier(name) => match self.lookup(name, 0) {
+                Some(true) => Err(assign_to_constant(name).with_span(target.span)),
+                Some(false) if self.lookup(name, self.first_writable()).is_none() => {
+                    Err(assign_to_global(name).with_span(target.span))
+                }
+                _ => Ok(()),
+            },
+            _ => self.expression(target),
+        }
+    }
+
+    /// Mirror of the runtime rule for a plain `name : value`.
+    fn bind(&mut self, name: &str) -> Result<(), FenicsError> {
+        match self.lookup(name, self.first_writable()) {
+            Some(true) => Err(assign_to_constant(name)),
+            Some(false) => Ok(()),
+            None => self.declare(self.scopes.len() - 1, name, false),
+        }
+    }
+
+    fn declare(&mut self, frame: usize, name: &str, is_const: bool) -> Result<(), FenicsError> {
+        let names = &mut self.scopes[frame].names;
+        match names.get(name) {
+            Some(true) => Err(
+                FenicsError::name(format!("Cannot redeclare constant '{}'", name))
+                    .with_hint("declare it without `const` if it needs to change"),
+            ),
+            Some(false) if is_const => Err(FenicsError::name(format!(
+                "'{}' is already declared in this scope",
+                name
+            ))),
+            _ => {
+                names.insert(name.to_string(), is_const);
+                Ok(())
+            }
+        }
+    }
+
+    /// The first frame a plain assignment can rebind in: functions can't rebind globals.
+    fn first_writable(&self) -> usize {
+        usize::from(self.functions > 0)
+    }
+
+    /// Whether `name` is constant in frames down to `first_frame`, if declared there.
+    fn lookup(&self, name: &str, first_frame: usize) -> Option<bool> {
+        self.scopes[first_frame..]
+            .iter()
+            .rev()
+            .find_map(|frame| frame.names.get(name).copied())
+    }
+
+    fn block(&mut self, vars: &[&str], body: &[Statement]) -> Result<(), FenicsError> {
+        self.scoped(vars.iter().copied(), body)
+    }
+
+    fn function_body(
+        &mut self,
+        parameters: &[Parameter],
+        body: &[Statement],
+    ) -> Result<(), FenicsError> {
+        self.functions += 1;
+        let result = self.scoped(parameters.iter().map(|p| p.name.as_str()), body);
+        self.functions -= 1;
+        result
+    }
+
+    fn scoped<'a>(
+        &mut self,
+        vars: impl Iterator<Item = &'a str>,
+        body: &[Statement],
+    ) -> Result<(), FenicsError> {
+        let names = vars.map(|name| (name.to_string(), false)).collect();
+        self.scopes.push(Frame { names });
+        let result = self.statements(body);
+        self.scopes.pop();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_program;
+
+    fn check(src: &str) -> Result<(), String> {
+        parse_program(src)
+            .map(|_| ())
+            .map_err(|e| e.message().to_string())
+    }
+
+    #[test]
+    fn rejects_writes_to_constants() {
+        for src in [
+            "const x : 1\nx +: 1\n",
+            "const x : 1\nx++\n",
+            "const x : 1\nx : 2\n",
+            "const x : 1\nif true:\n    x : 2\n\n",
+        ] {
+            assert_eq!(
+                check(src),
+                Err("Cannot assign to constant 'x'".to_string()),
+                "{}",
+                src
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_redeclaring_in_the_same_scope() {
+        assert_eq!(
+            check("const x : 1\nconst x : 2\n"),
+            Err("Cannot redeclare constant 'x'".to_string())
+        );
+        assert_eq!(
+            check("x : 1\nconst x : 2\n"),
+            Err("'x' is already declared in this scope".to_string())
+        );
+        assert_eq!(
+            check("const f : 1\nfn f():\n    return 1\n\n"),
+            Err("Cannot redeclare constant 'f'".to_string())
+        );
+        assert_eq!(
+            check("const x : 1\nfn f():\n    global x : 2\n\n"),
+            Err("Cannot redeclare constant 'x'".to_string())
+        );
+    }
+
+    #[test]
+    fn allows_writes_into_constant_arrays_and_objects() {
+        assert!(check("const x : [1, 2]\nx[0] : 5\nx[1] +: 1\n").is_ok());
+        assert!(check("const o : obj:\n- a: 1\no.a : 2\no[\"b\"] : 3\n").is_ok());
+    }
+
+    #[test]
+    fn allows_shadowing_and_mutable_redeclaration() {
+        assert!(check("x : 1\nInt x : 2\nx : 3\n").is_ok());
+        assert!(check("const x : 1\nif true:\n    const x : 2\n\n").is_ok());
+        assert!(check("const x : 1\nfn f():\n    const x : 2\n\n").is_ok());
+        assert!(check("const x : 1\nfn f():\n    x : 2\n\n").is_ok());
+        assert!(check("const x : 1\nf : fn(x): x + 1\n").is_ok());
+    }
+
+    #[test]
+    fn finds_writes_inside_lambdas() {
+        assert_eq!(
+            check("const n : 1\nf : fn(): n +: 1\n"),
+            Err("Cannot assign to constant 'n'".to_string())
+        );
+        assert_eq!(
+            check("fn f():\n    const x : 1\n    g : fn(): x : 2\n\n"),
+            Err("Cannot assign to constant 'x'".to_string())
+        );
+    }
+
+    #[test]
+    fn functions_write_globals_only_through_global() {
+        assert!(check("i : 1\nfn f():\n    i : 2\n    i +: 1\n\n").is_ok());
+        assert!(check("i : 1\nfn f():\n    global i : i + 1\n\n").is_ok());
+        assert_eq!(
+            check("i : 1\nfn f():\n    i +: 1\n\n"),
+            Err("Cannot assign to global 'i' inside a function".to_string())
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use crate::ast::{Expression, Span};
+use crate::error::FenicsError;
+use crate::features::{numbers, Value};
+use crate::interpreter::Interpreter;
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+
+impl Interpreter {
+    /// Methods on arrays; callbacks also get the index when they take two parameters.
+    pub(crate) fn call_array_method(
+        &mut self,
+        arr: &[Value],
+        method: &str,
+        args: &[Expression],
+        call_site: Span,
+    ) -> Result<Value, FenicsError> {
+        let mut values = Vec::new();
+        for a in args {
+            values.push(self.evaluate_expression(a)?);
+        }
+        let args = values;
+        let arity = |min: usize, max: usize| {
+            if (min..=max).contains(&args.len()) {
+                return Ok(());
+            }
+            let expected = match (min, max) {
+                (0, 0) => "no arguments".to_string(),
+                (0, 1) => "at most 1 argument".to_string(),
+                (1, 1) => "exactly 1 argument".to_string(),
+                (min, max) if min == max => format!("exactly {} arguments", min),
+                (min, max) => format!("{} or {} arguments", min, max),
+            };
+            Err(FenicsError::type_error(format!(
+                "{}() takes {}",
+                method, expected
+            )))
+        };
+
+        match method {
+            "reverse" => {
+                arity(0, 0)?;
+                Ok(Value::array(arr.iter().rev().cloned().collect()))
+            }
+            "has" => {
+                arity(1, 1)?;
+                Ok(Value::Boolean(arr.iter().any(|item| item.equals(&args[0]))))
+            }
+            "join" => {
+                arity(0, 1)?;
+                let separator = match args.first() {
+                    Some(Value::String(s)) => s.as_str(),
+                    Some(other) => {
+                        return Err(FenicsError::type_error(format!(
+                            "join() requires a String separator, got {}",
+                            other.type_name()
+                        )))
+                    }
+                    None => "",
+                };
+                let items: Vec<String> = arr.iter().map(|v| v.to_string()).collect();
+                Ok(Value::String(items.join(separator)))
+            }
+            "sort" => {
+                arity(1, 1)?;
+                match &args[0] {
+                    Value::String(order) => sort_by_order(arr, order),
+                    func @ Value::Function { params, .. } if params.len() == 2 => {
+                        let mut compare = |a: &Value, b: &Value| {
+                            let result =
+                                self.invoke_function(func, vec![a.clone(), b.clone()], call_site)?;
+                            match result {
+                                n if n.is_nan() => Ok(Ordering::Equal),
+                                n if n.is_number() => Ok(numbers::compare(&n, &Value::Integer(0))),
+                                other => Err(FenicsError::type_error(format!(
+                                    "sort() comparator must return a number, got {}",
+                                    other.type_name()
+                                ))),
+                            }
+                        };
+                        merge_sort(arr.to_vec(), &mut compare).map(Value::array)
+                    }
+                    func @ Value::Function { .. } => {
+                        let mut keyed = Vec::with_capacity(arr.len());
+                        for item in arr {
+                            let key = self.invoke_function(func, vec![item.clone()], call_site)?;
+                            keyed.push((key, item.clone()));
+                        }
+                        keyed.sort_by(|(a, _), (b, _)| a.compare(b));
+                        Ok(Value::array(
+                            keyed.into_iter().map(|(_, item)| item).collect(),
+                        ))
+                    }
+                    _ => Err(FenicsError::type_error(
+                        "sort() requires an order like '0-9' or 'a-z', or a function",
+                    )),
+                }
+            }
+            "map" | "filter" | "each" | "find" | "any" | "all" | "flat_map" | "group_by" => {
+                arity(1, 1)?;
+                let func = function_arg(method, &args[0])?;
+                let mut results = Vec::new();
+                let mut groups: IndexMap<String, Vec<Value>> = IndexMap::new();
+                for (i, item) in arr.iter().enumerate() {
+                    let result =
+                        self.invoke_function(func, callback_args(func, item, i), call_site)?;
+                    match method {
+                        "map" => results.push(result),
+                        "filter" if result.is_truthy() => results.push(item.clone()),
+                        "find" if result.is_truthy() => return Ok(item.clone()),
+                        "any" if result.is_truthy() => return Ok(Value::Boolean(true)),
+                        "all" if !result.is_truthy() => return Ok(Value::Boolean(false)),
+                        "flat_map" => match result {
+                            Value::Array(items) => results.extend(items.borrow().iter().cloned()),
+                            other => results.push(other),
+                        },
+                        "group_by" => {
+                            groups
+                                .entry(result.to_string())
+                                .or_default()
+                                .push(item.clone());
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(match method {
+                    "map" | "filter" | "flat_map" => Value::array(results),
+                    "group_by" => Value::object(
+                        groups
+                            .into_iter()
+                            .map(|(key, items)| (key, Value::array(items)))
+                            .collect(),
+                    ),
+                    "any" => Value::Boolean(false),
+                    "all" => Value::Boolean(true),
+                    // `each` and a `find` that found nothing
+                    _ => Value::Null,
+                })
+            }
+            "reduce" => {
+                arity(1, 2)?;
+                let func = function_arg(method, &args[0])?;
+                let mut items = arr.iter().cloned();
+                let mut acc = match args.get(1) {
+                    Some(initial) => initial.clone(),
+                    None => items.next().ok_or_else(|| {
+                        FenicsError::type_error("reduce() of an empty array needs an initial value")
+                    })?,
+                };
+                for item in items {
+                    acc = self.invoke_function(func, vec![acc, item], call_site)?;
+                }
+                Ok(acc)
+            }
+            "zip" => {
+                arity(1, 1)?;
+                let Value::Array(other) = &args[0] else {
+                    return Err(FenicsError::type_error(format!(
+                        "zip() requires an Array, got {}",
+                        args[0].type_name()
+                    )));
+                };
+                Ok(Value::array(
+                    arr.iter()
+                        .zip(other.borrow().iter())
+                        .map(|(a, b)| Value::array(vec![a.clone(), b.clone()]))
+                        .collect(),
+                ))
+            }
+            "enumerate" => {
+                arity(0, 0)?;
+                Ok(Value::array(
+                    arr.iter()
+                        .enumerate()
+                        .map(|(i, item)| Value::array(vec![Value::Integer(i as i64), item.clone()]))
+                        .collect(),
+                ))
+            }
+            "chunk" => {
+                arity(1, 1)?;
+                match args[0] {
+                    Value::Integer(size) if size > 0 => Ok(Value::array(
+                        arr.chunks(size as usize)
+                            .map(|chunk| Value::array(chunk.to_vec()))
+                            .collect(),
+                    )),
+                    _ => Err(FenicsError::type_error(format!(
+                        "chunk() size must be a positive Int, got {}",
+                        args[0]
+                    ))),
+                }
+            }
+            "unique" => {
+                arity(0, 0)?;
+                let mut unique: Vec<Value> = Vec::new();
+                for item in arr {
+                    if !unique.iter().any(|seen| seen.equals(item)) {
+                        unique.push(item.clone());
+                    }
+                }
+                Ok(Value::array(unique))
+            }
+            _ => Err(FenicsError::type_error(format!(
+                "Method '{}' not found",
+                method
+            ))),
+        }
+    }
+}
+
+fn function_arg<'a>(method: &str, arg: &'a Value) -> Result<&'a Value, FenicsError> {
+    match arg {
+        Value::Function { .. } => Ok(arg),
+        other => Err(FenicsError::type_error(format!(
+            "{}() requires a function, got {}",
+            method,
+            other.type_name()
+        ))),
+    }
+}
+
+/// The item, and its index too for a function taking two parameters.
+fn callback_args(func: &Value, item: &Value, index: usize) -> Vec<Value> {
+    match func {
+        Value::Function { params, .. } if params.len() == 2 => {
+            vec![item.clone(), Value::Integer(index as i64)]
+        }
+        _ => vec![item.clone()],
+    }
+}
+
+/// `sort('0-9')`, `sort('9-0')`, `sort('a-z')` and `sort('z-a')`.
+fn sort_by_order(arr: &[Value], order: &str) -> Result<Value, FenicsError> {
+    let (numeric, descending) = match order {
+        "0-9" => (true, false),
+        "9-0" => (true, true),
+        "a-z" => (false, false),
+        "z-a" => (false, true),
+        _ => {
+            return Err(FenicsError::runtime(
+                "Unsupported sort order. Use '0-9', '9-0', 'a-z', or 'z-a'",
+            ))
+        }
+    };
+    let fits = |v: &Value| match v {
+        v if v.is_number() => numeric,
+        Value::String(_) => !numeric,
+        _ => false,
+    };
+    if !arr.iter().all(fits) {
+        let kind = if numeric { "numeric" } else { "string" };
+        return Err(FenicsError::type_error(format!(
+            "sort('{}') requires {} array",
+            order, kind
+        )));
+    }
+    let mut sorted = arr.to_vec();
+    sorted.sort_by(Value::compare);
+    if descending {
+        sorted.reverse();
+    }
+    Ok(Value::array(sorted))
+}
+
+/// A stable merge sort whose comparison may fail, and may be inconsistent.
+fn merge_sort(
+    mut items: Vec<Value>,
+    compare: &mut dyn FnMut(&Value, &Value) -> Result<Ordering, FenicsError>,
+) -> Result<Vec<Value>, FenicsError> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let left = merge_sort(items, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Ties keep the left item first, so the sort is stable
+        if compare(a, b)? == Ordering::Greater {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
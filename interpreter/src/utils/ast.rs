@@ -1,9 +1,11 @@
-use crate::ast::{Expression, Literal};
-use crate::parser::{parse_expression, Rule};
+use crate::ast::{Expression, ExpressionKind, Literal};
+use crate::parser::{parse_expression, span_of, Rule};
+use crate::utils::string_literal::string_value;
+use indexmap::IndexMap;
 use pest::iterators::Pair;
-use std::collections::HashMap;
 
 pub(crate) fn parse_array_literal(pair: Pair<Rule>) -> Result<Expression, String> {
+    let span = span_of(&pair);
     let mut elements = Vec::new();
 
     for inner in pair.into_inner() {
@@ -12,11 +14,15 @@ pub(crate) fn parse_array_literal(pair: Pair<Rule>) -> Result<Expression, String
         }
     }
 
-    Ok(Expression::Literal(Literal::Array(elements)))
+    Ok(Expression::new(
+        ExpressionKind::Literal(Literal::Array(elements)),
+        span,
+    ))
 }
 
 pub(crate) fn parse_object_literal(pair: Pair<Rule>) -> Result<Expression, String> {
-    let properties = HashMap::new();
+    let span = span_of(&pair);
+    let properties = IndexMap::new();
 
     for inner in pair.into_inner() {
         match inner.as_rule() {
@@ -28,11 +34,15 @@ pub(crate) fn parse_object_literal(pair: Pair<Rule>) -> Result<Expression, Strin
         }
     }
 
-    Ok(Expression::Literal(Literal::Object(properties)))
+    Ok(Expression::new(
+        ExpressionKind::Literal(Literal::Object(properties)),
+        span,
+    ))
 }
 
 pub(crate) fn parse_pairs_literal(pair: Pair<Rule>) -> Result<Expression, String> {
-    let mut properties = HashMap::new();
+    let span = span_of(&pair);
+    let mut properties = IndexMap::new();
 
     for pair_item in pair.into_inner() {
         if pair_item.as_rule() == Rule::pairs_item {
@@ -41,10 +51,7 @@ pub(crate) fn parse_pairs_literal(pair: Pair<Rule>) -> Result<Expression, String
 
             for item in pair_item.into_inner() {
                 match item.as_rule() {
-                    Rule::string => {
-                        let s = item.as_str();
-                        key = s[1..s.len() - 1].to_string();
-                    }
+                    Rule::string => key = string_value(item.as_str())?,
                     Rule::identifier => {
                         key = item.as_str().to_string();
                     }
@@ -59,5 +66,8 @@ pub(crate) fn parse_pairs_literal(pair: Pair<Rule>) -> Result<Expression, String
         }
     }
 
-    Ok(Expression::Literal(Literal::Object(properties)))
+    Ok(Expression::new(
+        ExpressionKind::Literal(Literal::Object(properties)),
+        span,
+    ))
 }
@@ -0,0 +1,88 @@
+use crate::error::FenicsError;
+use crate::features::numbers::{decimal_to_float, int_to_decimal, int_to_float, int_value};
+use crate::features::Value;
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, Num};
+use rust_decimal::Decimal;
+use std::str::FromStr;
+
+/// `int(value)`: truncates numbers toward zero and parses base-10 Strings.
+pub(crate) fn to_int(value: &Value) -> Result<Value, FenicsError> {
+    match value {
+        Value::Integer(_) | Value::BigInt(_) => Ok(value.clone()),
+        Value::Float(f) => BigInt::from_f64(f.trunc())
+            .map(int_value)
+            .ok_or_else(|| FenicsError::type_error(format!("Cannot convert {} to Int", f))),
+        Value::Decimal(d) => Ok(int_value(BigInt::from(d.trunc().mantissa()))),
+        Value::Boolean(b) => Ok(Value::Integer(*b as i64)),
+        Value::String(s) => s.trim().parse::<BigInt>().map(int_value).map_err(|_| {
+            let message = format!("Cannot convert '{}' to Int", s);
+            if s.trim().parse::<f64>().is_ok() {
+                FenicsError::type_error(message)
+                    .with_hint("use float() for numbers with a fractional part")
+            } else {
+                FenicsError::type_error(message)
+            }
+        }),
+        other => Err(FenicsError::type_error(format!(
+            "Cannot convert {} to Int",
+            other.type_name()
+        ))),
+    }
+}
+
+/// `float(value)`: numbers, Booleans as 1.0 or 0.0, and numeric Strings.
+pub(crate) fn to_float(value: &Value) -> Result<Value, FenicsError> {
+    match value {
+        Value::Integer(i) => Ok(Value::Float(*i as f64)),
+        Value::BigInt(i) => Ok(Value::Float(int_to_float(i))),
+        Value::Float(f) => Ok(Value::Float(*f)),
+        Value::Decimal(d) => Ok(Value::Float(decimal_to_float(*d))),
+        Value::Boolean(b) => Ok(Value::Float(if *b { 1.0 } else { 0.0 })),
+        Value::String(s) => s
+            .trim()
+            .parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| FenicsError::type_error(format!("Cannot convert '{}' to Float", s))),
+        other => Err(FenicsError::type_error(format!(
+            "Cannot convert {} to Float",
+            other.type_name()
+        ))),
+    }
+}
+
+/// `decimal(value)`: Ints, numeric Strings, and Floats as they print.
+pub(crate) fn to_decimal(value: &Value) -> Result<Value, FenicsError> {
+    let decimal = match value {
+        Value::Decimal(d) => Ok(*d),
+        Value::Integer(i) => Ok(Decimal::from(*i)),
+        Value::BigInt(i) => int_to_decimal(i),
+        Value::Float(f) => Decimal::from_str(&f.to_string())
+            .map_err(|_| FenicsError::type_error(format!("Cannot convert {} to Decimal", f))),
+        Value::String(s) => Decimal::from_str(s.trim())
+            .map_err(|_| FenicsError::type_error(format!("Cannot convert '{}' to Decimal", s))),
+        other => Err(FenicsError::type_error(format!(
+            "Cannot convert {} to Decimal",
+            other.type_name()
+        ))),
+    };
+    decimal.map(Value::Decimal)
+}
+
+/// `parse_int(text, radix)`: a whole number written in base `radix`, from 2 to 36.
+pub(crate) fn parse_int(text: &str, radix: i64) -> Result<Value, FenicsError> {
+    if !(2..=36).contains(&radix) {
+        return Err(FenicsError::type_error(format!(
+            "parse_int() radix must be between 2 and 36, got {}",
+            radix
+        )));
+    }
+    BigInt::from_str_radix(text.trim(), radix as u32)
+        .map(int_value)
+        .map_err(|_| {
+            FenicsError::type_error(format!(
+                "Cannot parse '{}' as an Int in base {}",
+                text, radix
+            ))
+        })
+}
@@ -1,68 +1,159 @@
-use crate::ast::Expression;
-use crate::features::Value;
-use crate::interpreter::Interpreter;
+use crate::ast::{Expression, Span, Statement};
+use crate::error::FenicsError;
+use crate::features::numbers::int_value;
+use crate::features::{Binding, Value};
+use crate::interpreter::{CallFrame, ControlFlow, Interpreter};
+use crate::utils::conversions::{parse_int, to_decimal, to_float, to_int};
+use crate::utils::string_methods::string_method;
+use crate::utils::type_checker::conform;
+use num_bigint::BigInt;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// How many calls can be active at once before the program is stopped.
+pub const MAX_CALL_DEPTH: usize = 1000;
 
 impl Interpreter {
     pub(crate) fn call_function(
         &mut self,
         name: &str,
         args: &[Expression],
-    ) -> Result<Value, String> {
+        call_site: Span,
+    ) -> Result<Value, FenicsError> {
         match name {
+            // A variable named like a builtin shadows it
+            _ if self.is_defined(name) => self.call_variable(name, args, call_site),
             "print" => {
                 for arg in args {
                     let val = self.evaluate_expression(arg)?;
-                    println!("{}", val.to_string());
+                    println!("{}", val);
                 }
-                return Ok(Value::Null);
+                Ok(Value::Null)
             }
             "len" => {
                 if args.len() != 1 {
-                    return Err("len() takes exactly 1 argument".to_string());
+                    return Err(FenicsError::type_error("len() takes exactly 1 argument"));
                 }
                 let val = self.evaluate_expression(&args[0])?;
                 match val {
-                    Value::String(s) => Ok(Value::Integer(s.len() as i64)),
-                    Value::Array(a) => Ok(Value::Integer(a.len() as i64)),
-                    _ => Err("len() requires a string or array".to_string()),
+                    Value::String(s) => Ok(Value::Integer(s.chars().count() as i64)),
+                    Value::Array(a) => Ok(Value::Integer(a.borrow().len() as i64)),
+                    Value::Range(r) => Ok(int_value(BigInt::from(r.count()))),
+                    _ => Err(FenicsError::type_error(
+                        "len() requires a string, array or range",
+                    )),
                 }
             }
-            _ => {
-                let func = self.get_variable(name)?;
-                match func {
-                    Value::Function { params, body } => {
-                        if args.len() != params.len() {
-                            return Err(format!(
-                                "Function '{}' expects {} arguments, got {}",
-                                name,
-                                params.len(),
-                                args.len()
-                            ));
-                        }
-
-                        self.locals.push(std::collections::HashMap::new());
-                        for (param, arg) in params.iter().zip(args.iter()) {
-                            let val = self.evaluate_expression(arg)?;
-                            self.locals
-                                .last_mut()
-                                .unwrap()
-                                .insert(param.name.clone(), val);
-                        }
-
-                        let mut result = Value::Null;
-                        for stmt in &body {
-                            if let Some(ret) = self.execute_statement(stmt)? {
-                                result = ret;
-                                break;
-                            }
+            "input" => {
+                if args.len() > 1 {
+                    return Err(FenicsError::type_error("input() takes at most 1 argument"));
+                }
+                if let Some(prompt) = args.first() {
+                    print!("{}", self.evaluate_expression(prompt)?);
+                    io::stdout().flush().map_err(|e| {
+                        FenicsError::io(format!("Failed to write the prompt: {}", e))
+                    })?;
+                }
+                let mut line = String::new();
+                let read = io::stdin()
+                    .read_line(&mut line)
+                    .map_err(|e| FenicsError::io(format!("Failed to read input: {}", e)))?;
+                // null at the end of input, so a loop can tell it from an empty line
+                if read == 0 {
+                    return Ok(Value::Null);
+                }
+                let line = line.strip_suffix('\n').unwrap_or(&line);
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                Ok(Value::String(line.to_string()))
+            }
+            "type" | "str" | "int" | "float" | "decimal" | "bool" => {
+                if args.len() != 1 {
+                    return Err(FenicsError::type_error(format!(
+                        "{}() takes exactly 1 argument",
+                        name
+                    )));
+                }
+                let val = self.evaluate_expression(&args[0])?;
+                match name {
+                    "type" => Ok(Value::String(val.type_name().to_string())),
+                    "str" => Ok(Value::String(val.to_string())),
+                    "int" => to_int(&val),
+                    "float" => to_float(&val),
+                    "decimal" => to_decimal(&val),
+                    _ => Ok(Value::Boolean(val.is_truthy())),
+                }
+            }
+            "copy" | "deep_copy" => {
+                if args.len() != 1 {
+                    return Err(FenicsError::type_error(format!(
+                        "{}() takes exactly 1 argument",
+                        name
+                    )));
+                }
+                let val = self.evaluate_expression(&args[0])?;
+                Ok(match name {
+                    "copy" => val.shallow_copy(),
+                    _ => val.deep_copy(),
+                })
+            }
+            "parse_int" => {
+                if args.is_empty() || args.len() > 2 {
+                    return Err(FenicsError::type_error(
+                        "parse_int() takes 1 or 2 arguments",
+                    ));
+                }
+                let text = match self.evaluate_expression(&args[0])? {
+                    Value::String(s) => s,
+                    other => {
+                        return Err(FenicsError::type_error(format!(
+                            "parse_int() requires a String, got {}",
+                            other.type_name()
+                        )))
+                    }
+                };
+                let radix = match args.get(1) {
+                    Some(arg) => match self.evaluate_expression(arg)? {
+                        Value::Integer(radix) => radix,
+                        other => {
+                            return Err(FenicsError::type_error(format!(
+                                "parse_int() radix must be an Int, got {}",
+                                other.type_name()
+                            )))
                         }
+                    },
+                    None => 10,
+                };
+                parse_int(&text, radix)
+            }
+            _ => self.call_variable(name, args, call_site),
+        }
+    }
 
-                        self.locals.pop();
-                        Ok(result)
-                    }
-                    _ => Err(format!("'{}' is not a function", name)),
+    /// Call the function held by the variable `name`.
+    fn call_variable(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+        call_site: Span,
+    ) -> Result<Value, FenicsError> {
+        let func = self.get_variable(name)?;
+        match &func {
+            Value::Function { params, .. } => {
+                if args.len() != params.len() {
+                    return Err(FenicsError::type_error(format!(
+                        "Function '{}' expects {} arguments, got {}",
+                        name,
+                        params.len(),
+                        args.len()
+                    )));
                 }
+                self.call_function_value(&func, args, call_site)
             }
+            _ => Err(FenicsError::type_error(format!(
+                "'{}' is not a function",
+                name
+            ))),
         }
     }
 
@@ -71,7 +162,8 @@ impl Interpreter {
         obj: &Value,
         method: &str,
         args: &[Expression],
-    ) -> Result<Value, String> {
+        call_site: Span,
+    ) -> Result<Value, FenicsError> {
         match (obj, method) {
             (Value::BridgeModule(module_name), m) => {
                 let mut eval_args = Vec::new();
@@ -80,154 +172,47 @@ impl Interpreter {
                 }
                 match self.bridges.get(module_name) {
                     Some(bridge) => bridge.call(m, &eval_args),
-                    None => Err(format!("Bridge '{}' not registered", module_name)),
+                    None => Err(FenicsError::name(format!(
+                        "Bridge '{}' not registered",
+                        module_name
+                    ))),
                 }
             }
-            (Value::Array(arr), "reverse") => {
-                let mut reversed = arr.clone();
-                reversed.reverse();
-                Ok(Value::Array(reversed))
+            (Value::Array(arr), m) => {
+                let items = arr.borrow().clone();
+                self.call_array_method(&items, m, args, call_site)
             }
-            (Value::Array(arr), "sort") => {
+            (Value::Range(range), "has") => {
                 if args.len() != 1 {
-                    return Err("sort() takes exactly 1 argument".to_string());
+                    return Err(FenicsError::type_error("has() takes exactly 1 argument"));
                 }
-                let order_val = self.evaluate_expression(&args[0])?;
-                let order = if let Value::String(s) = order_val {
-                    s
-                } else {
-                    return Err("sort() requires a string order like '0-9' or 'a-z'".to_string());
+                let found = match self.evaluate_expression(&args[0])? {
+                    Value::Integer(i) => range.contains(i),
+                    Value::Float(f) if f.fract() == 0.0 => range.contains(f as i64),
+                    _ => false,
                 };
-
-                let mut sorted = arr.clone();
-                match order.as_str() {
-                    "0-9" => {
-                        sorted.sort_by(|a, b| {
-                            let na = match a {
-                                Value::Integer(i) => *i as f64,
-                                Value::Float(f) => *f,
-                                _ => return std::cmp::Ordering::Greater,
-                            };
-                            let nb = match b {
-                                Value::Integer(i) => *i as f64,
-                                Value::Float(f) => *f,
-                                _ => return std::cmp::Ordering::Less,
-                            };
-                            na.partial_cmp(&nb).unwrap_or(std::cmp::Ordering::Equal)
-                        });
-                        for v in &sorted {
-                            match v {
-                                Value::Integer(_) | Value::Float(_) => {}
-                                _ => return Err("sort('0-9') requires numeric array".to_string()),
-                            }
-                        }
-                        Ok(Value::Array(sorted))
-                    }
-                    "9-0" => {
-                        sorted.sort_by(|a, b| {
-                            let na = match a {
-                                Value::Integer(i) => *i as f64,
-                                Value::Float(f) => *f,
-                                _ => return std::cmp::Ordering::Less,
-                            };
-                            let nb = match b {
-                                Value::Integer(i) => *i as f64,
-                                Value::Float(f) => *f,
-                                _ => return std::cmp::Ordering::Greater,
-                            };
-                            nb.partial_cmp(&na).unwrap_or(std::cmp::Ordering::Equal)
-                        });
-                        for v in &sorted {
-                            match v {
-                                Value::Integer(_) | Value::Float(_) => {}
-                                _ => return Err("sort('9-0') requires numeric array".to_string()),
-                            }
-                        }
-                        Ok(Value::Array(sorted))
-                    }
-                    "a-z" => {
-                        sorted.sort_by(|a, b| {
-                            let sa = match a {
-                                Value::String(s) => s,
-                                _ => return std::cmp::Ordering::Greater,
-                            };
-                            let sb = match b {
-                                Value::String(s) => s,
-                                _ => return std::cmp::Ordering::Less,
-                            };
-                            sa.cmp(sb)
-                        });
-                        for v in &sorted {
-                            match v {
-                                Value::String(_) => {}
-                                _ => return Err("sort('a-z') requires string array".to_string()),
-                            }
-                        }
-                        Ok(Value::Array(sorted))
-                    }
-                    "z-a" => {
-                        sorted.sort_by(|a, b| {
-                            let sa = match a {
-                                Value::String(s) => s,
-                                _ => return std::cmp::Ordering::Less,
-                            };
-                            let sb = match b {
-                                Value::String(s) => s,
-                                _ => return std::cmp::Ordering::Greater,
-                            };
-                            sb.cmp(sa)
-                        });
-                        for v in &sorted {
-                            match v {
-                                Value::String(_) => {}
-                                _ => return Err("sort('z-a') requires string array".to_string()),
-                            }
-                        }
-                        Ok(Value::Array(sorted))
-                    }
-                    _ => {
-                        Err("Unsupported sort order. Use '0-9', '9-0', 'a-z', or 'z-a'".to_string())
-                    }
-                }
-            }
-            (Value::Array(arr), "has") => {
-                if args.len() != 1 {
-                    return Err("has() takes exactly 1 argument".to_string());
-                }
-                let search_val = self.evaluate_expression(&args[0])?;
-                Ok(Value::Boolean(arr.contains(&search_val)))
+                Ok(Value::Boolean(found))
             }
-            (Value::String(s), "split") => {
-                if args.len() != 1 {
-                    return Err("split() takes exactly 1 argument".to_string());
-                }
-                let delimiter = self.evaluate_expression(&args[0])?;
-                if let Value::String(delim) = delimiter {
-                    let parts: Vec<Value> = s
-                        .split(&delim)
-                        .map(|p| Value::String(p.to_string()))
-                        .collect();
-                    Ok(Value::Array(parts))
-                } else {
-                    Err("split() requires a string delimiter".to_string())
-                }
-            }
-            (Value::Object(_), "keys") => {
-                if let Value::Object(obj) = obj {
-                    let keys: Vec<Value> = obj.keys().map(|k| Value::String(k.clone())).collect();
-                    Ok(Value::Array(keys))
-                } else {
-                    unreachable!()
+            (Value::String(s), m) => {
+                let Some(string_method) = string_method(m) else {
+                    return Err(FenicsError::type_error(format!("Method '{}' not found", m)));
+                };
+                let mut values = Vec::new();
+                for a in args {
+                    values.push(self.evaluate_expression(a)?);
                 }
+                string_method.call(s, &values)
             }
-            (Value::Object(map), m) => {
-                if let Some(func) = map.get(m) {
-                    self.call_function_value(func, args)
-                } else {
-                    Err(format!("Method '{}' not found", m))
-                }
+            (Value::Object(fields), m) => self.call_object_method(fields, m, args, call_site),
+            // A caught error can be read like an object, but not changed
+            (Value::Error(err), m @ ("keys" | "values" | "entries" | "has" | "get")) => {
+                let fields = Rc::new(RefCell::new(Self::error_fields(err)));
+                self.call_object_method(&fields, m, args, call_site)
             }
-            _ => Err(format!("Method '{}' not found", method)),
+            _ => Err(FenicsError::type_error(format!(
+                "Method '{}' not found",
+                method
+            ))),
         }
     }
 
@@ -235,34 +220,129 @@ impl Interpreter {
         &mut self,
         func: &Value,
         args: &[Expression],
-    ) -> Result<Value, String> {
+        call_site: Span,
+    ) -> Result<Value, FenicsError> {
+        let mut values = Vec::new();
+        for a in args {
+            values.push(self.evaluate_expression(a)?);
+        }
+        self.invoke_function(func, values, call_site)
+    }
+
+    /// Call a function value with evaluated arguments, from `call_site` in the running file.
+    pub(crate) fn invoke_function(
+        &mut self,
+        func: &Value,
+        args: Vec<Value>,
+        call_site: Span,
+    ) -> Result<Value, FenicsError> {
         match func {
-            Value::Function { params, body } => {
+            Value::Function {
+                name,
+                params,
+                return_type,
+                body,
+                env,
+                module,
+            } => {
                 if args.len() != params.len() {
-                    return Err(format!(
+                    return Err(FenicsError::type_error(format!(
                         "Function takes {} arguments, but {} provided",
                         params.len(),
                         args.len()
-                    ));
-                }
-                self.locals.push(std::collections::HashMap::new());
-                for (p, a) in params.iter().zip(args.iter()) {
-                    let val = self.evaluate_expression(a)?;
-                    self.locals.last_mut().unwrap().insert(p.name.clone(), val);
+                    )));
                 }
+                let describe = || match name {
+                    Some(name) => format!("'{}'", name),
+                    None => "anonymous function".to_string(),
+                };
 
-                let mut result = Value::Null;
-                for stmt in body {
-                    if let Some(ret) = self.execute_statement(stmt)? {
-                        result = ret;
-                        break;
+                let mut bindings = Vec::with_capacity(params.len());
+                for (p, val) in params.iter().zip(args) {
+                    let value = match &p.type_annotation {
+                        Some(ty) => conform(val, ty).map_err(|found| {
+                            FenicsError::type_error(format!(
+                                "Type mismatch for parameter '{}' of {}: expected {}, got {}",
+                                p.name,
+                                describe(),
+                                ty,
+                                found
+                            ))
+                        })?,
+                        None => val,
+                    };
+                    if let Some(ty) = &p.type_annotation {
+                        self.collection_types.register(&p.name, &value, ty);
                     }
+                    let binding = Binding {
+                        value,
+                        is_const: false,
+                        type_annotation: p.type_annotation.clone(),
+                    };
+                    bindings.push((&p.name, binding));
+                }
+
+                if self.call_stack.len() >= MAX_CALL_DEPTH {
+                    return Err(FenicsError::runtime(format!(
+                        "Maximum call depth of {} exceeded in {}",
+                        MAX_CALL_DEPTH,
+                        describe()
+                    ))
+                    .with_hint("check that the recursion reaches its base case")
+                    .with_stack(self.backtrace()));
                 }
+                self.call_stack.push(CallFrame {
+                    function: name.clone().unwrap_or_else(|| "<anonymous>".to_string()),
+                    module: self.module.clone(),
+                    call_site,
+                });
+                let caller_module = std::mem::replace(&mut self.module, module.clone());
+
+                // The body sees its own frame on top of the frames captured where
+                // the function was defined, never the caller's
+                let caller_locals = std::mem::replace(&mut self.locals, env.0.clone());
+
+                self.push_scope();
+                for (name, binding) in bindings {
+                    self.declare_local_binding(name, binding);
+                }
+                let result = self.run_function_body(body).map_err(|e| {
+                    let e = e.with_stack(self.backtrace());
+                    // A span from another file is rendered against that file's source
+                    if Rc::ptr_eq(module, &caller_module) {
+                        e
+                    } else {
+                        e.with_module(module.clone())
+                    }
+                });
 
-                self.locals.pop();
-                Ok(result)
+                self.locals = caller_locals;
+                self.module = caller_module;
+                self.call_stack.pop();
+                match (result?, return_type) {
+                    (value, Some(ty)) => conform(value, ty).map_err(|found| {
+                        FenicsError::type_error(format!(
+                            "Type mismatch for return value of {}: expected {}, got {}",
+                            describe(),
+                            ty,
+                            found
+                        ))
+                    }),
+                    (value, None) => Ok(value),
+                }
             }
-            _ => Err("Target is not a function".to_string()),
+            _ => Err(FenicsError::type_error("Target is not a function")),
+        }
+    }
+
+    /// Run a function body in its pushed frame, pop it, and return the call's result.
+    fn run_function_body(&mut self, body: &[Statement]) -> Result<Value, FenicsError> {
+        let outcome = self.execute_block(body);
+        self.locals.pop();
+        match outcome? {
+            ControlFlow::Normal => Ok(Value::Null),
+            ControlFlow::Return(value) => Ok(value),
+            flow => Err(flow.escaped_loop_error()),
         }
     }
 }
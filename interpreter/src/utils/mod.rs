@@ -1,5 +1,12 @@
+pub mod array_methods;
 pub mod ast;
+pub mod conversions;
 pub mod function_store;
+pub mod object_methods;
 pub mod string_interpolation;
+pub mod string_literal;
+pub mod string_methods;
+pub mod syntax_errors;
+pub mod type_checker;
 pub mod type_finder;
 pub mod variable_store;
@@ -0,0 +1,109 @@
+use crate::ast::{Expression, Span};
+use crate::error::FenicsError;
+use crate::features::{SharedObject, Value};
+use crate::interpreter::Interpreter;
+
+impl Interpreter {
+    /// Methods on objects; a field holding a function of the same name wins.
+    pub(crate) fn call_object_method(
+        &mut self,
+        fields: &SharedObject,
+        method: &str,
+        args: &[Expression],
+        call_site: Span,
+    ) -> Result<Value, FenicsError> {
+        let field = fields.borrow().get(method).cloned();
+        if let Some(func) = field {
+            return self.call_function_value(&func, args, call_site);
+        }
+
+        let mut values = Vec::new();
+        for a in args {
+            values.push(self.evaluate_expression(a)?);
+        }
+        let args = values;
+        let arity = |min: usize, max: usize| {
+            if (min..=max).contains(&args.len()) {
+                return Ok(());
+            }
+            let expected = match (min, max) {
+                (0, 0) => "no arguments",
+                (1, 1) => "exactly 1 argument",
+                _ => "1 or 2 arguments",
+            };
+            Err(FenicsError::type_error(format!(
+                "{}() takes {}",
+                method, expected
+            )))
+        };
+        let key = |args: &[Value]| match &args[0] {
+            Value::String(key) => Ok(key.clone()),
+            other => Err(FenicsError::type_error(format!(
+                "{}() requires a String key, got {}",
+                method,
+                other.type_name()
+            ))),
+        };
+
+        match method {
+            "keys" => {
+                arity(0, 0)?;
+                let keys = fields.borrow().keys().cloned().map(Value::String).collect();
+                Ok(Value::array(keys))
+            }
+            "values" => {
+                arity(0, 0)?;
+                Ok(Value::array(fields.borrow().values().cloned().collect()))
+            }
+            "entries" => {
+                arity(0, 0)?;
+                let entries = fields
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| {
+                        Value::array(vec![Value::String(key.clone()), value.clone()])
+                    })
+                    .collect();
+                Ok(Value::array(entries))
+            }
+            "has" => {
+                arity(1, 1)?;
+                Ok(Value::Boolean(fields.borrow().contains_key(&key(&args)?)))
+            }
+            // `get(key, default)`: the default, or null, for a missing key
+            "get" => {
+                arity(1, 2)?;
+                let found = fields.borrow().get(&key(&args)?).cloned();
+                Ok(found
+                    .or_else(|| args.get(1).cloned())
+                    .unwrap_or(Value::Null))
+            }
+            // Removes the field and returns its value, or null if it was missing
+            "remove" => {
+                arity(1, 1)?;
+                let removed = fields.borrow_mut().shift_remove(&key(&args)?);
+                Ok(removed.unwrap_or(Value::Null))
+            }
+            // A new object with the fields of both, in order; where both
+            // have a field, the argument's value wins but keeps the place
+            "merge" => {
+                arity(1, 1)?;
+                let Value::Object(other) = &args[0] else {
+                    return Err(FenicsError::type_error(format!(
+                        "merge() requires an Object, got {}",
+                        args[0].type_name()
+                    )));
+                };
+                let mut merged = fields.borrow().clone();
+                for (key, value) in other.borrow().iter() {
+                    merged.insert(key.clone(), value.clone());
+                }
+                Ok(Value::object(merged))
+            }
+            _ => Err(FenicsError::type_error(format!(
+                "Method '{}' not found",
+                method
+            ))),
+        }
+    }
+}
@@ -1,21 +1,22 @@
-use crate::ast::{Expression, StringPart};
-use crate::parser::{parse_expression, FenicsParser, Rule};
+use crate::ast::{Expression, ExpressionKind, StringPart};
+use crate::error::FenicsError;
+use crate::parser::{parse_expression, span_of, with_span_offset, FenicsParser, Rule};
+use crate::utils::string_literal::string_chars;
 use pest::iterators::Pair;
 use pest::Parser;
 
 /// Parse a string interpolation literal into an `Expression::StringInterpolation` node.
 pub(crate) fn parse_string_interpolation(pair: Pair<Rule>) -> Result<Expression, String> {
-    let s = pair.as_str();
-    let content = &s[1..s.len() - 1]; // Strip surrounding quotes
-
+    let span = span_of(&pair);
     let mut parts = Vec::new();
     let mut current_text = String::new();
-    let mut chars = content.chars().peekable();
+    let mut chars = string_chars(pair.as_str())?.into_iter().peekable();
 
-    while let Some(ch) = chars.next() {
-        if ch == '#' {
-            if let Some(&'{') = chars.peek() {
-                chars.next(); // consume '{'
+    while let Some((_, ch, escaped)) = chars.next() {
+        if ch == '#' && !escaped {
+            if let Some((brace, _, _)) = chars.next_if(|&(_, c, _)| c == '{') {
+                // Where the expression starts in the whole source
+                let expr_offset = span.start + brace + 1;
 
                 if !current_text.is_empty() {
                     parts.push(StringPart::Text(current_text.clone()));
@@ -24,7 +25,7 @@ pub(crate) fn parse_string_interpolation(pair: Pair<Rule>) -> Result<Expression,
 
                 let mut expr_str = String::new();
                 let mut depth = 1;
-                while let Some(ch) = chars.next() {
+                for (_, ch, _) in chars.by_ref() {
                     match ch {
                         '{' => {
                             depth += 1;
@@ -47,9 +48,8 @@ pub(crate) fn parse_string_interpolation(pair: Pair<Rule>) -> Result<Expression,
                     .into_iter()
                     .next()
                     .ok_or("No expression found in interpolation")?;
-                parts.push(StringPart::Expression(Box::new(parse_expression(
-                    expr_pair,
-                )?)));
+                let expr = with_span_offset(expr_offset, || parse_expression(expr_pair))?;
+                parts.push(StringPart::Expression(Box::new(expr)));
             } else {
                 current_text.push(ch);
             }
@@ -62,16 +62,19 @@ pub(crate) fn parse_string_interpolation(pair: Pair<Rule>) -> Result<Expression,
         parts.push(StringPart::Text(current_text));
     }
 
-    Ok(Expression::StringInterpolation { parts })
+    Ok(Expression::new(
+        ExpressionKind::StringInterpolation { parts },
+        span,
+    ))
 }
 
 /// Evaluate string parts using the provided expression evaluator.
 pub(crate) fn evaluate_string_parts<E>(
     parts: &[StringPart],
     mut eval_expr: E,
-) -> Result<String, String>
+) -> Result<String, FenicsError>
 where
-    E: FnMut(&Expression) -> Result<String, String>,
+    E: FnMut(&Expression) -> Result<String, FenicsError>,
 {
     let mut result = String::new();
 
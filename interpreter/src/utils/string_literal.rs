@@ -0,0 +1,112 @@
+/// The value of a `string` literal, given with its quotes.
+pub(crate) fn string_value(literal: &str) -> Result<String, String> {
+    Ok(string_chars(literal)?
+        .into_iter()
+        .map(|(_, c, _)| c)
+        .collect())
+}
+
+/// A string literal's decoded, dedented characters with their offsets and escapedness.
+pub(crate) fn string_chars(literal: &str) -> Result<Vec<(usize, char, bool)>, String> {
+    let (raw, literal, offset) = match literal.strip_prefix('r') {
+        Some(rest) => (true, rest, 1),
+        None => (false, literal, 0),
+    };
+    let quotes = if literal.starts_with("\"\"\"") { 3 } else { 1 };
+    let body = &literal[quotes..literal.len() - quotes];
+    let offset = offset + quotes;
+
+    let written: Vec<(usize, char)> = if quotes == 3 {
+        dedent(body)
+            .into_iter()
+            .flat_map(|(at, line)| line.char_indices().map(move |(i, c)| (at + i, c)))
+            .map(|(at, c)| (offset + at, c))
+            .collect()
+    } else {
+        body.char_indices()
+            .map(|(at, c)| (offset + at, c))
+            .collect()
+    };
+    if raw {
+        return Ok(written.into_iter().map(|(at, c)| (at, c, false)).collect());
+    }
+
+    let mut chars = Vec::with_capacity(written.len());
+    let mut written = written.into_iter().peekable();
+    while let Some((at, c)) = written.next() {
+        if c != '\\' {
+            chars.push((at, c, false));
+            continue;
+        }
+        let decoded = match written.next().map(|(_, c)| c) {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '"' | '#')) => c,
+            Some('u') => {
+                let digits: String = match written.next_if(|&(_, c)| c == '{') {
+                    Some(_) => written
+                        .by_ref()
+                        .map(|(_, c)| c)
+                        .take_while(|&c| c != '}')
+                        .collect(),
+                    None => String::new(),
+                };
+                (1..=6)
+                    .contains(&digits.len())
+                    .then(|| u32::from_str_radix(&digits, 16).ok())
+                    .flatten()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid unicode escape '\\u{{{}}}'; expected 1 to 6 hex digits naming a character",
+                            digits
+                        )
+                    })?
+            }
+            Some(c) => {
+                return Err(format!(
+                    "Unknown escape sequence '\\{}'",
+                    c.escape_default()
+                ))
+            }
+            None => return Err("Unfinished escape sequence at the end of the string".to_string()),
+        };
+        chars.push((at, decoded, true));
+    }
+    Ok(chars)
+}
+
+/// A triple-quoted string's body lines with offsets, minus their shared indentation.
+fn dedent(body: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut at = 0;
+    for line in body.split_inclusive('\n') {
+        lines.push((at, line));
+        at += line.len();
+    }
+    if lines.len() > 1 && lines[0].1.trim_end_matches(['\r', '\n']).is_empty() {
+        lines.remove(0);
+    }
+    if lines.len() > 1 && lines[lines.len() - 1].1.trim().is_empty() {
+        lines.pop();
+        let (at, last) = lines.pop().unwrap_or_default();
+        lines.push((at, last.trim_end_matches(['\r', '\n'])));
+    }
+
+    let indent_of = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+    let common = lines
+        .iter()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(_, line)| indent_of(line))
+        .min()
+        .unwrap_or(0);
+    lines
+        .into_iter()
+        .map(|(at, line)| {
+            let cut = common.min(indent_of(line));
+            (at + cut, &line[cut..])
+        })
+        .collect()
+}
@@ -0,0 +1,310 @@
+use crate::error::FenicsError;
+use crate::features::Value;
+
+/// Size in bytes past which `repeat` and padding give up instead of allocating.
+const MAX_STRING_LEN: usize = 1 << 30;
+
+/// A string method: its name, argument count bounds, and implementation.
+pub(crate) struct StringMethod {
+    name: &'static str,
+    arity: (usize, usize),
+    call: fn(&str, &[Value]) -> Result<Value, FenicsError>,
+}
+
+impl StringMethod {
+    pub(crate) fn call(&self, s: &str, args: &[Value]) -> Result<Value, FenicsError> {
+        self.check_arity(args.len())?;
+        (self.call)(s, args)
+    }
+
+    pub(crate) fn check_arity(&self, given: usize) -> Result<(), FenicsError> {
+        let (min, max) = self.arity;
+        if (min..=max).contains(&given) {
+            return Ok(());
+        }
+        let expected = match (min, max) {
+            (0, 0) => "no arguments".to_string(),
+            (1, 1) => "exactly 1 argument".to_string(),
+            (min, max) if min == max => format!("exactly {} arguments", min),
+            (min, max) => format!("{} or {} arguments", min, max),
+        };
+        Err(FenicsError::type_error(format!(
+            "{}() takes {}",
+            self.name, expected
+        )))
+    }
+}
+
+/// Indices and lengths are counted in characters, not bytes.
+pub(crate) const STRING_METHODS: &[StringMethod] = &[
+    StringMethod {
+        name: "upper",
+        arity: (0, 0),
+        call: |s, _| Ok(string(s.to_uppercase())),
+    },
+    StringMethod {
+        name: "lower",
+        arity: (0, 0),
+        call: |s, _| Ok(string(s.to_lowercase())),
+    },
+    StringMethod {
+        name: "trim",
+        arity: (0, 0),
+        call: |s, _| Ok(string(s.trim())),
+    },
+    StringMethod {
+        name: "trim_start",
+        arity: (0, 0),
+        call: |s, _| Ok(string(s.trim_start())),
+    },
+    StringMethod {
+        name: "trim_end",
+        arity: (0, 0),
+        call: |s, _| Ok(string(s.trim_end())),
+    },
+    StringMethod {
+        name: "starts_with",
+        arity: (1, 1),
+        call: |s, args| {
+            Ok(Value::Boolean(s.starts_with(string_arg(
+                "starts_with",
+                args,
+                0,
+            )?)))
+        },
+    },
+    StringMethod {
+        name: "ends_with",
+        arity: (1, 1),
+        call: |s, args| {
+            Ok(Value::Boolean(s.ends_with(string_arg(
+                "ends_with",
+                args,
+                0,
+            )?)))
+        },
+    },
+    StringMethod {
+        name: "contains",
+        arity: (1, 1),
+        call: |s, args| match &args[0] {
+            Value::String(part) => Ok(Value::Boolean(s.contains(part.as_str()))),
+            Value::Regex(regex) => Ok(Value::Boolean(regex.is_match(s))),
+            other => Err(FenicsError::type_error(format!(
+                "contains() requires a String or Regex, got {}",
+                other.type_name()
+            ))),
+        },
+    },
+    StringMethod {
+        name: "index_of",
+        arity: (1, 1),
+        // -1 when the string doesn't occur
+        call: |s, args| {
+            let index = s
+                .find(string_arg("index_of", args, 0)?)
+                .map_or(-1, |at| s[..at].chars().count() as i64);
+            Ok(Value::Integer(index))
+        },
+    },
+    StringMethod {
+        name: "slice",
+        arity: (1, 2),
+        call: slice,
+    },
+    StringMethod {
+        name: "chars",
+        arity: (0, 0),
+        call: |s, _| {
+            Ok(Value::array(
+                s.chars().map(|c| string(c.to_string())).collect(),
+            ))
+        },
+    },
+    StringMethod {
+        name: "bytes",
+        arity: (0, 0),
+        call: |s, _| {
+            Ok(Value::array(
+                s.bytes().map(|b| Value::Integer(b as i64)).collect(),
+            ))
+        },
+    },
+    StringMethod {
+        name: "lines",
+        arity: (0, 0),
+        call: |s, _| Ok(Value::array(s.lines().map(string).collect())),
+    },
+    StringMethod {
+        name: "repeat",
+        arity: (1, 1),
+        call: |s, args| match int_arg("repeat", args, 0)? {
+            count if count >= 0 => {
+                let count = usize::try_from(count).unwrap_or(usize::MAX);
+                check_length("repeat", s.len().checked_mul(count))?;
+                Ok(string(s.repeat(count)))
+            }
+            count => Err(FenicsError::type_error(format!(
+                "repeat() count can't be negative, got {}",
+                count
+            ))),
+        },
+    },
+    StringMethod {
+        name: "pad_start",
+        arity: (1, 2),
+        call: |s, args| pad("pad_start", s, args, true),
+    },
+    StringMethod {
+        name: "pad_end",
+        arity: (1, 2),
+        call: |s, args| pad("pad_end", s, args, false),
+    },
+    StringMethod {
+        name: "split",
+        arity: (1, 1),
+        call: |s, args| match &args[0] {
+            Value::String(delim) => Ok(Value::array(s.split(delim.as_str()).map(string).collect())),
+            Value::Regex(regex) => Ok(regex.split(s)),
+            _ => Err(FenicsError::type_error(
+                "split() requires a string or regex delimiter",
+            )),
+        },
+    },
+    StringMethod {
+        name: "replace",
+        arity: (2, 2),
+        call: |s, args| replace("replace", s, args, false),
+    },
+    StringMethod {
+        name: "replace_all",
+        arity: (2, 2),
+        call: |s, args| replace("replace_all", s, args, true),
+    },
+    StringMethod {
+        name: "test",
+        arity: (1, 1),
+        call: |s, args| match &args[0] {
+            Value::Regex(regex) => Ok(Value::Boolean(regex.is_match(s))),
+            _ => Err(FenicsError::type_error("test() requires a regex")),
+        },
+    },
+    StringMethod {
+        name: "match",
+        arity: (1, 1),
+        call: |s, args| match &args[0] {
+            Value::Regex(regex) => Ok(regex.find(s)),
+            _ => Err(FenicsError::type_error("match() requires a regex")),
+        },
+    },
+    StringMethod {
+        name: "match_all",
+        arity: (1, 1),
+        call: |s, args| match &args[0] {
+            Value::Regex(regex) => Ok(regex.find_all(s)),
+            _ => Err(FenicsError::type_error("match_all() requires a regex")),
+        },
+    },
+];
+
+pub(crate) fn string_method(name: &str) -> Option<&'static StringMethod> {
+    STRING_METHODS.iter().find(|method| method.name == name)
+}
+
+fn string(s: impl Into<String>) -> Value {
+    Value::String(s.into())
+}
+
+fn string_arg<'a>(method: &str, args: &'a [Value], i: usize) -> Result<&'a str, FenicsError> {
+    match &args[i] {
+        Value::String(s) => Ok(s),
+        other => Err(FenicsError::type_error(format!(
+            "{}() requires a String, got {}",
+            method,
+            other.type_name()
+        ))),
+    }
+}
+
+fn int_arg(method: &str, args: &[Value], i: usize) -> Result<i64, FenicsError> {
+    match &args[i] {
+        Value::Integer(n) => Ok(*n),
+        other => Err(FenicsError::type_error(format!(
+            "{}() requires an Int, got {}",
+            method,
+            other.type_name()
+        ))),
+    }
+}
+
+/// `slice(start, end)`: characters in a range; negative indices count from the end.
+fn slice(s: &str, args: &[Value]) -> Result<Value, FenicsError> {
+    let chars: Vec<char> = s.chars().collect();
+    let len = chars.len() as i64;
+    let clamp = |i: i64| (if i < 0 { len + i } else { i }).clamp(0, len) as usize;
+    let start = clamp(int_arg("slice", args, 0)?);
+    let end = match args.get(1) {
+        Some(_) => clamp(int_arg("slice", args, 1)?),
+        None => chars.len(),
+    };
+    Ok(string(
+        chars[start..end.max(start)].iter().collect::<String>(),
+    ))
+}
+
+/// Pad `s` to `width` characters with copies of the fill string, a space by default.
+fn pad(method: &str, s: &str, args: &[Value], start: bool) -> Result<Value, FenicsError> {
+    let width = usize::try_from(int_arg(method, args, 0)?.max(0)).unwrap_or(usize::MAX);
+    let fill = match args.get(1) {
+        Some(_) => string_arg(method, args, 1)?,
+        None => " ",
+    };
+    let missing = width.saturating_sub(s.chars().count());
+    if missing == 0 {
+        return Ok(string(s));
+    }
+    if fill.is_empty() {
+        return Err(FenicsError::type_error(format!(
+            "{}() fill string can't be empty",
+            method
+        )));
+    }
+    let widest = fill.chars().map(char::len_utf8).max().unwrap_or(1);
+    check_length(method, missing.checked_mul(widest))?;
+    let padding: String = fill.chars().cycle().take(missing).collect();
+    Ok(string(if start {
+        padding + s
+    } else {
+        format!("{}{}", s, padding)
+    }))
+}
+
+/// Fail when a string of `bytes` bytes would be too large to build.
+fn check_length(method: &str, bytes: Option<usize>) -> Result<(), FenicsError> {
+    match bytes {
+        Some(bytes) if bytes <= MAX_STRING_LEN => Ok(()),
+        _ => Err(FenicsError::runtime(format!(
+            "{}() result would be too long",
+            method
+        ))),
+    }
+}
+
+/// `replace(pattern, replacement)` and `replace_all`.
+fn replace(method: &str, s: &str, args: &[Value], all: bool) -> Result<Value, FenicsError> {
+    let Value::String(replacement) = &args[1] else {
+        return Err(FenicsError::type_error(format!(
+            "{}() requires a string replacement",
+            method
+        )));
+    };
+    match &args[0] {
+        Value::String(from) if all => Ok(string(s.replace(from.as_str(), replacement))),
+        Value::String(from) => Ok(string(s.replacen(from.as_str(), replacement, 1))),
+        Value::Regex(regex) => Ok(string(regex.replace(s, replacement, all))),
+        _ => Err(FenicsError::type_error(format!(
+            "{}() requires a string or regex pattern",
+            method
+        ))),
+    }
+}
@@ -0,0 +1,437 @@
+use crate::ast::Span;
+use crate::error::FenicsError;
+use crate::parser::Rule;
+use pest::error::{ErrorVariant, InputLocation};
+
+/// Keywords that open a block and so must end their line with ':'.
+const BLOCK_KEYWORDS: [&str; 10] = [
+    "if", "else", "for", "while", "loop", "fn", "try", "catch", "finally", "lib",
+];
+
+/// Turn a pest error from parsing `source[offset..]` into a parse error in `source`.
+pub(crate) fn syntax_error(
+    err: pest::error::Error<Rule>,
+    source: &str,
+    offset: usize,
+) -> FenicsError {
+    let pos = offset
+        + match err.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+    // Failing at the start of a line means the line before was left
+    // incomplete; point at where it ends
+    let incomplete = previous_line_end(source, offset, pos);
+    let pos = incomplete.unwrap_or(pos);
+
+    // The parser gives up on an unterminated string where it starts, so
+    // look for one on the lines leading up to the failure
+    let mut triple: Option<(usize, Quote)> = None;
+    for (start, line) in lines(source, offset, pos) {
+        match scan_line(line, triple.map(|(_, kind)| kind), |_, _, _| {}) {
+            Some((quote, Quote::Plain | Quote::Raw)) => {
+                return FenicsError::parse("Unterminated string")
+                    .with_span(Span::new(start + quote, start + line.len()))
+                    .with_hint("close the string with '\"' on the same line");
+            }
+            Some((quote, kind)) => triple = triple.or(Some((start + quote, kind))),
+            None => triple = None,
+        }
+    }
+    if let Some((quote, _)) = triple {
+        return FenicsError::parse("Unterminated string")
+            .with_span(Span::new(quote, quote + 3))
+            .with_hint("close the string with '\"\"\"'");
+    }
+
+    let first_line = statement_start(source, offset, pos);
+    if let Some(err) = lines(source, first_line, pos).find_map(|(start, line)| {
+        missing_colon(line, start).or_else(|| equals_assignment(line, start))
+    }) {
+        return err;
+    }
+    if let Some(err) = incomplete.and_then(|end| indentation_error(source, first_line, end)) {
+        return err;
+    }
+
+    let found = FenicsError::parse(format!("Unexpected {}", found_at(source, pos)))
+        .with_span(Span::new(pos, pos));
+    if incomplete.is_some() {
+        return found.with_hint("the statement on this line is incomplete");
+    }
+    let expected = match &err.variant {
+        ErrorVariant::ParsingError { positives, .. } => describe_rules(positives),
+        ErrorVariant::CustomError { .. } => Vec::new(),
+    };
+    if expected.is_empty() {
+        found
+    } else {
+        found.with_hint(format!("expected {}", expected.join(", ")))
+    }
+}
+
+/// An unexpected indent, an empty block or an unmatched dedent before `end`.
+fn indentation_error(source: &str, first_line: usize, end: usize) -> Option<FenicsError> {
+    let (prev_start, prev) = lines(source, first_line, end)
+        .filter(|(_, line)| !code_of(line).trim().is_empty())
+        .last()?;
+    let prev_code = code_of(prev);
+    let prev_indent = indent_of(prev);
+    let opens_block = prev_code.ends_with(':');
+    let next = lines(source, end, source.len())
+        .skip(1)
+        .find(|(_, line)| !code_of(line).trim().is_empty());
+
+    let indent = next.map_or("", |(_, line)| indent_of(line));
+    if opens_block && !(indent.len() > prev_indent.len() && indent.starts_with(prev_indent)) {
+        let colon = prev_start + prev_code.len() - 1;
+        return Some(
+            FenicsError::parse("Expected an indented block after ':'")
+                .with_span(Span::new(colon, colon + 1))
+                .with_hint("indent the lines of the block under this one"),
+        );
+    }
+    let (start, _) = next?;
+    let indentation = Span::new(start, start + indent.len());
+    if indent.len() > prev_indent.len() {
+        return Some(
+            FenicsError::parse("Unexpected indentation")
+                .with_span(indentation)
+                .with_hint("only the lines of a block, after a line ending with ':', are indented"),
+        );
+    }
+    let enclosing = lines(source, first_line, end)
+        .filter(|(_, line)| !code_of(line).trim().is_empty())
+        .any(|(_, line)| indent_of(line) == indent);
+    if !indent.is_empty() && !enclosing {
+        return Some(
+            FenicsError::parse("Indentation doesn't match any enclosing block")
+                .with_span(indentation)
+                .with_hint("line the statement up with the block it belongs to"),
+        );
+    }
+    None
+}
+
+/// Lines indented with inconsistent tabs and spaces.
+pub(crate) fn indentation_errors(source: &str) -> Vec<FenicsError> {
+    let mut errors = Vec::new();
+    // The first indented line sets the style for the file
+    let mut style: Option<(char, usize)> = None;
+    let mut in_string = None;
+    for (number, (start, line)) in lines(source, 0, source.len()).enumerate() {
+        let starts_in_string = in_string.is_some();
+        in_string = string_left_open(line, in_string);
+        let indent = indent_of(line);
+        if starts_in_string || indent.is_empty() || line.trim().is_empty() {
+            continue;
+        }
+        let span = Span::new(start, start + indent.len());
+        if indent.contains(' ') && indent.contains('\t') {
+            errors.push(
+                FenicsError::parse("Indentation mixes tabs and spaces")
+                    .with_span(span)
+                    .with_hint("indent with either tabs or spaces, not both"),
+            );
+            continue;
+        }
+        let c = indent.chars().next().unwrap_or(' ');
+        match style {
+            None => style = Some((c, number + 1)),
+            Some((expected, line)) if expected != c => errors.push(
+                FenicsError::parse("Indentation mixes tabs and spaces")
+                    .with_span(span)
+                    .with_hint(format!(
+                        "line {} indents with {}",
+                        line,
+                        if expected == '\t' { "tabs" } else { "spaces" }
+                    )),
+            ),
+            Some(_) => {}
+        }
+    }
+    errors
+}
+
+/// The triple-quoted string open at the end of `line`, given the one open at its start.
+fn string_left_open(line: &str, open: Option<Quote>) -> Option<Quote> {
+    scan_line(line, open, |_, _, _| {})
+        .map(|(_, quote)| quote)
+        .filter(|quote| matches!(quote, Quote::Triple | Quote::RawTriple))
+}
+
+/// The kinds of string literal, as far as where they end is concerned.
+#[derive(Clone, Copy, PartialEq)]
+enum Quote {
+    /// `"..."`, ending on its line, with escapes
+    Plain,
+    /// `r"..."`, ending on its line, without escapes
+    Raw,
+    /// `"""..."""`, which can span lines
+    Triple,
+    /// `r"""..."""`
+    RawTriple,
+}
+
+/// Walk the code on `line` up to any comment, tracking open strings.
+fn scan_line(
+    line: &str,
+    mut open: Option<Quote>,
+    mut visit: impl FnMut(usize, char, bool),
+) -> Option<(usize, Quote)> {
+    let mut opened_at = 0;
+    // Characters already visited as part of a quote or escape
+    let mut skip = 0;
+    for (i, c) in line.char_indices() {
+        if skip > 0 {
+            skip -= 1;
+            continue;
+        }
+        let rest = &line[i..];
+        let delimiter = match open {
+            None => {
+                if rest.starts_with("//") {
+                    break;
+                }
+                let after_word = line[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_');
+                let opening = if rest.starts_with("r\"\"\"") && !after_word {
+                    Some((Quote::RawTriple, 4))
+                } else if rest.starts_with("r\"") && !after_word {
+                    Some((Quote::Raw, 2))
+                } else if rest.starts_with("\"\"\"") {
+                    Some((Quote::Triple, 3))
+                } else if c == '"' {
+                    Some((Quote::Plain, 1))
+                } else {
+                    None
+                };
+                if let Some((quote, _)) = opening {
+                    open = Some(quote);
+                    opened_at = i;
+                }
+                opening.map(|(_, len)| len)
+            }
+            Some(quote) => {
+                let close = match quote {
+                    Quote::Plain | Quote::Raw => "\"",
+                    Quote::Triple | Quote::RawTriple => "\"\"\"",
+                };
+                if rest.starts_with(close) {
+                    open = None;
+                    Some(close.len())
+                } else {
+                    None
+                }
+            }
+        };
+        match delimiter {
+            Some(len) => {
+                for (j, c) in rest.char_indices().take(len) {
+                    visit(i + j, c, false);
+                }
+                skip = len - 1;
+            }
+            None if c == '\\' && matches!(open, Some(Quote::Plain | Quote::Triple)) => {
+                visit(i, c, true);
+                if let Some(next) = rest[1..].chars().next() {
+                    visit(i + 1, next, true);
+                    skip = 1;
+                }
+            }
+            None => visit(i, c, open.is_some()),
+        }
+    }
+    open.map(|quote| (opened_at, quote))
+}
+
+fn indent_of(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// The end of earlier code when only whitespace separates it from `pos`.
+fn previous_line_end(source: &str, floor: usize, pos: usize) -> Option<usize> {
+    let pos = pos.min(source.len());
+    let before = &source[floor..pos];
+    let end = floor + before.trim_end().len();
+    (end > floor && source[end..pos].contains('\n')).then_some(end)
+}
+
+/// Start of the top-level statement that contains `pos`, never before `floor`.
+pub(crate) fn statement_start(source: &str, floor: usize, pos: usize) -> usize {
+    let mut start = line_start(source, pos.min(source.len()));
+    while start > floor && !is_statement_line(&source[start..]) {
+        start = line_start(source, start - 1);
+    }
+    start.max(floor)
+}
+
+/// Start of the first top-level statement after the line holding `pos`.
+pub(crate) fn next_statement(source: &str, pos: usize) -> usize {
+    let mut next = match source[pos.min(source.len())..].find('\n') {
+        Some(i) => pos + i + 1,
+        None => return source.len(),
+    };
+    while next < source.len() && !is_statement_line(&source[next..]) {
+        next = source[next..]
+            .find('\n')
+            .map_or(source.len(), |i| next + i + 1);
+    }
+    next
+}
+
+fn line_start(source: &str, pos: usize) -> usize {
+    source[..pos].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// Whether a line starts a top-level statement rather than continuing one.
+fn is_statement_line(rest: &str) -> bool {
+    let line = rest.lines().next().unwrap_or("");
+    let word = line
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .next()
+        .unwrap_or("");
+    !(line.trim().is_empty()
+        || line.starts_with(char::is_whitespace)
+        || line.starts_with("//")
+        || line.starts_with('-')
+        || ["else", "catch", "finally"].contains(&word))
+}
+
+/// The lines overlapping `start..=end` with their start offsets.
+fn lines(source: &str, start: usize, end: usize) -> impl Iterator<Item = (usize, &str)> {
+    let end = end.min(source.len());
+    let mut at = line_start(source, start.min(end));
+    std::iter::from_fn(move || {
+        if at > end || at >= source.len() {
+            return None;
+        }
+        let len = source[at..].find('\n').unwrap_or(source.len() - at);
+        let line = (at, source[at..at + len].trim_end_matches('\r'));
+        at += len + 1;
+        Some(line)
+    })
+}
+
+/// The line without a trailing comment or string contents.
+fn code_of(line: &str) -> String {
+    let mut code = String::with_capacity(line.len());
+    scan_line(line, None, |_, c, in_string| {
+        if in_string {
+            code.push_str(&" ".repeat(c.len_utf8()));
+        } else {
+            code.push(c);
+        }
+    });
+    code.trim_end().to_string()
+}
+
+/// A block header such as `if x > 1` that is missing its ':'.
+fn missing_colon(line: &str, start: usize) -> Option<FenicsError> {
+    let code = code_of(line);
+    let trimmed = code.trim_start();
+    // Loop labels come before the keyword: `@outer for ...`
+    let header = match trimmed.strip_prefix('@') {
+        Some(rest) => rest.split_once(char::is_whitespace)?.1.trim_start(),
+        None => trimmed,
+    };
+    let keyword = keyword_of(header)?;
+    if code.contains(':') {
+        return None;
+    }
+    let end = start + code.len();
+    Some(
+        FenicsError::parse(format!("Missing ':' after the `{}` line", keyword))
+            .with_span(Span::new(end, end))
+            .with_hint("a block starts with ':' and continues on the next lines"),
+    )
+}
+
+/// The block keyword `code` starts with, if any.
+fn keyword_of(code: &str) -> Option<&'static str> {
+    BLOCK_KEYWORDS.iter().copied().find(|keyword| {
+        code.strip_prefix(keyword).is_some_and(|rest| {
+            rest.is_empty() || rest.starts_with(|c: char| c.is_whitespace() || c == '(')
+        })
+    })
+}
+
+fn starts_with_keyword(code: &str) -> bool {
+    keyword_of(code.trim_start().trim_start_matches('@')).is_some()
+}
+
+/// An assignment written with `=` (or `+=` and friends) instead of `:`.
+fn equals_assignment(line: &str, start: usize) -> Option<FenicsError> {
+    let code = code_of(line);
+    let bytes = code.as_bytes();
+    let at = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && bytes.get(i + 1) != Some(&b'=')
+            && bytes.get(i + 1) != Some(&b'>')
+            && (i == 0 || !b"=!<>".contains(&bytes[i - 1]))
+    })?;
+    // Only when the `=` comes before anything that would make it legal
+    if code[..at].contains(':') {
+        return None;
+    }
+    if starts_with_keyword(&code) {
+        return Some(
+            FenicsError::parse("Use '==' to compare, not '='")
+                .with_span(Span::new(start + at, start + at + 1)),
+        );
+    }
+    let (op_start, fixed) = match at.checked_sub(1).map(|i| bytes[i]) {
+        Some(op @ (b'+' | b'-' | b'*' | b'/' | b'%')) => (at - 1, format!("{}:", op as char)),
+        _ => (at, ":".to_string()),
+    };
+    Some(
+        FenicsError::parse(format!(
+            "Use '{}' to assign, not '{}'",
+            fixed,
+            &code[op_start..=at]
+        ))
+        .with_span(Span::new(start + op_start, start + at + 1))
+        .with_hint(format!("write `name {} value`", fixed)),
+    )
+}
+
+/// What sits at `pos`, for "Unexpected ..." messages.
+fn found_at(source: &str, pos: usize) -> String {
+    let rest = &source[pos.min(source.len())..];
+    match rest.chars().next() {
+        None => "end of input".to_string(),
+        Some('\n' | '\r') => "end of line".to_string(),
+        Some(c) if c.is_alphanumeric() || c == '_' => {
+            let word: String = rest
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            format!("`{}`", word)
+        }
+        Some(c) => format!("`{}`", c),
+    }
+}
+
+/// Readable names for the rules pest expected, operators collapsed into one.
+fn describe_rules(rules: &[Rule]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for rule in rules {
+        let debug = format!("{:?}", rule);
+        let name = if debug.ends_with("_op") {
+            "an operator".to_string()
+        } else {
+            match rule {
+                Rule::expression | Rule::primary_expression => "an expression".to_string(),
+                Rule::identifier => "a name".to_string(),
+                Rule::statement => "a statement".to_string(),
+                Rule::block => "a new line with the block".to_string(),
+                Rule::pairs_item => "an object item (`- key: value`)".to_string(),
+                Rule::EOI => "end of input".to_string(),
+                _ => debug.replace('_', " "),
+            }
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
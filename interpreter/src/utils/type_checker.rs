@@ -0,0 +1,208 @@
+use crate::ast::Type;
+use crate::features::{SharedArray, SharedObject, Value};
+use crate::utils::conversions::{to_decimal, to_float};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// Check `value` against a declared type, widening numbers and converting collections nothing else shares.
+pub(crate) fn conform(value: Value, ty: &Type) -> Result<Value, String> {
+    match (ty, value) {
+        (Type::Int, v @ (Value::Integer(_) | Value::BigInt(_)))
+        | (Type::Decimal, v @ Value::Decimal(_))
+        | (Type::Float, v @ Value::Float(_))
+        | (Type::String, v @ Value::String(_))
+        | (Type::Boolean, v @ Value::Boolean(_))
+        | (Type::Array, v @ Value::Array(_))
+        | (Type::Object, v @ Value::Object(_))
+        | (Type::Regex, v @ Value::Regex(_)) => Ok(v),
+        (Type::Float, v @ (Value::Integer(_) | Value::BigInt(_)))
+        | (Type::Decimal, v @ (Value::Integer(_) | Value::BigInt(_) | Value::Float(_))) => {
+            let converted = match ty {
+                Type::Float => to_float(&v),
+                _ => to_decimal(&v),
+            };
+            converted.map_err(|_| v.type_name().to_string())
+        }
+        // A freshly built collection is converted; a shared one is only validated below
+        (Type::List(item_type), Value::Array(items)) if Rc::strong_count(&items) == 1 => {
+            let owned = std::mem::take(&mut *items.borrow_mut());
+            let conformed = owned
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    conform(item, item_type)
+                        .map_err(|found| format!("Array with {} at index {}", found, i))
+                })
+                .collect::<Result<_, String>>()?;
+            *items.borrow_mut() = conformed;
+            Ok(Value::Array(items))
+        }
+        (Type::Pairs(key_type, value_type), Value::Object(fields))
+            if Rc::strong_count(&fields) == 1 =>
+        {
+            let owned = std::mem::take(&mut *fields.borrow_mut());
+            let conformed = owned
+                .into_iter()
+                .map(|(key, item)| {
+                    validate(&Value::String(key.clone()), key_type)
+                        .map_err(|found| format!("Object with {} key '{}'", found, key))?;
+                    let item = conform(item, value_type)
+                        .map_err(|found| format!("Object with {} at key '{}'", found, key))?;
+                    Ok((key, item))
+                })
+                .collect::<Result<_, String>>()?;
+            *fields.borrow_mut() = conformed;
+            Ok(Value::Object(fields))
+        }
+        (_, v) => validate(&v, ty).map(|()| v),
+    }
+}
+
+/// Check `value` against a declared type without converting anything.
+pub(crate) fn validate(value: &Value, ty: &Type) -> Result<(), String> {
+    match (ty, value) {
+        (Type::Int, Value::Integer(_) | Value::BigInt(_))
+        | (Type::Decimal, Value::Decimal(_))
+        | (Type::Float, Value::Float(_))
+        | (Type::String, Value::String(_))
+        | (Type::Boolean, Value::Boolean(_))
+        | (Type::Array, Value::Array(_))
+        | (Type::Object, Value::Object(_))
+        | (Type::Regex, Value::Regex(_)) => Ok(()),
+        (Type::Float, Value::Integer(_) | Value::BigInt(_)) => to_float(value)
+            .map(|_| ())
+            .map_err(|_| value.type_name().to_string()),
+        (Type::Decimal, Value::Integer(_) | Value::BigInt(_) | Value::Float(_)) => {
+            to_decimal(value)
+                .map(|_| ())
+                .map_err(|_| value.type_name().to_string())
+        }
+        (Type::List(item_type), Value::Array(items)) => {
+            items.borrow().iter().enumerate().try_for_each(|(i, item)| {
+                validate(item, item_type)
+                    .map_err(|found| format!("Array with {} at index {}", found, i))
+            })
+        }
+        (Type::Pairs(key_type, value_type), Value::Object(fields)) => {
+            fields.borrow().iter().try_for_each(|(key, item)| {
+                validate(&Value::String(key.clone()), key_type)
+                    .map_err(|found| format!("Object with {} key '{}'", found, key))?;
+                validate(item, value_type)
+                    .map_err(|found| format!("Object with {} at key '{}'", found, key))
+            })
+        }
+        _ => Err(value.type_name().to_string()),
+    }
+}
+
+/// Declared types of arrays and objects bound to typed variables, so writes through any alias are checked.
+#[derive(Default)]
+pub(crate) struct CollectionTypes {
+    entries: HashMap<usize, Entry>,
+    // Size at which entries for dropped collections are next cleared out
+    prune_at: usize,
+}
+
+struct Entry {
+    owner: Owner,
+    // The typed variables the collection was bound to, with their types
+    declared: Vec<(String, Type)>,
+}
+
+// Keeps the collection's address from being reused while its entry exists
+enum Owner {
+    Array(Weak<RefCell<Vec<Value>>>),
+    Object(Weak<RefCell<IndexMap<String, Value>>>),
+}
+
+impl Owner {
+    fn is_alive(&self) -> bool {
+        match self {
+            Owner::Array(items) => items.strong_count() > 0,
+            Owner::Object(fields) => fields.strong_count() > 0,
+        }
+    }
+}
+
+impl CollectionTypes {
+    /// Record that `value` was bound to `name` declared as `ty`.
+    pub(crate) fn register(&mut self, name: &str, value: &Value, ty: &Type) {
+        let owner = match (ty, value) {
+            (Type::List(_), Value::Array(items)) => Owner::Array(Rc::downgrade(items)),
+            (Type::Pairs(..), Value::Object(fields)) => Owner::Object(Rc::downgrade(fields)),
+            _ => return,
+        };
+        if self.entries.len() >= self.prune_at {
+            self.entries.retain(|_, entry| entry.owner.is_alive());
+            self.prune_at = (self.entries.len() * 2).max(64);
+        }
+        let address = match &owner {
+            Owner::Array(items) => items.as_ptr() as usize,
+            Owner::Object(fields) => fields.as_ptr() as usize,
+        };
+        let entry = self.entries.entry(address).or_insert(Entry {
+            owner,
+            declared: Vec::new(),
+        });
+        let declared = (name.to_string(), ty.clone());
+        if !entry.declared.contains(&declared) {
+            entry.declared.push(declared);
+        }
+    }
+
+    /// Check `value` for storing at `items[index]`; Err holds the variable, its type and what was found.
+    pub(crate) fn conform_element(
+        &self,
+        items: &SharedArray,
+        index: usize,
+        value: Value,
+    ) -> Result<Value, (String, Type, String)> {
+        self.conform_item(Rc::as_ptr(items) as usize, None, value, |found| {
+            format!("Array with {} at index {}", found, index)
+        })
+    }
+
+    /// Check `value` for storing at `fields[key]`; Err holds the variable, its type and what was found.
+    pub(crate) fn conform_field(
+        &self,
+        fields: &SharedObject,
+        key: &str,
+        value: Value,
+    ) -> Result<Value, (String, Type, String)> {
+        self.conform_item(Rc::as_ptr(fields) as usize, Some(key), value, |found| {
+            format!("Object with {} at key '{}'", found, key)
+        })
+    }
+
+    fn conform_item(
+        &self,
+        address: usize,
+        key: Option<&str>,
+        mut value: Value,
+        describe: impl Fn(String) -> String,
+    ) -> Result<Value, (String, Type, String)> {
+        let Some(entry) = self.entries.get(&address) else {
+            return Ok(value);
+        };
+        for (name, ty) in &entry.declared {
+            let item_type = match ty {
+                Type::List(item_type) => item_type,
+                Type::Pairs(key_type, value_type) => {
+                    if let Some(key) = key {
+                        validate(&Value::String(key.to_string()), key_type).map_err(|found| {
+                            let found = format!("Object with {} key '{}'", found, key);
+                            (name.clone(), ty.clone(), found)
+                        })?;
+                    }
+                    value_type
+                }
+                _ => continue,
+            };
+            value = conform(value, item_type)
+                .map_err(|found| (name.clone(), ty.clone(), describe(found)))?;
+        }
+        Ok(value)
+    }
+}
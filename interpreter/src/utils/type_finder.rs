@@ -29,6 +29,7 @@ pub(crate) fn parse_basic_type(pair: &Pair<Rule>) -> Result<Type, String> {
 	match pair.as_str() {
 		"Int" => Ok(Type::Int),
 		"Float" => Ok(Type::Float),
+		"Decimal" => Ok(Type::Decimal),
 		"String" => Ok(Type::String),
 		"Boolean" | "Bool" => Ok(Type::Boolean),
 		"Array" => Ok(Type::Array),
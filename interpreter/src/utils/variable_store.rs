@@ -1,260 +1,410 @@
-use crate::ast::{BinaryOperator, Expression, UnaryOperator};
-use crate::features::Value;
+use crate::ast::{BinaryOperator, Expression, ExpressionKind, Type, UnaryOperator};
+use crate::error::{closest_name, FenicsError};
+use crate::features::numbers::int_value;
+use crate::features::{Binding, Range, Scope, SharedArray, SharedObject, Value};
 use crate::interpreter::Interpreter;
+use crate::utils::type_checker::{conform, CollectionTypes};
+use indexmap::IndexMap;
+use num_bigint::BigInt;
 
 impl Interpreter {
-    pub(crate) fn get_variable(&self, name: &str) -> Result<Value, String> {
+    pub(crate) fn get_variable(&self, name: &str) -> Result<Value, FenicsError> {
         for scope in self.locals.iter().rev() {
-            if let Some(val) = scope.get(name) {
-                return Ok(val.clone());
+            if let Some(binding) = scope.borrow().get(name) {
+                return Ok(binding.value.clone());
             }
         }
 
         self.globals
             .get(name)
-            .cloned()
-            .ok_or_else(|| format!("Variable '{}' not found", name))
+            .map(|binding| binding.value.clone())
+            .ok_or_else(|| self.unknown_variable(name))
     }
 
-    pub(crate) fn get_property(&self, obj: &Value, property: &str) -> Result<Value, String> {
-        match (obj, property) {
-            (Value::String(s), "length") => Ok(Value::Integer(s.len() as i64)),
-            (Value::Array(arr), "length") => Ok(Value::Integer(arr.len() as i64)),
-            (Value::Array(arr), "first") => arr
-                .first()
-                .cloned()
-                .ok_or_else(|| "Array is empty".to_string()),
-            (Value::Array(arr), "last") => arr
-                .last()
-                .cloned()
-                .ok_or_else(|| "Array is empty".to_string()),
-            (Value::Object(obj), prop) => obj
-                .get(prop)
-                .cloned()
-                .ok_or_else(|| format!("Property '{}' not found", prop)),
-            _ => Err(format!("Property '{}' not found", property)),
+    /// Whether `name` has a visible binding.
+    pub(crate) fn is_defined(&self, name: &str) -> bool {
+        self.locals
+            .iter()
+            .any(|scope| scope.borrow().contains_key(name))
+            || self.globals.contains_key(name)
+    }
+
+    /// Error for a name with no visible binding, suggesting a likely typo.
+    pub(crate) fn unknown_variable(&self, name: &str) -> FenicsError {
+        let err = FenicsError::name(format!("Variable '{}' not found", name));
+        let scopes: Vec<_> = self.locals.iter().map(|scope| scope.borrow()).collect();
+        let visible = scopes
+            .iter()
+            .flat_map(|scope| scope.keys())
+            .chain(self.globals.keys())
+            .map(String::as_str);
+        match closest_name(name, visible) {
+            Some(candidate) => err.with_hint(format!("did you mean '{}'?", candidate)),
+            None => err,
+        }
+    }
+
+    fn overwrite_binding(
+        slot: &mut Binding,
+        name: &str,
+        value: Value,
+        collection_types: &mut CollectionTypes,
+    ) -> Result<(), FenicsError> {
+        if slot.is_const {
+            return Err(FenicsError::name(format!(
+                "Cannot assign to constant '{}'",
+                name
+            )));
+        }
+        slot.value = match &slot.type_annotation {
+            Some(ty) => {
+                let value =
+                    conform(value, ty).map_err(|found| Self::type_mismatch(name, ty, &found))?;
+                collection_types.register(name, &value, ty);
+                value
+            }
+            None => value,
+        };
+        Ok(())
+    }
+
+    fn type_mismatch(name: &str, ty: &Type, found: &str) -> FenicsError {
+        FenicsError::type_error(format!(
+            "Type mismatch for '{}': expected {}, got {}",
+            name, ty, found
+        ))
+    }
+
+    /// Overwrite an existing variable in the innermost frame that defines it; functions can't overwrite globals.
+    pub(crate) fn store_variable(&mut self, name: &str, value: Value) -> Result<(), FenicsError> {
+        for scope in self.locals.iter().rev() {
+            let mut scope = scope.borrow_mut();
+            if let Some(slot) = scope.get_mut(name) {
+                return Self::overwrite_binding(slot, name, value, &mut self.collection_types);
+            }
+        }
+        let in_function = !self.call_stack.is_empty();
+        match self.globals.get_mut(name) {
+            Some(slot) if in_function && !slot.is_const => Err(FenicsError::name(format!(
+                "Cannot assign to global '{}' inside a function",
+                name
+            ))
+            .with_hint(format!("write it with `global {} : ...`", name))),
+            Some(slot) => Self::overwrite_binding(slot, name, value, &mut self.collection_types),
+            None => Err(self.unknown_variable(name)),
+        }
+    }
+
+    /// Bind `name` for a plain `name : value` statement, declaring it unless it's writable.
+    pub(crate) fn bind_variable(&mut self, name: &str, value: Value) -> Result<(), FenicsError> {
+        let visible = self
+            .locals
+            .iter()
+            .any(|scope| scope.borrow().contains_key(name))
+            || (self.call_stack.is_empty() && self.globals.contains_key(name));
+        if visible {
+            self.store_variable(name, value)
+        } else {
+            self.declare_variable(name, Binding::mutable(value), false)
+        }
+    }
+
+    /// Declare a variable in the innermost frame, or among globals.
+    pub(crate) fn declare_variable(
+        &mut self,
+        name: &str,
+        binding: Binding,
+        is_global: bool,
+    ) -> Result<(), FenicsError> {
+        let is_const = binding.is_const;
+        let check = |existing: Option<&Binding>| match existing {
+            Some(binding) if binding.is_const => Err(FenicsError::name(format!(
+                "Cannot redeclare constant '{}'",
+                name
+            ))),
+            Some(_) if is_const => Err(FenicsError::name(format!(
+                "'{}' is already declared in this scope",
+                name
+            ))),
+            _ => Ok(()),
+        };
+
+        match self.locals.last() {
+            Some(scope) if !is_global => {
+                let mut scope = scope.borrow_mut();
+                check(scope.get(name))?;
+                scope.insert(name.to_string(), binding);
+            }
+            _ => {
+                check(self.globals.get(name))?;
+                self.globals.insert(name.to_string(), binding);
+            }
+        }
+        Ok(())
+    }
+
+    /// Define a mutable variable in the innermost local frame.
+    pub(crate) fn declare_local(&mut self, name: &str, value: Value) {
+        self.declare_local_binding(name, Binding::mutable(value));
+    }
+
+    pub(crate) fn declare_local_binding(&mut self, name: &str, binding: Binding) {
+        self.locals
+            .last()
+            .expect("declare_local called without a local frame")
+            .borrow_mut()
+            .insert(name.to_string(), binding);
+    }
+
+    /// Push a fresh, empty local frame.
+    pub(crate) fn push_scope(&mut self) {
+        self.locals.push(Scope::default());
+    }
+
+    pub(crate) fn get_property(&self, obj: &Value, property: &str) -> Result<Value, FenicsError> {
+        self.find_property(obj, property)?
+            .ok_or_else(|| FenicsError::index(format!("Property '{}' not found", property)))
+    }
+
+    /// The value of `obj.property`, or None when `obj` has no such property.
+    pub(crate) fn find_property(
+        &self,
+        obj: &Value,
+        property: &str,
+    ) -> Result<Option<Value>, FenicsError> {
+        Ok(match (obj, property) {
+            (Value::String(s), "length") => Some(Value::Integer(s.chars().count() as i64)),
+            (Value::Array(arr), "length") => Some(Value::Integer(arr.borrow().len() as i64)),
+            (Value::Range(range), "length") => Some(int_value(BigInt::from(range.count()))),
+            (Value::Array(arr), "first") => Some(
+                arr.borrow()
+                    .first()
+                    .cloned()
+                    .ok_or_else(|| FenicsError::index("Array is empty"))?,
+            ),
+            (Value::Array(arr), "last") => Some(
+                arr.borrow()
+                    .last()
+                    .cloned()
+                    .ok_or_else(|| FenicsError::index("Array is empty"))?,
+            ),
+            (Value::Object(obj), prop) => obj.borrow().get(prop).cloned(),
+            (Value::Error(err), prop) => Self::error_property(err, prop),
+            _ => None,
+        })
+    }
+
+    /// All properties of a caught error, for the object read methods.
+    pub(crate) fn error_fields(err: &FenicsError) -> IndexMap<String, Value> {
+        let mut names: Vec<String> = ["message", "kind", "span", "stack", "value"]
+            .map(String::from)
+            .into();
+        if let Some(Value::Object(thrown)) = err.thrown_value() {
+            names.extend(thrown.borrow().keys().cloned());
+        }
+        names
+            .into_iter()
+            .filter_map(|name| Some((name.clone(), Self::error_property(err, &name)?)))
+            .collect()
+    }
+
+    /// Properties of a caught error: `message`, `kind`, `span`, `stack` and `value`.
+    fn error_property(err: &FenicsError, property: &str) -> Option<Value> {
+        match (property, err.thrown_value()) {
+            ("message", _) => Some(Value::String(err.message().to_string())),
+            ("kind", _) => Some(Value::String(err.kind().to_string())),
+            ("span", _) => Some(match err.span() {
+                Some(span) => Value::object(IndexMap::from([
+                    ("start".to_string(), Value::Integer(span.start as i64)),
+                    ("end".to_string(), Value::Integer(span.end as i64)),
+                ])),
+                None => Value::Null,
+            }),
+            ("stack", _) => Some(Value::array(
+                err.stack()
+                    .iter()
+                    .map(|frame| Value::String(frame.to_string()))
+                    .collect(),
+            )),
+            ("value", thrown) => Some(thrown.cloned().unwrap_or(Value::Null)),
+            (prop, Some(Value::Object(obj))) => obj.borrow().get(prop).cloned(),
+            _ => None,
         }
     }
 
-    pub(crate) fn get_bracket_access(&self, obj: &Value, index: &Value) -> Result<Value, String> {
+    pub(crate) fn get_bracket_access(
+        &self,
+        obj: &Value,
+        index: &Value,
+    ) -> Result<Value, FenicsError> {
         match (obj, index) {
             (Value::Array(arr), Value::Integer(i)) => {
                 let idx = *i as usize;
-                arr.get(idx)
+                arr.borrow()
+                    .get(idx)
                     .cloned()
-                    .ok_or_else(|| "Index out of bounds".to_string())
+                    .ok_or_else(|| FenicsError::index("Index out of bounds"))
             }
             (Value::Object(obj), Value::String(key)) => obj
+                .borrow()
                 .get(key)
                 .cloned()
-                .ok_or_else(|| format!("Key '{}' not found", key)),
-            _ => Err("Invalid bracket access".to_string()),
+                .ok_or_else(|| FenicsError::index(format!("Key '{}' not found", key))),
+            (Value::Array(arr), Value::Range(range)) => {
+                let arr = arr.borrow();
+                let items = Self::slice_indices(range, arr.len())?
+                    .map(|i| arr[i].clone())
+                    .collect();
+                Ok(Value::array(items))
+            }
+            (Value::String(s), Value::Range(range)) => {
+                let chars: Vec<char> = s.chars().collect();
+                let sliced = Self::slice_indices(range, chars.len())?
+                    .map(|i| chars[i])
+                    .collect();
+                Ok(Value::String(sliced))
+            }
+            _ => Err(FenicsError::type_error("Invalid bracket access")),
+        }
+    }
+
+    /// Indices selected by slicing a sequence of `len` elements with `range`.
+    fn slice_indices(
+        range: &Range,
+        len: usize,
+    ) -> Result<impl Iterator<Item = usize> + '_, FenicsError> {
+        if let Some(bad) = range.iter().find(|&i| i < 0 || i as usize >= len) {
+            return Err(FenicsError::index(format!(
+                "Slice {} out of bounds: index {} for length {}",
+                range, bad, len
+            )));
         }
+        Ok(range.iter().map(|i| i as usize))
     }
 
+    /// Assign to a variable, an array element or an object field.
     pub(crate) fn assign_value(
         &mut self,
         target: &Expression,
         op: &BinaryOperator,
         right_val: Value,
-    ) -> Result<Value, String> {
-        match target {
-            Expression::Identifier(name) => {
-                let new_val = match op {
-                    BinaryOperator::Assign => right_val,
-                    BinaryOperator::AddAssign => {
-                        let current = self.get_variable(name)?;
-                        self.evaluate_binary_op(&current, &BinaryOperator::Add, &right_val)?
-                    }
-                    BinaryOperator::SubAssign => {
-                        let current = self.get_variable(name)?;
-                        self.evaluate_binary_op(&current, &BinaryOperator::Subtract, &right_val)?
-                    }
-                    BinaryOperator::MulAssign => {
-                        let current = self.get_variable(name)?;
-                        self.evaluate_binary_op(&current, &BinaryOperator::Multiply, &right_val)?
-                    }
-                    BinaryOperator::DivAssign => {
-                        let current = self.get_variable(name)?;
-                        self.evaluate_binary_op(&current, &BinaryOperator::Divide, &right_val)?
-                    }
-                    BinaryOperator::ModAssign => {
-                        let current = self.get_variable(name)?;
-                        self.evaluate_binary_op(&current, &BinaryOperator::Modulo, &right_val)?
+    ) -> Result<Value, FenicsError> {
+        if let ExpressionKind::EphemeralVar(name) = &target.kind {
+            self.ephemerals.insert(name.clone(), right_val.clone());
+            return Ok(right_val);
+        }
+        let op = match op {
+            BinaryOperator::Assign => None,
+            BinaryOperator::AddAssign => Some(BinaryOperator::Add),
+            BinaryOperator::SubAssign => Some(BinaryOperator::Subtract),
+            BinaryOperator::MulAssign => Some(BinaryOperator::Multiply),
+            BinaryOperator::DivAssign => Some(BinaryOperator::Divide),
+            BinaryOperator::ModAssign => Some(BinaryOperator::Modulo),
+            _ => return Err(FenicsError::type_error("Invalid assignment operator")),
+        };
+        self.update_target(target, |this, current| match op {
+            Some(op) => this.evaluate_binary_op(&current?, &op, &right_val),
+            None => Ok(right_val),
+        })
+    }
+
+    pub(crate) fn increment_decrement(
+        &mut self,
+        target: &Expression,
+        op: &UnaryOperator,
+    ) -> Result<Value, FenicsError> {
+        self.update_target(target, |this, current| {
+            this.evaluate_unary_op(op, &current?)
+        })
+    }
+
+    /// Store `update` of the target's current value, checked against the type of the place it goes to.
+    fn update_target(
+        &mut self,
+        target: &Expression,
+        update: impl FnOnce(&mut Self, Result<Value, FenicsError>) -> Result<Value, FenicsError>,
+    ) -> Result<Value, FenicsError> {
+        let place = self.place(target)?;
+        // A missing field can be assigned but not updated
+        let current = match &place {
+            Place::Variable(name) => Ok(self.get_variable(name)?),
+            Place::Element(items, i) => Ok(items.borrow()[*i].clone()),
+            Place::Field(fields, key) => fields.borrow().get(key).cloned().ok_or_else(|| {
+                let message = match target.kind {
+                    ExpressionKind::PropertyAccess { .. } => {
+                        format!("Property '{}' not found", key)
                     }
-                    _ => return Err("Invalid assignment operator".to_string()),
+                    _ => format!("Key '{}' not found", key),
                 };
+                FenicsError::index(message)
+            }),
+        };
+        let new_val = update(self, current)?;
 
-                for scope in self.locals.iter_mut().rev() {
-                    if scope.contains_key(name) {
-                        scope.insert(name.clone(), new_val.clone());
-                        return Ok(new_val);
-                    }
-                }
-                if self.globals.contains_key(name) {
-                    self.globals.insert(name.clone(), new_val.clone());
-                    return Ok(new_val);
-                }
-                Err(format!("Variable '{}' not found", name))
+        let mismatch =
+            |(name, ty, found): (String, Type, String)| Self::type_mismatch(&name, &ty, &found);
+        match place {
+            Place::Variable(name) => {
+                self.store_variable(&name, new_val.clone())?;
+                Ok(new_val)
             }
-            Expression::PropertyAccess { object, property } => {
-                let obj = self.evaluate_expression(object)?;
-                match obj {
-                    Value::Object(mut map) => {
-                        let new_val = if let Some(current) = map.get(property) {
-                            match op {
-                                BinaryOperator::Assign => right_val,
-                                BinaryOperator::AddAssign => self.evaluate_binary_op(
-                                    current,
-                                    &BinaryOperator::Add,
-                                    &right_val,
-                                )?,
-                                BinaryOperator::SubAssign => self.evaluate_binary_op(
-                                    current,
-                                    &BinaryOperator::Subtract,
-                                    &right_val,
-                                )?,
-                                BinaryOperator::MulAssign => self.evaluate_binary_op(
-                                    current,
-                                    &BinaryOperator::Multiply,
-                                    &right_val,
-                                )?,
-                                BinaryOperator::DivAssign => self.evaluate_binary_op(
-                                    current,
-                                    &BinaryOperator::Divide,
-                                    &right_val,
-                                )?,
-                                BinaryOperator::ModAssign => self.evaluate_binary_op(
-                                    current,
-                                    &BinaryOperator::Modulo,
-                                    &right_val,
-                                )?,
-                                _ => return Err("Invalid assignment operator".to_string()),
-                            }
-                        } else {
-                            right_val
-                        };
-                        map.insert(property.clone(), new_val.clone());
-                        Ok(new_val)
-                    }
-                    _ => Err("Can only access properties on objects".to_string()),
+            Place::Element(items, i) => {
+                let new_val = self
+                    .collection_types
+                    .conform_element(&items, i, new_val)
+                    .map_err(mismatch)?;
+                match items.borrow_mut().get_mut(i) {
+                    Some(slot) => *slot = new_val.clone(),
+                    None => return Err(FenicsError::index(format!("Index {} out of bounds", i))),
                 }
+                Ok(new_val)
             }
-            Expression::BracketAccess { object, index } => {
-                let index_val = self.evaluate_expression(index)?;
-
-                match object.as_ref() {
-                    Expression::Identifier(name) => {
-                        let mut obj = self.get_variable(name)?;
-
-                        match (&mut obj, &index_val) {
-							(Value::Array(arr), Value::Integer(idx)) => {
-								let idx = *idx as usize;
-								if idx >= arr.len() {
-									return Err(format!("Index {} out of bounds", idx));
-								}
-
-								let new_val = match op {
-									BinaryOperator::Assign => right_val,
-									_ => {
-										let current = &arr[idx];
-										match op {
-											BinaryOperator::AddAssign => self.evaluate_binary_op(current, &BinaryOperator::Add, &right_val)?,
-											BinaryOperator::SubAssign => self.evaluate_binary_op(current, &BinaryOperator::Subtract, &right_val)?,
-											BinaryOperator::MulAssign => self.evaluate_binary_op(current, &BinaryOperator::Multiply, &right_val)?,
-											BinaryOperator::DivAssign => self.evaluate_binary_op(current, &BinaryOperator::Divide, &right_val)?,
-											BinaryOperator::ModAssign => self.evaluate_binary_op(current, &BinaryOperator::Modulo, &right_val)?,
-											_ => return Err("Invalid assignment operator".to_string()),
-										}
-									}
-								};
-								arr[idx] = new_val.clone();
-
-								for scope in self.locals.iter_mut().rev() {
-									if scope.contains_key(name) {
-										scope.insert(name.clone(), obj.clone());
-										return Ok(new_val);
-									}
-								}
-								if self.globals.contains_key(name) {
-									self.globals.insert(name.clone(), obj.clone());
-									return Ok(new_val);
-								}
-								Err(format!("Variable '{}' not found", name))
-							}
-							(Value::Object(map), Value::String(key)) => {
-								let new_val = if let Some(current) = map.get(key.as_str()) {
-									match op {
-										BinaryOperator::Assign => right_val,
-										BinaryOperator::AddAssign => self.evaluate_binary_op(current, &BinaryOperator::Add, &right_val)?,
-										BinaryOperator::SubAssign => self.evaluate_binary_op(current, &BinaryOperator::Subtract, &right_val)?,
-										BinaryOperator::MulAssign => self.evaluate_binary_op(current, &BinaryOperator::Multiply, &right_val)?,
-										BinaryOperator::DivAssign => self.evaluate_binary_op(current, &BinaryOperator::Divide, &right_val)?,
-										BinaryOperator::ModAssign => self.evaluate_binary_op(current, &BinaryOperator::Modulo, &right_val)?,
-										_ => return Err("Invalid assignment operator".to_string()),
-									}
-								} else {
-									right_val
-								};
-								map.insert(key.clone(), new_val.clone());
-
-								for scope in self.locals.iter_mut().rev() {
-									if scope.contains_key(name) {
-										scope.insert(name.clone(), obj.clone());
-										return Ok(new_val);
-									}
-								}
-								if self.globals.contains_key(name) {
-									self.globals.insert(name.clone(), obj.clone());
-									return Ok(new_val);
-								}
-								Err(format!("Variable '{}' not found", name))
-							}
-							_ => Err("Bracket access requires an array with integer index or object with string key".to_string()),
-						}
-                    }
-                    _ => Err(
-                        "Bracket access assignment only works on identifier variables".to_string(),
-                    ),
-                }
+            Place::Field(fields, key) => {
+                let new_val = self
+                    .collection_types
+                    .conform_field(&fields, &key, new_val)
+                    .map_err(mismatch)?;
+                fields.borrow_mut().insert(key, new_val.clone());
+                Ok(new_val)
             }
-            Expression::EphemeralVar(name) => {
-                self.ephemerals.insert(name.clone(), right_val.clone());
-                Ok(right_val)
-            }
-            _ => Err("Invalid assignment target".to_string()),
         }
     }
 
-    pub(crate) fn increment_decrement(
-        &mut self,
-        target: &Expression,
-        op: &UnaryOperator,
-    ) -> Result<Value, String> {
-        match target {
-            Expression::Identifier(name) => {
-                let current = self.get_variable(name)?;
-                let new_val = match (op, &current) {
-                    (UnaryOperator::Increment, Value::Integer(i)) => Value::Integer(i + 1),
-                    (UnaryOperator::Decrement, Value::Integer(i)) => Value::Integer(i - 1),
-                    (UnaryOperator::Increment, Value::Float(f)) => Value::Float(f + 1.0),
-                    (UnaryOperator::Decrement, Value::Float(f)) => Value::Float(f - 1.0),
-                    _ => return Err("Increment/decrement only works on numbers".to_string()),
-                };
-
-                for scope in self.locals.iter_mut().rev() {
-                    if scope.contains_key(name) {
-                        scope.insert(name.clone(), new_val.clone());
-                        return Ok(new_val);
-                    }
+    /// Where an assignment to `target` writes.
+    fn place(&mut self, target: &Expression) -> Result<Place, FenicsError> {
+        match &target.kind {
+            ExpressionKind::Identifier(name) => Ok(Place::Variable(name.clone())),
+            ExpressionKind::PropertyAccess { object, property } => {
+                match self.evaluate_expression(object)? {
+                    Value::Object(fields) => Ok(Place::Field(fields, property.clone())),
+                    _ => Err(FenicsError::type_error(
+                        "Can only access properties on objects",
+                    )),
                 }
-                if self.globals.contains_key(name) {
-                    self.globals.insert(name.clone(), new_val.clone());
-                    return Ok(new_val);
+            }
+            ExpressionKind::BracketAccess { object, index } => {
+                let obj = self.evaluate_expression(object)?;
+                match (obj, self.evaluate_expression(index)?) {
+                    (Value::Array(items), Value::Integer(i)) => {
+                        if i < 0 || i as usize >= items.borrow().len() {
+                            return Err(FenicsError::index(format!("Index {} out of bounds", i)));
+                        }
+                        Ok(Place::Element(items, i as usize))
+                    }
+                    (Value::Object(fields), Value::String(key)) => Ok(Place::Field(fields, key)),
+                    _ => Err(FenicsError::type_error(
+                        "Bracket access requires an array with integer index or object with string key",
+                    )),
                 }
-                Err(format!("Variable '{}' not found", name))
             }
-            _ => Err("Increment/decrement only works on variables".to_string()),
+            _ => Err(FenicsError::type_error("Invalid assignment target")),
         }
     }
 }
+
+/// Where an assignment stores its value.
+enum Place {
+    Variable(String),
+    Element(SharedArray, usize),
+    Field(SharedObject, String),
+}
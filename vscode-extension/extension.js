@@ -17,6 +17,16 @@ function activate(context) {
       detail: "Reactive loop",
       doc: "Loop that activates on condition change.",
     },
+    {
+      label: "break",
+      detail: "Loop",
+      doc: "Leave the innermost loop, or the loop named by `@label`.",
+    },
+    {
+      label: "continue",
+      detail: "Loop",
+      doc: "Skip to the next iteration of the innermost loop, or the loop named by `@label`.",
+    },
     {
       label: "return",
       detail: "Function",
@@ -32,6 +42,16 @@ function activate(context) {
       detail: "Error handling",
       doc: "Catch branch for handling errors.",
     },
+    {
+      label: "finally",
+      detail: "Error handling",
+      doc: "Block that always runs after try/catch.",
+    },
+    {
+      label: "throw",
+      detail: "Error handling",
+      doc: "Raise a value as an error, or rethrow a caught one.",
+    },
     {
       label: "then",
       detail: "Ternary",
@@ -88,6 +108,32 @@ function activate(context) {
     },
     { label: "str", detail: "builtin", doc: "Converts a value to String." },
     { label: "int", detail: "builtin", doc: "Converts a value to Int." },
+    { label: "float", detail: "builtin", doc: "Converts a value to Float." },
+    {
+      label: "decimal",
+      detail: "builtin",
+      doc: "Converts a number or String to an exact Decimal.",
+    },
+    {
+      label: "bool",
+      detail: "builtin",
+      doc: "Converts a value to Boolean by its truthiness.",
+    },
+    {
+      label: "parse_int",
+      detail: "builtin",
+      doc: "Parses a String as an Int in the given base (2 to 36, default 10).",
+    },
+    {
+      label: "copy",
+      detail: "builtin",
+      doc: "Returns a new array or object holding the same items.",
+    },
+    {
+      label: "deep_copy",
+      detail: "builtin",
+      doc: "Returns a copy of an array or object and everything nested in it.",
+    },
     {
       label: "sort",
       detail: "builtin",
@@ -129,6 +175,7 @@ function activate(context) {
   const types = [
     { label: "Int", detail: "type", doc: "Integer number type." },
     { label: "Float", detail: "type", doc: "Floating-point number type." },
+    { label: "Decimal", detail: "type", doc: "Exact decimal number type." },
     { label: "String", detail: "type", doc: "String type." },
     { label: "Boolean", detail: "type", doc: "Boolean type." },
     { label: "Bool", detail: "type", doc: "Alias of Boolean." },
@@ -278,7 +325,7 @@ function activate(context) {
         varMatch &&
         !/^fn\b/.test(line) &&
         !/^lib\b/.test(line) &&
-        !/^(else|catch)\s*:/i.test(line)
+        !/^(else|catch|finally)\s*:/i.test(line)
       ) {
         const valuePart = raw.includes(":")
           ? raw.substring(raw.indexOf(":") + 1)
@@ -674,6 +721,12 @@ function activate(context) {
               type: "type(value) -> String",
               str: "str(value) -> String",
               int: "int(value) -> Int",
+              float: "float(value) -> Float",
+              decimal: "decimal(value) -> Decimal",
+              bool: "bool(value) -> Boolean",
+              parse_int: "parse_int(text, radix?) -> Int",
+              copy: "copy(value) -> Any",
+              deep_copy: "deep_copy(value) -> Any",
               sort: "sort(array) -> Array",
               reverse: "reverse(array) -> Array",
               has: "has(collection, keyOrValue) -> Boolean",